@@ -0,0 +1,24 @@
+use std::env;
+use win_event_log::prelude::*;
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            println!("Usage: evtx_dump <file.evtx>");
+            return;
+        }
+    };
+
+    match EvtxReader::open(&path) {
+        Ok(reader) => {
+            for event in reader.events() {
+                match event {
+                    Ok(event) => println!("{}", event),
+                    Err(e) => println!("Error: {}", e),
+                }
+            }
+        }
+        Err(e) => println!("Error: {}", e),
+    }
+}
@@ -1,5 +1,7 @@
+#[cfg(windows)]
 use win_event_log::prelude::*;
 
+#[cfg(windows)]
 fn main() {
     let conditions = vec![
         Condition::filter(EventFilter::level(1, Comparison::Equal)),
@@ -26,3 +28,8 @@ fn main() {
         Err(e) => println!("Error: {}", e),
     }
 }
+
+#[cfg(not(windows))]
+fn main() {
+    println!("This example requires Windows");
+}
@@ -25,9 +25,9 @@ struct MyEvent {
     pub system: Option<System>,
 }
 
-#[cfg(all(feature = "xml", feature = "subscriber"))]
+#[cfg(all(windows, feature = "xml", feature = "subscriber"))]
 fn main() {
-    use std::{thread::sleep, time::Duration};
+    use std::time::Duration;
     use win_event_log::prelude::*;
 
     let conditions = vec![
@@ -46,26 +46,35 @@ fn main() {
         )
         .build();
 
-    match WinEventsSubscriber::get(query) {
+    match Subscription::query(query)
+        .start(StartMode::FutureEvents)
+        .subscribe()
+    {
         Ok(mut events) => {
             println!("Ctrl+C to quit!");
-            while let Some(_event) = events.next() {
-                // catch up to present
-            }
             println!("Waiting for new events...");
             loop {
-                while let Some(event) = events.next() {
-                    let parsed: MyEvent = event.into();
-                    println!("Parsed: {:?}", parsed);
+                match events.next_timeout(Duration::from_secs(1)) {
+                    Ok(Received::Event(event)) => {
+                        let parsed: MyEvent = event.into();
+                        println!("Parsed: {:?}", parsed);
+                    }
+                    Ok(Received::TimedOut) => {}
+                    Ok(Received::Cancelled) => break,
+                    Err(e) => {
+                        println!("Error: {}", e);
+                        if !events.health().is_live() {
+                            break;
+                        }
+                    }
                 }
-                sleep(Duration::from_millis(200));
             }
         }
         Err(e) => println!("Error: {}", e),
     }
 }
 
-#[cfg(not(all(feature = "xml", feature = "subscriber")))]
+#[cfg(not(all(windows, feature = "xml", feature = "subscriber")))]
 fn main() {
-    println!("This example requires serde");
+    println!("This example requires Windows, serde and the subscriber feature");
 }
@@ -2,7 +2,7 @@
 
 #[cfg(feature = "xml")]
 use serde::Deserialize;
-#[cfg(feature = "xml")]
+#[cfg(all(windows, feature = "xml"))]
 use win_event_log::prelude::*;
 
 #[cfg(feature = "xml")]
@@ -27,7 +27,7 @@ struct MyEvent {
     pub system: Option<System>,
 }
 
-#[cfg(feature = "xml")]
+#[cfg(all(windows, feature = "xml"))]
 fn main() {
     let conditions = vec![
         Condition::filter(EventFilter::level(1, Comparison::Equal)),
@@ -56,7 +56,7 @@ fn main() {
     }
 }
 
-#[cfg(not(feature = "xml"))]
+#[cfg(not(all(windows, feature = "xml")))]
 fn main() {
-    println!("This example requires serde");
+    println!("This example requires Windows and serde");
 }
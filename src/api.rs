@@ -1,19 +1,21 @@
 #![allow(non_upper_case_globals)]
 
-#[cfg(feature = "xml")]
-use quick_xml::de::from_str;
-#[cfg(feature = "xml")]
-use serde::de::DeserializeOwned;
-use std::ffi::{CString, OsString};
-use std::fmt;
+use crate::bookmark::Bookmark;
+use crate::error::Error;
+use crate::event::Event;
+use crate::query_list::{query_path_kind, validate_file_path, PathKind};
+use crate::session::EvtSession;
+use crate::variant::Variant;
+use std::ffi::{CString, OsStr, OsString};
 use std::mem::transmute;
 use std::os::windows::prelude::*;
+use std::path::Path;
 use std::ptr::null_mut;
 use winapi::shared::minwindef::{BOOL, DWORD, PDWORD};
 use winapi::um::errhandlingapi::GetLastError;
 use winapi::um::libloaderapi::{GetModuleHandleA, GetProcAddress, LoadLibraryA};
-use winapi::um::winevt::EVT_SUBSCRIBE_CALLBACK;
-use winapi::um::winnt::{HANDLE, LPCWSTR, PVOID};
+use winapi::um::winevt::{EvtSeekRelativeToBookmark, EVT_SUBSCRIBE_CALLBACK};
+use winapi::um::winnt::{HANDLE, LPCWSTR, LPWSTR, PVOID};
 
 bitflags! {
     struct EvtQueryOptions: u32 {
@@ -25,6 +27,10 @@ bitflags! {
     }
 }
 
+pub(crate) const ERROR_INSUFFICIENT_BUFFER: DWORD = 122;
+pub(crate) const ERROR_NO_MORE_ITEMS: DWORD = 259;
+const ERROR_TIMEOUT: DWORD = 1460;
+
 pub type EvtHandle = HANDLE;
 type PevtHandle = *mut HANDLE;
 
@@ -57,6 +63,25 @@ type EvtRenderFn = unsafe extern "system" fn(
     PropertyCount: PDWORD,
 ) -> BOOL;
 
+/// Defines the EvtCreateRenderContext() function signature, for lazy loading
+type EvtCreateRenderContextFn = unsafe extern "system" fn(
+    ValuePathsCount: DWORD,
+    ValuePaths: *const LPCWSTR,
+    Flags: DWORD,
+) -> EvtHandle;
+
+/// Defines the EvtCreateBookmark() function signature, for lazy loading
+type EvtCreateBookmarkFn = unsafe extern "system" fn(BookmarkXml: LPCWSTR) -> EvtHandle;
+
+/// Defines the EvtSeek() function signature, for lazy loading
+type EvtSeekFn = unsafe extern "system" fn(
+    ResultSet: EvtHandle,
+    Position: i64,
+    Bookmark: EvtHandle,
+    Timeout: DWORD,
+    Flags: DWORD,
+) -> BOOL;
+
 /// Defines the EvtClose() function signature, for lazy loading
 type EvtCloseFn = unsafe extern "system" fn(Object: EvtHandle) -> BOOL;
 
@@ -72,12 +97,97 @@ type EvtSubscribeFn = unsafe extern "system" fn(
     Flags: DWORD,
 ) -> EvtHandle;
 
+/// Defines the EvtOpenChannelEnum() function signature, for lazy loading
+type EvtOpenChannelEnumFn =
+    unsafe extern "system" fn(Session: EvtHandle, Flags: DWORD) -> EvtHandle;
+
+/// Defines the EvtNextChannelPath() function signature, for lazy loading
+type EvtNextChannelPathFn = unsafe extern "system" fn(
+    ChannelEnum: EvtHandle,
+    ChannelPathBufferSize: DWORD,
+    ChannelPathBuffer: LPWSTR,
+    ChannelPathBufferUsed: PDWORD,
+) -> BOOL;
+
+/// Defines the EvtOpenChannelConfig() function signature, for lazy loading
+type EvtOpenChannelConfigFn =
+    unsafe extern "system" fn(Session: EvtHandle, ChannelPath: LPCWSTR, Flags: DWORD) -> EvtHandle;
+
+/// Defines the EvtGetChannelConfigProperty() function signature, for lazy loading
+type EvtGetChannelConfigPropertyFn = unsafe extern "system" fn(
+    ChannelConfig: EvtHandle,
+    PropertyId: DWORD,
+    Flags: DWORD,
+    PropertyValueBufferSize: DWORD,
+    PropertyValueBuffer: PVOID,
+    PropertyValueBufferUsed: PDWORD,
+) -> BOOL;
+
+/// Defines the EvtOpenLog() function signature, for lazy loading
+type EvtOpenLogFn =
+    unsafe extern "system" fn(Session: EvtHandle, Path: LPCWSTR, Flags: DWORD) -> EvtHandle;
+
+/// Defines the EvtGetLogInfo() function signature, for lazy loading
+type EvtGetLogInfoFn = unsafe extern "system" fn(
+    Log: EvtHandle,
+    PropertyId: DWORD,
+    PropertyValueBufferSize: DWORD,
+    PropertyValueBuffer: PVOID,
+    PropertyValueBufferUsed: PDWORD,
+) -> BOOL;
+
+/// Defines the EvtExportLog() function signature, for lazy loading
+type EvtExportLogFn = unsafe extern "system" fn(
+    Session: EvtHandle,
+    Path: LPCWSTR,
+    Query: LPCWSTR,
+    TargetFilePath: LPCWSTR,
+    Flags: DWORD,
+) -> BOOL;
+
+/// Defines the EvtArchiveExportedLog() function signature, for lazy loading
+type EvtArchiveExportedLogFn = unsafe extern "system" fn(
+    Session: EvtHandle,
+    LogFilePath: LPCWSTR,
+    Locale: DWORD,
+    Flags: DWORD,
+) -> BOOL;
+
+/// Defines the EvtClearLog() function signature, for lazy loading
+type EvtClearLogFn = unsafe extern "system" fn(
+    Session: EvtHandle,
+    ChannelPath: LPCWSTR,
+    TargetFilePath: LPCWSTR,
+    Flags: DWORD,
+) -> BOOL;
+
+/// Defines the EvtOpenSession() function signature, for lazy loading
+type EvtOpenSessionFn = unsafe extern "system" fn(
+    LoginClass: DWORD,
+    Login: PVOID,
+    Timeout: DWORD,
+    Flags: DWORD,
+) -> EvtHandle;
+
 #[derive(Clone)]
 pub enum EvtApi {
+    ArchiveExportedLog(EvtArchiveExportedLogFn),
+    ClearLog(EvtClearLogFn),
     Close(EvtCloseFn),
+    CreateBookmark(EvtCreateBookmarkFn),
+    CreateRenderContext(EvtCreateRenderContextFn),
+    ExportLog(EvtExportLogFn),
+    GetChannelConfigProperty(EvtGetChannelConfigPropertyFn),
+    GetLogInfo(EvtGetLogInfoFn),
     Next(EvtNextFn),
+    NextChannelPath(EvtNextChannelPathFn),
+    OpenChannelConfig(EvtOpenChannelConfigFn),
+    OpenChannelEnum(EvtOpenChannelEnumFn),
+    OpenLog(EvtOpenLogFn),
+    OpenSession(EvtOpenSessionFn),
     Query(EvtQueryFn),
     Render(EvtRenderFn),
+    Seek(EvtSeekFn),
     Subscribe(EvtSubscribeFn),
 }
 
@@ -102,18 +212,57 @@ fn try_load_from_dll(function: &str) -> Option<EvtApi> {
         Some(h) => match unsafe { GetProcAddress(h, ffi_function.as_ptr()) } {
             i if i.is_null() => None,
             addr => match function {
+                "EvtArchiveExportedLog" => Some(EvtApi::ArchiveExportedLog(unsafe {
+                    transmute::<HANDLE, EvtArchiveExportedLogFn>(addr as _)
+                })),
+                "EvtClearLog" => Some(EvtApi::ClearLog(unsafe {
+                    transmute::<HANDLE, EvtClearLogFn>(addr as _)
+                })),
                 "EvtClose" => Some(EvtApi::Close(unsafe {
                     transmute::<HANDLE, EvtCloseFn>(addr as _)
                 })),
+                "EvtCreateBookmark" => Some(EvtApi::CreateBookmark(unsafe {
+                    transmute::<HANDLE, EvtCreateBookmarkFn>(addr as _)
+                })),
+                "EvtCreateRenderContext" => Some(EvtApi::CreateRenderContext(unsafe {
+                    transmute::<HANDLE, EvtCreateRenderContextFn>(addr as _)
+                })),
+                "EvtExportLog" => Some(EvtApi::ExportLog(unsafe {
+                    transmute::<HANDLE, EvtExportLogFn>(addr as _)
+                })),
+                "EvtGetChannelConfigProperty" => Some(EvtApi::GetChannelConfigProperty(unsafe {
+                    transmute::<HANDLE, EvtGetChannelConfigPropertyFn>(addr as _)
+                })),
+                "EvtGetLogInfo" => Some(EvtApi::GetLogInfo(unsafe {
+                    transmute::<HANDLE, EvtGetLogInfoFn>(addr as _)
+                })),
                 "EvtNext" => Some(EvtApi::Next(unsafe {
                     transmute::<HANDLE, EvtNextFn>(addr as _)
                 })),
+                "EvtNextChannelPath" => Some(EvtApi::NextChannelPath(unsafe {
+                    transmute::<HANDLE, EvtNextChannelPathFn>(addr as _)
+                })),
+                "EvtOpenChannelConfig" => Some(EvtApi::OpenChannelConfig(unsafe {
+                    transmute::<HANDLE, EvtOpenChannelConfigFn>(addr as _)
+                })),
+                "EvtOpenChannelEnum" => Some(EvtApi::OpenChannelEnum(unsafe {
+                    transmute::<HANDLE, EvtOpenChannelEnumFn>(addr as _)
+                })),
+                "EvtOpenLog" => Some(EvtApi::OpenLog(unsafe {
+                    transmute::<HANDLE, EvtOpenLogFn>(addr as _)
+                })),
+                "EvtOpenSession" => Some(EvtApi::OpenSession(unsafe {
+                    transmute::<HANDLE, EvtOpenSessionFn>(addr as _)
+                })),
                 "EvtQuery" => Some(EvtApi::Query(unsafe {
                     transmute::<HANDLE, EvtQueryFn>(addr as _)
                 })),
                 "EvtRender" => Some(EvtApi::Render(unsafe {
                     transmute::<HANDLE, EvtRenderFn>(addr as _)
                 })),
+                "EvtSeek" => Some(EvtApi::Seek(unsafe {
+                    transmute::<HANDLE, EvtSeekFn>(addr as _)
+                })),
                 "EvtSubscribe" => Some(EvtApi::Subscribe(unsafe {
                     transmute::<HANDLE, EvtSubscribeFn>(addr as _)
                 })),
@@ -125,15 +274,37 @@ fn try_load_from_dll(function: &str) -> Option<EvtApi> {
 }
 
 lazy_static! {
+    pub static ref EvtArchiveExportedLog: Option<EvtApi> =
+        try_load_from_dll("EvtArchiveExportedLog");
+    pub static ref EvtClearLog: Option<EvtApi> = try_load_from_dll("EvtClearLog");
     pub static ref EvtClose: Option<EvtApi> = try_load_from_dll("EvtClose");
+    pub static ref EvtCreateBookmark: Option<EvtApi> = try_load_from_dll("EvtCreateBookmark");
+    pub static ref EvtCreateRenderContext: Option<EvtApi> =
+        try_load_from_dll("EvtCreateRenderContext");
+    pub static ref EvtExportLog: Option<EvtApi> = try_load_from_dll("EvtExportLog");
+    pub static ref EvtGetChannelConfigProperty: Option<EvtApi> =
+        try_load_from_dll("EvtGetChannelConfigProperty");
+    pub static ref EvtGetLogInfo: Option<EvtApi> = try_load_from_dll("EvtGetLogInfo");
     pub static ref EvtNext: Option<EvtApi> = try_load_from_dll("EvtNext");
+    pub static ref EvtNextChannelPath: Option<EvtApi> = try_load_from_dll("EvtNextChannelPath");
+    pub static ref EvtOpenChannelConfig: Option<EvtApi> = try_load_from_dll("EvtOpenChannelConfig");
+    pub static ref EvtOpenChannelEnum: Option<EvtApi> = try_load_from_dll("EvtOpenChannelEnum");
+    pub static ref EvtOpenLog: Option<EvtApi> = try_load_from_dll("EvtOpenLog");
+    pub static ref EvtOpenSession: Option<EvtApi> = try_load_from_dll("EvtOpenSession");
     pub static ref EvtQuery: Option<EvtApi> = try_load_from_dll("EvtQuery");
     pub static ref EvtRender: Option<EvtApi> = try_load_from_dll("EvtRender");
+    pub static ref EvtSeek: Option<EvtApi> = try_load_from_dll("EvtSeek");
     pub static ref EvtSubscribe: Option<EvtApi> = try_load_from_dll("EvtSubscribe");
 }
 
 pub struct EvtHandleWrapper(pub EvtHandle);
 
+// Event log handles belong to the process rather than the thread that opened
+// them, so they can move between threads. They are not `Sync`: reading a query
+// or subscription moves its position, and the API does not promise that calls on
+// one handle from several threads at once are safe.
+unsafe impl Send for EvtHandleWrapper {}
+
 impl Drop for EvtHandleWrapper {
     fn drop(&mut self) {
         if let Some(EvtApi::Close(ref close)) = *EvtClose {
@@ -144,9 +315,31 @@ impl Drop for EvtHandleWrapper {
     }
 }
 
+/// Creates a bookmark handle from `bookmark`, for resuming a query or subscription
+pub(crate) fn bookmark_handle(bookmark: &Bookmark) -> Result<EvtHandleWrapper, String> {
+    let xml = OsString::from(bookmark.to_xml())
+        .encode_wide()
+        .chain(Some(0))
+        .collect::<Vec<u16>>();
+    match *EvtCreateBookmark {
+        Some(EvtApi::CreateBookmark(ref create)) => match unsafe { create(xml.as_ptr()) } {
+            i if i.is_null() => Err(format!(
+                "There was an error creating the bookmark: {}",
+                unsafe { GetLastError() }
+            )),
+            i => Ok(EvtHandleWrapper(i)),
+        },
+        _ => Err("EvtCreateBookmark API is not available".to_owned()),
+    }
+}
+
 /// Entry point for querying the event log
+///
+/// `Send`, so a query can be handed to a worker thread, but not `Sync`.
 pub struct WinEvents {
     handle: Option<EvtHandleWrapper>,
+    /// Kept open for as long as the query, when it runs on a remote session
+    _session: Option<EvtSession>,
 }
 
 impl WinEvents {
@@ -154,6 +347,9 @@ impl WinEvents {
     /// this platform or there is an error performing the query, the `Error` will
     /// describe the problem.
     ///
+    /// Queries whose paths use `file://` are run against those log files instead of
+    /// live channels. A single query cannot mix the two.
+    ///
     /// # Examples
     ///
     /// ```
@@ -170,24 +366,118 @@ impl WinEvents {
     /// ```
     ///
     pub fn get<T: Into<String> + Clone>(query: T) -> Result<WinEvents, String> {
+        WinEvents::get_in(None, query.into())
+    }
+
+    /// Runs `get` on `session`, or on the local machine
+    pub(crate) fn get_in(session: Option<&EvtSession>, query: String) -> Result<WinEvents, String> {
+        let flags = match query_path_kind(&query)? {
+            Some(PathKind::File) => EvtQueryOptions::EvtQueryFilePath,
+            _ => EvtQueryOptions::EvtQueryChannelPath,
+        };
+        WinEvents::run_query(session, None, query, flags)
+    }
+
+    /// Queries the event log like `get`, starting after the events `bookmark` has
+    /// already passed. An empty bookmark starts at the beginning.
+    pub fn resume<T: Into<String> + Clone>(
+        query: T,
+        bookmark: &Bookmark,
+    ) -> Result<WinEvents, String> {
+        WinEvents::resume_in(None, query.into(), bookmark)
+    }
+
+    /// Runs `resume` on `session`, or on the local machine
+    pub(crate) fn resume_in(
+        session: Option<&EvtSession>,
+        query: String,
+        bookmark: &Bookmark,
+    ) -> Result<WinEvents, String> {
+        let events = WinEvents::get_in(session, query)?;
+        if bookmark.is_empty() {
+            return Ok(events);
+        }
+        let bookmark = bookmark_handle(bookmark)?;
+        match (&*EvtSeek, &events.handle) {
+            (Some(EvtApi::Seek(ref seek)), Some(ref handle)) => {
+                if unsafe { seek(handle.0, 1, bookmark.0, 0, EvtSeekRelativeToBookmark) } == 0 {
+                    return Err(format!(
+                        "There was an error seeking to the bookmark: {}",
+                        unsafe { GetLastError() }
+                    ));
+                }
+                Ok(events)
+            }
+            _ => Err("EvtSeek API is not available".to_owned()),
+        }
+    }
+
+    /// Queries an exported or archived log file (`.evtx`, `.evt` or `.etl`). The
+    /// `query` may be an XPath expression such as `"*"` or a `QueryList` whose
+    /// paths are all `file://` paths.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let events = WinEvents::from_file(r"C:\logs\Security.evtx", "*");
+    /// ```
+    ///
+    pub fn from_file<P: AsRef<Path>, T: Into<String> + Clone>(
+        path: P,
+        query: T,
+    ) -> Result<WinEvents, String> {
+        let path = path.as_ref();
+        validate_file_path(path)?;
+        if !path.is_file() {
+            return Err(format!("Log file does not exist: {}", path.display()));
+        }
+        let query = query.into();
+        if let Some(PathKind::Channel) = query_path_kind(&query)? {
+            return Err("A query against a log file cannot select from channel paths".to_owned());
+        }
+        let path = std::path::absolute(path)
+            .map_err(|err| format!("Could not resolve {}: {err}", path.display()))?;
+        WinEvents::run_query(
+            None,
+            Some(path.as_os_str()),
+            query,
+            EvtQueryOptions::EvtQueryFilePath,
+        )
+    }
+
+    fn run_query(
+        session: Option<&EvtSession>,
+        path: Option<&OsStr>,
+        query: String,
+        flags: EvtQueryOptions,
+    ) -> Result<WinEvents, String> {
         if EvtQuery.is_none() || EvtNext.is_none() || EvtRender.is_none() {
             Err("EvtQuery API is not available".to_owned())
         } else {
             // Small hack to prevent occasional parsing errors from the Evt* API
             let ffi_query = {
-                let mut tmp = OsString::from(query.into())
-                    .encode_wide()
-                    .collect::<Vec<u16>>();
+                let mut tmp = OsString::from(query).encode_wide().collect::<Vec<u16>>();
                 tmp.append(&mut OsString::from("\0").encode_wide().collect::<Vec<u16>>());
                 tmp
             };
+            let ffi_path = path.map(|path| {
+                path.encode_wide()
+                    .chain(std::iter::once(0))
+                    .collect::<Vec<u16>>()
+            });
             if let Some(EvtApi::Query(ref evt_query)) = *EvtQuery {
+                let session_handle = session.map(EvtSession::lock);
                 match unsafe {
                     evt_query(
-                        null_mut(),
-                        null_mut(),
+                        session_handle
+                            .as_ref()
+                            .map_or(null_mut(), |handle| handle.0),
+                        ffi_path
+                            .as_ref()
+                            .map(|path| path.as_ptr())
+                            .unwrap_or(null_mut()),
                         ffi_query.as_ptr(),
-                        EvtQueryOptions::EvtQueryChannelPath.bits(),
+                        flags.bits(),
                     )
                 } {
                     i if i.is_null() => Err(format!(
@@ -196,6 +486,7 @@ impl WinEvents {
                     )),
                     i => Ok(WinEvents {
                         handle: Some(EvtHandleWrapper(i)),
+                        _session: session.cloned(),
                     }),
                 }
             } else {
@@ -205,39 +496,56 @@ impl WinEvents {
     }
 
     /// Gets the next item from the event log. If there are no more evens `None` is returned.
-    fn next_evt(&mut self) -> Option<EvtHandleWrapper> {
-        if let Some(ref handle) = self.handle {
-            let mut next_handle: Vec<EvtHandle> = vec![null_mut() as _];
-            match *EvtNext {
-                Some(EvtApi::Next(ref next)) => {
-                    let mut number_returned: DWORD = 0;
-                    if unsafe {
-                        next(
-                            handle.0,
-                            1,
-                            next_handle.as_mut_ptr() as _,
-                            0,
-                            0,
-                            &mut number_returned,
-                        )
-                    } > 0
-                    {
-                        Some(EvtHandleWrapper(next_handle[0]))
-                    } else {
-                        None
-                    }
-                }
-                _ => None,
-            }
-        } else {
-            None
+    pub(crate) fn next_evt(&mut self) -> Option<EvtHandleWrapper> {
+        self.try_next_evt().ok().flatten()
+    }
+
+    /// Gets the next item from the event log, telling running out of events apart
+    /// from failing to read them
+    pub(crate) fn try_next_evt(&mut self) -> Result<Option<EvtHandleWrapper>, Error> {
+        let handle = match self.handle {
+            Some(ref handle) => handle,
+            None => return Ok(None),
+        };
+        let next = match *EvtNext {
+            Some(EvtApi::Next(ref next)) => next,
+            _ => return Err(Error::Other("EvtNext API is not available".to_owned())),
+        };
+        let mut next_handle: Vec<EvtHandle> = vec![null_mut() as _];
+        let mut number_returned: DWORD = 0;
+        if unsafe {
+            next(
+                handle.0,
+                1,
+                next_handle.as_mut_ptr() as _,
+                0,
+                0,
+                &mut number_returned,
+            )
+        } > 0
+        {
+            return Ok(Some(EvtHandleWrapper(next_handle[0])));
+        }
+        match unsafe { GetLastError() } {
+            ERROR_NO_MORE_ITEMS | ERROR_TIMEOUT => Ok(None),
+            code => Err(Error::Windows {
+                code,
+                context: "There was an error reading events".to_owned(),
+            }),
         }
     }
 
     /// Create a `WinEvents` from existing event handle
     pub fn new(handle: EvtHandle) -> WinEvents {
+        WinEvents::with_session(handle, None)
+    }
+
+    /// Creates a `WinEvents` from a handle opened on `session`, keeping the
+    /// session open until the handle is closed
+    pub(crate) fn with_session(handle: EvtHandle, session: Option<EvtSession>) -> WinEvents {
         WinEvents {
             handle: Some(EvtHandleWrapper(handle)),
+            _session: session,
         }
     }
 }
@@ -251,34 +559,6 @@ impl IntoIterator for WinEvents {
     }
 }
 
-pub struct Event(String);
-
-impl fmt::Display for Event {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.0)
-    }
-}
-
-#[cfg(feature = "xml")]
-impl Event {
-    pub fn into<T>(self) -> T
-    where
-        T: DeserializeOwned + Default,
-    {
-        match from_str::<T>(&self.0) {
-            Ok(o) => o,
-            _ => Default::default(),
-        }
-    }
-
-    pub fn try_into<T>(self) -> Result<T, String>
-    where
-        T: DeserializeOwned + Default,
-    {
-        from_str::<T>(&self.0).map_err(|err| format!("Deserialization error: {err}"))
-    }
-}
-
 /// An iterator abstraction over `WinEvents`
 ///
 /// # Example
@@ -293,7 +573,7 @@ impl Event {
 /// }
 /// ```
 pub struct WinEventsIntoIterator {
-    win_events: WinEvents,
+    pub(crate) win_events: WinEvents,
 }
 
 impl Iterator for WinEventsIntoIterator {
@@ -301,55 +581,113 @@ impl Iterator for WinEventsIntoIterator {
 
     /// Returns the next event. If there are no more events, `None` is returned.
     fn next(&mut self) -> Option<Event> {
-        match self.win_events.next_evt() {
-            Some(handle) => match *EvtRender {
-                Some(EvtApi::Render(ref render)) => {
-                    let mut buffer_used: DWORD = 0;
-                    let mut property_count: DWORD = 0;
-
-                    if unsafe {
-                        render(
-                            null_mut(),
-                            handle.0 as _,
-                            1,
-                            0,
-                            null_mut(),
-                            &mut buffer_used,
-                            &mut property_count,
-                        ) == 0
-                            && GetLastError() == 122
-                    } {
-                        let mut buf: Vec<u16> = vec![0; buffer_used as usize];
-                        match unsafe {
-                            render(
-                                null_mut(),
-                                handle.0 as _,
-                                1,
-                                buf.len() as _,
-                                buf.as_mut_ptr() as _,
-                                &mut buffer_used,
-                                &mut property_count,
-                            )
-                        } {
-                            0 => None,
-                            _ => {
-                                let s = OsString::from_wide(&buf[..]).to_string_lossy().to_string();
-                                Some(Event(s))
-                            }
-                        }
-                    } else {
-                        None
-                    }
-                }
-                _ => None,
-            },
-            _ => None,
+        let handle = self.win_events.next_evt()?;
+        render_event(handle.0).ok()
+    }
+}
+
+/// Renders the XML of the event at `handle`
+pub(crate) fn render_event(handle: EvtHandle) -> Result<Event, Error> {
+    let render = match *EvtRender {
+        Some(EvtApi::Render(ref render)) => render,
+        _ => return Err(Error::Other("EvtRender API is not available".to_owned())),
+    };
+    let mut buffer_used: DWORD = 0;
+    let mut property_count: DWORD = 0;
+    if unsafe {
+        render(
+            null_mut(),
+            handle as _,
+            1,
+            0,
+            null_mut(),
+            &mut buffer_used,
+            &mut property_count,
+        ) != 0
+            || GetLastError() != ERROR_INSUFFICIENT_BUFFER
+    } {
+        return Err(Error::last_os("There was an error rendering the event"));
+    }
+    let mut buf: Vec<u16> = vec![0; buffer_used as usize];
+    match unsafe {
+        render(
+            null_mut(),
+            handle as _,
+            1,
+            buf.len() as _,
+            buf.as_mut_ptr() as _,
+            &mut buffer_used,
+            &mut property_count,
+        )
+    } {
+        0 => Err(Error::last_os("There was an error rendering the event")),
+        _ => {
+            let s = OsString::from_wide(&buf[..]).to_string_lossy().to_string();
+            Ok(Event(s))
         }
     }
 }
 
+/// Reads a property into an `EVT_VARIANT` with `get`, which is called as the
+/// `Evt*Property` functions are: with an empty buffer to learn the size needed,
+/// then with a buffer of that size
+pub(crate) fn get_variant(
+    context: &str,
+    mut get: impl FnMut(DWORD, PVOID, PDWORD) -> BOOL,
+) -> Result<Variant, Error> {
+    let mut buffer_used: DWORD = 0;
+    if get(0, null_mut(), &mut buffer_used) == 0
+        && unsafe { GetLastError() } != ERROR_INSUFFICIENT_BUFFER
+    {
+        return Err(Error::last_os(context));
+    }
+    // u64s keep the variant aligned
+    let mut buffer = vec![0u64; (buffer_used as usize).div_ceil(8)];
+    if get(
+        (buffer.len() * 8) as DWORD,
+        buffer.as_mut_ptr() as PVOID,
+        &mut buffer_used,
+    ) == 0
+    {
+        return Err(Error::last_os(context));
+    }
+    let bytes = buffer
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .take(buffer_used as usize)
+        .collect::<Vec<u8>>();
+    Variant::decode(&bytes, buffer.as_ptr() as u64).map_err(Error::Other)
+}
+
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
+    /// Compiles only for `Send` types
+    pub(crate) fn assert_send<T: Send>() {}
+
+    /// `<T as NotSync<_>>::check()` compiles only when `T` is not `Sync`, since
+    /// otherwise both impls apply and `_` is ambiguous
+    pub(crate) trait NotSync<A> {
+        fn check() {}
+    }
+
+    impl<T: ?Sized> NotSync<()> for T {}
+
+    impl<T: ?Sized + Sync> NotSync<u8> for T {}
+
+    #[test]
+    fn thread_safety() {
+        use super::{EvtHandleWrapper, WinEvents, WinEventsIntoIterator};
+        use crate::render::{RenderContext, WinEventValues};
+        assert_send::<EvtHandleWrapper>();
+        assert_send::<WinEvents>();
+        assert_send::<WinEventsIntoIterator>();
+        assert_send::<RenderContext>();
+        assert_send::<WinEventValues>();
+        <EvtHandleWrapper as NotSync<_>>::check();
+        <WinEvents as NotSync<_>>::check();
+        <WinEventsIntoIterator as NotSync<_>>::check();
+    }
+
     #[test]
     fn test_params_query_list() {
         use crate::{QueryList, WinEvents};
@@ -382,4 +720,11 @@ and
             assert!(last_error == 0);
         }
     }
+
+    #[test]
+    fn test_from_file_missing() {
+        use crate::WinEvents;
+        assert!(WinEvents::from_file(r"C:\does\not\exist.evtx", "*").is_err());
+        assert!(WinEvents::from_file(r"C:\Windows\win.ini", "*").is_err());
+    }
 }
@@ -0,0 +1,205 @@
+//! Bookmarks, recording how far a reader got in each channel so a later query or
+//! subscription can carry on from there
+//!
+//! The XML is the format `EvtRender` produces for a bookmark handle, so bookmarks
+//! saved by other tools can be loaded, and ours can be passed to `EvtCreateBookmark`.
+
+use crate::event::Event;
+use crate::evtx::value::escape_xml;
+use crate::evtx::{XmlElement, XmlNode};
+use std::fmt;
+
+/// The position in one channel
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BookmarkEntry {
+    pub channel: String,
+    /// `EventRecordID` of the last event handled
+    pub record_id: u64,
+}
+
+/// A position in one or more channels
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Bookmark {
+    entries: Vec<BookmarkEntry>,
+    /// Index of the entry updated last, written as `IsCurrent`
+    current: Option<usize>,
+}
+
+impl Bookmark {
+    /// An empty bookmark, positioned before the start of every channel
+    pub fn new() -> Bookmark {
+        Default::default()
+    }
+
+    /// A bookmark positioned at `event`
+    pub fn from_event(event: &Event) -> Result<Bookmark, String> {
+        let mut bookmark = Bookmark::new();
+        bookmark.update(event)?;
+        Ok(bookmark)
+    }
+
+    /// Parses the XML of a rendered bookmark
+    pub fn from_xml(xml: &str) -> Result<Bookmark, String> {
+        let nodes = XmlNode::parse(xml)?;
+        let list = match nodes.iter().find_map(XmlNode::as_element) {
+            Some(list) if list.name == "BookmarkList" => list,
+            _ => return Err("Bookmark XML has no BookmarkList element".to_owned()),
+        };
+        let mut bookmark = Bookmark::new();
+        for entry in list.elements().filter(|element| element.name == "Bookmark") {
+            let channel = required(entry, "Channel")?;
+            let record_id = required(entry, "RecordId")?
+                .parse()
+                .map_err(|_| format!("Bookmark for {channel} has an invalid RecordId"))?;
+            if entry
+                .attribute_value("IsCurrent")
+                .is_some_and(|current| current.eq_ignore_ascii_case("true"))
+            {
+                bookmark.current = Some(bookmark.entries.len());
+            }
+            bookmark.entries.push(BookmarkEntry { channel, record_id });
+        }
+        Ok(bookmark)
+    }
+
+    /// Moves the bookmark to `event`, within the event's channel
+    pub fn update(&mut self, event: &Event) -> Result<(), String> {
+        let nodes = XmlNode::parse(&event.0)?;
+        let system = nodes
+            .iter()
+            .find_map(XmlNode::as_element)
+            .and_then(|root| root.child("System"))
+            .ok_or_else(|| "Event has no System element".to_owned())?;
+        let channel = match system.child("Channel") {
+            Some(channel) => channel.text(),
+            None => return Err("Event has no Channel".to_owned()),
+        };
+        let record_id = system
+            .child("EventRecordID")
+            .and_then(|id| id.text().trim().parse().ok())
+            .ok_or_else(|| "Event has no valid EventRecordID".to_owned())?;
+        self.set(&channel, record_id);
+        Ok(())
+    }
+
+    /// Moves the bookmark to `record_id` in `channel`
+    pub fn set(&mut self, channel: &str, record_id: u64) {
+        let index = match self
+            .entries
+            .iter()
+            .position(|entry| entry.channel.eq_ignore_ascii_case(channel))
+        {
+            Some(index) => index,
+            None => {
+                self.entries.push(BookmarkEntry {
+                    channel: channel.to_owned(),
+                    record_id,
+                });
+                self.entries.len() - 1
+            }
+        };
+        self.entries[index].record_id = record_id;
+        self.current = Some(index);
+    }
+
+    pub fn entries(&self) -> &[BookmarkEntry] {
+        &self.entries
+    }
+
+    /// The last record handled in `channel`
+    pub fn record_id(&self, channel: &str) -> Option<u64> {
+        self.entries
+            .iter()
+            .find(|entry| entry.channel.eq_ignore_ascii_case(channel))
+            .map(|entry| entry.record_id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Renders the bookmark as `EvtRender` does
+    pub fn to_xml(&self) -> String {
+        let mut out = String::from("<BookmarkList>\r\n");
+        for (index, entry) in self.entries.iter().enumerate() {
+            out.push_str("  <Bookmark Channel='");
+            escape_xml(&entry.channel, &mut out);
+            out.push_str(&format!("' RecordId='{}'", entry.record_id));
+            if self.current == Some(index) {
+                out.push_str(" IsCurrent='true'");
+            }
+            out.push_str("/>\r\n");
+        }
+        out.push_str("</BookmarkList>");
+        out
+    }
+}
+
+fn required(entry: &XmlElement, name: &str) -> Result<String, String> {
+    entry
+        .attribute_value(name)
+        .ok_or_else(|| format!("Bookmark is missing the {name} attribute"))
+}
+
+impl fmt::Display for Bookmark {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_xml())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Bookmark;
+    use crate::event::Event;
+
+    fn event(channel: &str, record_id: u64) -> Event {
+        Event(format!(
+            "<Event xmlns='http://schemas.microsoft.com/win/2004/08/events/event'><System>\
+             <EventID>4624</EventID><EventRecordID>{record_id}</EventRecordID>\
+             <Channel>{channel}</Channel><Computer>dc01</Computer></System></Event>"
+        ))
+    }
+
+    #[test]
+    fn update_and_render() {
+        let mut bookmark = Bookmark::from_event(&event("Security", 41)).unwrap();
+        bookmark.update(&event("System", 7)).unwrap();
+        bookmark.update(&event("Security", 42)).unwrap();
+        assert_eq!(bookmark.record_id("security"), Some(42));
+        assert_eq!(bookmark.record_id("Application"), None);
+        assert_eq!(
+            bookmark.to_xml(),
+            "<BookmarkList>\r\n  \
+             <Bookmark Channel='Security' RecordId='42' IsCurrent='true'/>\r\n  \
+             <Bookmark Channel='System' RecordId='7'/>\r\n\
+             </BookmarkList>"
+        );
+        assert_eq!(Bookmark::from_xml(&bookmark.to_xml()).unwrap(), bookmark);
+        assert!(Bookmark::new()
+            .update(&Event("<Event><System/></Event>".to_owned()))
+            .is_err());
+    }
+
+    #[test]
+    fn parse_rendered_bookmark() {
+        let bookmark = Bookmark::from_xml(
+            "<BookmarkList Direction='backward'>\r\n  \
+             <Bookmark Channel='Microsoft-Windows-Sysmon/Operational' RecordId='1234567' \
+             IsCurrent='true'/>\r\n</BookmarkList>",
+        )
+        .unwrap();
+        assert_eq!(
+            bookmark.record_id("Microsoft-Windows-Sysmon/Operational"),
+            Some(1234567)
+        );
+        assert!(Bookmark::from_xml("<BookmarkList/>").unwrap().is_empty());
+        assert!(Bookmark::from_xml("<Bookmarks/>").is_err());
+        assert!(
+            Bookmark::from_xml("<BookmarkList><Bookmark Channel='A'/></BookmarkList>").is_err()
+        );
+        assert!(Bookmark::from_xml(
+            "<BookmarkList><Bookmark Channel='A' RecordId='x'/></BookmarkList>"
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,294 @@
+use super::{
+    ChannelConfig, ChannelProperties, ChannelProperty, LogInfo, LogProperties, LogProperty,
+};
+use crate::api::{
+    get_variant, EvtApi, EvtGetChannelConfigProperty, EvtGetLogInfo, EvtHandleWrapper,
+    EvtNextChannelPath, EvtOpenChannelConfig, EvtOpenChannelEnum, EvtOpenLog,
+    ERROR_INSUFFICIENT_BUFFER, ERROR_NO_MORE_ITEMS,
+};
+use crate::error::Error;
+use crate::query_list::validate_file_path;
+use crate::session::EvtSession;
+use crate::variant::Variant;
+use std::ffi::OsStr;
+use std::os::windows::prelude::*;
+use std::path::Path;
+use std::ptr::null_mut;
+use winapi::shared::minwindef::DWORD;
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::winevt::{EvtOpenChannelPath, EvtOpenFilePath};
+
+/// The path of every channel registered on a machine, such as `Security` or
+/// `Microsoft-Windows-Sysmon/Operational`
+pub struct ChannelPaths {
+    handle: EvtHandleWrapper,
+    done: bool,
+    /// Kept open for as long as the enumeration
+    _session: Option<EvtSession>,
+}
+
+impl ChannelPaths {
+    /// Lists the channels on the local machine
+    pub fn new() -> Result<ChannelPaths, Error> {
+        ChannelPaths::open_in(None)
+    }
+
+    fn open_in(session: Option<&EvtSession>) -> Result<ChannelPaths, Error> {
+        let open = match *EvtOpenChannelEnum {
+            Some(EvtApi::OpenChannelEnum(ref open)) => open,
+            _ => {
+                return Err(Error::Other(
+                    "EvtOpenChannelEnum API is not available".to_owned(),
+                ))
+            }
+        };
+        let session_handle = session.map(EvtSession::lock);
+        match unsafe {
+            open(
+                session_handle
+                    .as_ref()
+                    .map_or(null_mut(), |handle| handle.0),
+                0,
+            )
+        } {
+            i if i.is_null() => Err(Error::last_os("There was an error listing channels")),
+            i => Ok(ChannelPaths {
+                handle: EvtHandleWrapper(i),
+                done: false,
+                _session: session.cloned(),
+            }),
+        }
+    }
+}
+
+impl Iterator for ChannelPaths {
+    type Item = Result<String, Error>;
+
+    /// The next channel path. Listing stops after the first error.
+    fn next(&mut self) -> Option<Result<String, Error>> {
+        if self.done {
+            return None;
+        }
+        let next = match *EvtNextChannelPath {
+            Some(EvtApi::NextChannelPath(ref next)) => next,
+            _ => {
+                self.done = true;
+                return Some(Err(Error::Other(
+                    "EvtNextChannelPath API is not available".to_owned(),
+                )));
+            }
+        };
+        let mut buffer: Vec<u16> = vec![0; 256];
+        let mut buffer_used: DWORD = 0;
+        loop {
+            if unsafe {
+                next(
+                    self.handle.0,
+                    buffer.len() as DWORD,
+                    buffer.as_mut_ptr(),
+                    &mut buffer_used,
+                )
+            } != 0
+            {
+                let path = &buffer[..(buffer_used as usize).min(buffer.len())];
+                let len = path.iter().position(|c| *c == 0).unwrap_or(path.len());
+                return Some(Ok(String::from_utf16_lossy(&path[..len])));
+            }
+            match unsafe { GetLastError() } {
+                ERROR_INSUFFICIENT_BUFFER if buffer_used as usize > buffer.len() => {
+                    buffer.resize(buffer_used as usize, 0)
+                }
+                ERROR_NO_MORE_ITEMS => {
+                    self.done = true;
+                    return None;
+                }
+                code => {
+                    self.done = true;
+                    return Some(Err(Error::Windows {
+                        code,
+                        context: "There was an error listing channels".to_owned(),
+                    }));
+                }
+            }
+        }
+    }
+}
+
+/// A channel's configuration as held by the event log service, from which
+/// `ChannelConfig` reads its properties
+pub struct EvtChannelConfig {
+    handle: EvtHandleWrapper,
+    /// Kept open for as long as the configuration
+    _session: Option<EvtSession>,
+}
+
+impl EvtChannelConfig {
+    /// Opens the configuration of the local channel `channel`
+    pub fn open(channel: &str) -> Result<EvtChannelConfig, Error> {
+        EvtChannelConfig::open_in(None, channel)
+    }
+
+    fn open_in(session: Option<&EvtSession>, channel: &str) -> Result<EvtChannelConfig, Error> {
+        let open = match *EvtOpenChannelConfig {
+            Some(EvtApi::OpenChannelConfig(ref open)) => open,
+            _ => {
+                return Err(Error::Other(
+                    "EvtOpenChannelConfig API is not available".to_owned(),
+                ))
+            }
+        };
+        let ffi_channel = OsStr::new(channel)
+            .encode_wide()
+            .chain(Some(0))
+            .collect::<Vec<u16>>();
+        let session_handle = session.map(EvtSession::lock);
+        match unsafe {
+            open(
+                session_handle
+                    .as_ref()
+                    .map_or(null_mut(), |handle| handle.0),
+                ffi_channel.as_ptr(),
+                0,
+            )
+        } {
+            i if i.is_null() => Err(Error::last_os(
+                "There was an error opening the channel configuration",
+            )),
+            i => Ok(EvtChannelConfig {
+                handle: EvtHandleWrapper(i),
+                _session: session.cloned(),
+            }),
+        }
+    }
+}
+
+impl ChannelProperties for EvtChannelConfig {
+    fn property(&self, property: ChannelProperty) -> Result<Variant, Error> {
+        let get = match *EvtGetChannelConfigProperty {
+            Some(EvtApi::GetChannelConfigProperty(ref get)) => get,
+            _ => {
+                return Err(Error::Other(
+                    "EvtGetChannelConfigProperty API is not available".to_owned(),
+                ))
+            }
+        };
+        get_variant(
+            "There was an error reading the channel property",
+            |size, buffer, used| unsafe {
+                get(self.handle.0, property as DWORD, 0, size, buffer, used)
+            },
+        )
+    }
+}
+
+impl ChannelConfig {
+    /// The configuration of the local channel `channel`
+    pub fn get(channel: &str) -> Result<ChannelConfig, Error> {
+        ChannelConfig::from_properties(&EvtChannelConfig::open(channel)?)
+    }
+}
+
+/// An open channel or log file, from which `LogInfo` reads its statistics
+pub struct EvtLog {
+    handle: EvtHandleWrapper,
+    /// Kept open for as long as the log
+    _session: Option<EvtSession>,
+}
+
+impl EvtLog {
+    /// Opens the local channel `channel`
+    pub fn channel(channel: &str) -> Result<EvtLog, Error> {
+        EvtLog::open_in(None, OsStr::new(channel), EvtOpenChannelPath)
+    }
+
+    /// Opens an exported or archived log file
+    pub fn file<P: AsRef<Path>>(path: P) -> Result<EvtLog, Error> {
+        let path = path.as_ref();
+        validate_file_path(path)?;
+        if !path.is_file() {
+            return Err(Error::Other(format!(
+                "Log file does not exist: {}",
+                path.display()
+            )));
+        }
+        let path = std::path::absolute(path)
+            .map_err(|err| Error::Other(format!("Could not resolve {}: {err}", path.display())))?;
+        EvtLog::open_in(None, path.as_os_str(), EvtOpenFilePath)
+    }
+
+    fn open_in(session: Option<&EvtSession>, path: &OsStr, flags: DWORD) -> Result<EvtLog, Error> {
+        let open = match *EvtOpenLog {
+            Some(EvtApi::OpenLog(ref open)) => open,
+            _ => return Err(Error::Other("EvtOpenLog API is not available".to_owned())),
+        };
+        let ffi_path = path.encode_wide().chain(Some(0)).collect::<Vec<u16>>();
+        let session_handle = session.map(EvtSession::lock);
+        match unsafe {
+            open(
+                session_handle
+                    .as_ref()
+                    .map_or(null_mut(), |handle| handle.0),
+                ffi_path.as_ptr(),
+                flags,
+            )
+        } {
+            i if i.is_null() => Err(Error::last_os("There was an error opening the log")),
+            i => Ok(EvtLog {
+                handle: EvtHandleWrapper(i),
+                _session: session.cloned(),
+            }),
+        }
+    }
+}
+
+impl LogProperties for EvtLog {
+    fn property(&self, property: LogProperty) -> Result<Variant, Error> {
+        let get = match *EvtGetLogInfo {
+            Some(EvtApi::GetLogInfo(ref get)) => get,
+            _ => {
+                return Err(Error::Other(
+                    "EvtGetLogInfo API is not available".to_owned(),
+                ))
+            }
+        };
+        get_variant(
+            "There was an error reading the log information",
+            |size, buffer, used| unsafe {
+                get(self.handle.0, property as DWORD, size, buffer, used)
+            },
+        )
+    }
+}
+
+impl LogInfo {
+    /// The statistics of the local channel `channel`
+    pub fn for_channel(channel: &str) -> Result<LogInfo, Error> {
+        LogInfo::from_properties(&EvtLog::channel(channel)?)
+    }
+
+    /// The statistics of an exported or archived log file
+    pub fn for_file<P: AsRef<Path>>(path: P) -> Result<LogInfo, Error> {
+        LogInfo::from_properties(&EvtLog::file(path)?)
+    }
+}
+
+impl EvtSession {
+    /// Lists the channels on the remote machine
+    pub fn channel_paths(&self) -> Result<ChannelPaths, Error> {
+        ChannelPaths::open_in(Some(self))
+    }
+
+    /// The configuration of `channel` on the remote machine
+    pub fn channel_config(&self, channel: &str) -> Result<ChannelConfig, Error> {
+        ChannelConfig::from_properties(&EvtChannelConfig::open_in(Some(self), channel)?)
+    }
+
+    /// The statistics of `channel` on the remote machine
+    pub fn log_info(&self, channel: &str) -> Result<LogInfo, Error> {
+        LogInfo::from_properties(&EvtLog::open_in(
+            Some(self),
+            OsStr::new(channel),
+            EvtOpenChannelPath,
+        )?)
+    }
+}
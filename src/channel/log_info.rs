@@ -0,0 +1,114 @@
+use super::{boolean, number, optional_number};
+use crate::error::Error;
+use crate::variant::Variant;
+
+/// The `EVT_LOG_PROPERTY_ID`s read into a `LogInfo`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LogProperty {
+    CreationTime = 0,
+    LastWriteTime = 2,
+    FileSize = 3,
+    NumberOfLogRecords = 5,
+    OldestRecordNumber = 6,
+    Full = 7,
+}
+
+/// Somewhere to read a log's statistics from
+pub trait LogProperties {
+    fn property(&self, property: LogProperty) -> Result<Variant, Error>;
+}
+
+/// The state of a channel's log file, or of an exported log, for watching it
+/// fill up and roll over
+///
+/// Times are `FILETIME`s, 100ns intervals since 1601-01-01 UTC, as in
+/// `Variant::FileTime`.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use win_event_log::prelude::*;
+///
+/// # #[cfg(windows)]
+/// let info = LogInfo::for_channel("Security").unwrap();
+/// # #[cfg(windows)]
+/// println!("{} records, {} bytes, full: {}", info.record_count, info.file_size, info.is_full);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogInfo {
+    /// The record number of the oldest event, if there are any
+    pub oldest_record: Option<u64>,
+    pub record_count: u64,
+    /// The size of the log file, in bytes
+    pub file_size: u64,
+    pub creation_time: Option<u64>,
+    pub last_write_time: Option<u64>,
+    /// Whether the log has reached its maximum size
+    pub is_full: bool,
+}
+
+impl LogInfo {
+    /// Reads the statistics from `properties`
+    pub fn from_properties<P: LogProperties + ?Sized>(properties: &P) -> Result<LogInfo, Error> {
+        let get = |property| properties.property(property);
+        Ok(LogInfo {
+            oldest_record: optional_number(get, LogProperty::OldestRecordNumber)?,
+            record_count: number(get, LogProperty::NumberOfLogRecords)?,
+            file_size: number(get, LogProperty::FileSize)?,
+            creation_time: optional_number(get, LogProperty::CreationTime)?,
+            last_write_time: optional_number(get, LogProperty::LastWriteTime)?,
+            is_full: boolean(get, LogProperty::Full)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LogInfo, LogProperties, LogProperty};
+    use crate::error::Error;
+    use crate::variant::Variant;
+
+    struct Mock {
+        oldest: Variant,
+        full: Variant,
+    }
+
+    impl LogProperties for Mock {
+        fn property(&self, property: LogProperty) -> Result<Variant, Error> {
+            Ok(match property {
+                LogProperty::CreationTime => Variant::FileTime(132_000_000_000_000_000),
+                LogProperty::LastWriteTime => Variant::Null,
+                LogProperty::FileSize => Variant::UInt64(69_632),
+                LogProperty::NumberOfLogRecords => Variant::UInt64(12),
+                LogProperty::OldestRecordNumber => self.oldest.clone(),
+                LogProperty::Full => self.full.clone(),
+            })
+        }
+    }
+
+    #[test]
+    fn decode_log_info() {
+        let mut properties = Mock {
+            oldest: Variant::UInt64(1_041),
+            full: Variant::Boolean(false),
+        };
+        assert_eq!(
+            LogInfo::from_properties(&properties),
+            Ok(LogInfo {
+                oldest_record: Some(1_041),
+                record_count: 12,
+                file_size: 69_632,
+                creation_time: Some(132_000_000_000_000_000),
+                last_write_time: None,
+                is_full: false,
+            })
+        );
+        properties.oldest = Variant::Null;
+        assert_eq!(
+            LogInfo::from_properties(&properties).map(|info| info.oldest_record),
+            Ok(None)
+        );
+        properties.full = Variant::UInt32(1);
+        assert!(LogInfo::from_properties(&properties).is_err());
+    }
+}
@@ -0,0 +1,247 @@
+//! Event log channels and their configuration
+//!
+//! `ChannelConfig` is decoded from `Variant`s supplied by a `ChannelProperties`
+//! source, which is the event log service on Windows and can be a stand-in
+//! anywhere else.
+
+use crate::error::Error;
+use crate::variant::Variant;
+use std::fmt::Debug;
+
+#[cfg(windows)]
+mod live;
+mod log_info;
+
+#[cfg(windows)]
+pub use self::live::{ChannelPaths, EvtChannelConfig, EvtLog};
+pub use self::log_info::{LogInfo, LogProperties, LogProperty};
+
+/// The `EVT_CHANNEL_CONFIG_PROPERTY_ID`s read into a `ChannelConfig`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ChannelProperty {
+    Enabled = 0,
+    Isolation = 1,
+    Type = 2,
+    Access = 5,
+    Retention = 6,
+    AutoBackup = 7,
+    MaxSize = 8,
+    LogFilePath = 9,
+}
+
+/// Somewhere to read a channel's configuration properties from
+pub trait ChannelProperties {
+    fn property(&self, property: ChannelProperty) -> Result<Variant, Error>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChannelType {
+    Admin,
+    Operational,
+    Analytic,
+    Debug,
+}
+
+/// Which security descriptor the channel shares
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChannelIsolation {
+    /// The Application channel's
+    Application,
+    /// The System channel's
+    System,
+    /// Its own, given by `ChannelConfig::access`
+    Custom,
+}
+
+/// How a channel is set up, as `wevtutil get-log` shows it
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use win_event_log::prelude::*;
+///
+/// # #[cfg(windows)]
+/// for channel in ChannelPaths::new().unwrap() {
+///     let channel = channel.unwrap();
+///     if let Ok(config) = ChannelConfig::get(&channel) {
+///         println!("{}: enabled {}, {} bytes", channel, config.enabled, config.max_size);
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChannelConfig {
+    pub enabled: bool,
+    pub channel_type: ChannelType,
+    pub isolation: ChannelIsolation,
+    /// The largest the log file may grow to, in bytes
+    pub max_size: u64,
+    /// Whether a full log keeps its events instead of overwriting the oldest
+    pub retention: bool,
+    /// Whether a full log is archived and started afresh, when `retention` is set
+    pub auto_backup: bool,
+    /// The log file, which may name environment variables such as `%SystemRoot%`
+    pub log_file_path: String,
+    /// Who may read, write and clear the channel, in SDDL
+    pub access: String,
+}
+
+impl ChannelConfig {
+    /// Reads the configuration from `properties`
+    pub fn from_properties<P: ChannelProperties + ?Sized>(
+        properties: &P,
+    ) -> Result<ChannelConfig, Error> {
+        let get = |property| properties.property(property);
+        Ok(ChannelConfig {
+            enabled: boolean(get, ChannelProperty::Enabled)?,
+            channel_type: match number(get, ChannelProperty::Type)? {
+                0 => ChannelType::Admin,
+                1 => ChannelType::Operational,
+                2 => ChannelType::Analytic,
+                3 => ChannelType::Debug,
+                other => return Err(Error::Other(format!("Unknown channel type {other}"))),
+            },
+            isolation: match number(get, ChannelProperty::Isolation)? {
+                0 => ChannelIsolation::Application,
+                1 => ChannelIsolation::System,
+                2 => ChannelIsolation::Custom,
+                other => return Err(Error::Other(format!("Unknown channel isolation {other}"))),
+            },
+            max_size: number(get, ChannelProperty::MaxSize)?,
+            retention: boolean(get, ChannelProperty::Retention)?,
+            auto_backup: boolean(get, ChannelProperty::AutoBackup)?,
+            log_file_path: string(get, ChannelProperty::LogFilePath)?,
+            access: string(get, ChannelProperty::Access)?,
+        })
+    }
+}
+
+fn unexpected<K: Debug>(property: K, value: &Variant) -> Error {
+    Error::Other(format!(
+        "Property {property:?} has an unexpected value {value:?}"
+    ))
+}
+
+fn boolean<K: Debug + Copy>(
+    get: impl Fn(K) -> Result<Variant, Error>,
+    property: K,
+) -> Result<bool, Error> {
+    match get(property)? {
+        Variant::Boolean(value) => Ok(value),
+        other => Err(unexpected(property, &other)),
+    }
+}
+
+fn number<K: Debug + Copy>(
+    get: impl Fn(K) -> Result<Variant, Error>,
+    property: K,
+) -> Result<u64, Error> {
+    let value = get(property)?;
+    value.as_u64().ok_or_else(|| unexpected(property, &value))
+}
+
+/// A number property, which is null when there is nothing to report
+fn optional_number<K: Debug + Copy>(
+    get: impl Fn(K) -> Result<Variant, Error>,
+    property: K,
+) -> Result<Option<u64>, Error> {
+    match get(property)? {
+        Variant::Null => Ok(None),
+        value => match value.as_u64() {
+            Some(value) => Ok(Some(value)),
+            None => Err(unexpected(property, &value)),
+        },
+    }
+}
+
+/// A string property, which is null when it has not been set
+fn string<K: Debug + Copy>(
+    get: impl Fn(K) -> Result<Variant, Error>,
+    property: K,
+) -> Result<String, Error> {
+    match get(property)? {
+        Variant::Null => Ok(String::new()),
+        value => match value.as_str() {
+            Some(value) => Ok(value.to_owned()),
+            None => Err(unexpected(property, &value)),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChannelConfig, ChannelIsolation, ChannelProperties, ChannelProperty, ChannelType};
+    use crate::error::Error;
+    use crate::variant::Variant;
+    use std::collections::HashMap;
+
+    struct Mock(HashMap<ChannelProperty, Variant>);
+
+    impl ChannelProperties for Mock {
+        fn property(&self, property: ChannelProperty) -> Result<Variant, Error> {
+            self.0.get(&property).cloned().ok_or(Error::Windows {
+                code: 15027,
+                context: "There was an error reading the channel property".to_owned(),
+            })
+        }
+    }
+
+    #[test]
+    fn decode_config() {
+        let mut properties = Mock(
+            vec![
+                (ChannelProperty::Enabled, Variant::Boolean(true)),
+                (ChannelProperty::Isolation, Variant::UInt32(2)),
+                (ChannelProperty::Type, Variant::UInt32(0)),
+                (
+                    ChannelProperty::Access,
+                    Variant::String("O:BAG:SYD:(A;;0xf0005;;;SY)".to_owned()),
+                ),
+                (ChannelProperty::Retention, Variant::Boolean(false)),
+                (ChannelProperty::AutoBackup, Variant::Boolean(false)),
+                (ChannelProperty::MaxSize, Variant::UInt64(20_971_520)),
+                (
+                    ChannelProperty::LogFilePath,
+                    Variant::String(r"%SystemRoot%\System32\Winevt\Logs\Security.evtx".to_owned()),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        assert_eq!(
+            ChannelConfig::from_properties(&properties),
+            Ok(ChannelConfig {
+                enabled: true,
+                channel_type: ChannelType::Admin,
+                isolation: ChannelIsolation::Custom,
+                max_size: 20_971_520,
+                retention: false,
+                auto_backup: false,
+                log_file_path: r"%SystemRoot%\System32\Winevt\Logs\Security.evtx".to_owned(),
+                access: "O:BAG:SYD:(A;;0xf0005;;;SY)".to_owned(),
+            })
+        );
+        properties.0.insert(ChannelProperty::Access, Variant::Null);
+        assert_eq!(
+            ChannelConfig::from_properties(&properties).map(|config| config.access),
+            Ok(String::new())
+        );
+        properties
+            .0
+            .insert(ChannelProperty::Type, Variant::UInt32(9));
+        assert!(ChannelConfig::from_properties(&properties).is_err());
+        properties
+            .0
+            .insert(ChannelProperty::Type, Variant::UInt32(1));
+        properties
+            .0
+            .insert(ChannelProperty::Enabled, Variant::UInt32(1));
+        assert!(ChannelConfig::from_properties(&properties).is_err());
+        properties.0.remove(&ChannelProperty::Enabled);
+        assert_eq!(
+            ChannelConfig::from_properties(&properties)
+                .unwrap_err()
+                .code(),
+            Some(15027)
+        );
+    }
+}
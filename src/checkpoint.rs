@@ -0,0 +1,386 @@
+//! Saving bookmarks between runs
+//!
+//! A `CheckpointStore` keeps one bookmark per name, usually one name per query or
+//! subscription. `Checkpointed` wraps an event iterator and commits its position
+//! to a store as events are handled.
+
+use crate::bookmark::Bookmark;
+use crate::event::Event;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Somewhere bookmarks are kept between runs
+pub trait CheckpointStore {
+    /// The bookmark saved under `name`, if there is one
+    fn load(&self, name: &str) -> Result<Option<Bookmark>, String>;
+
+    /// Saves `bookmark` under `name`, replacing what was there
+    fn save(&self, name: &str, bookmark: &Bookmark) -> Result<(), String>;
+}
+
+impl<S: CheckpointStore + ?Sized> CheckpointStore for &S {
+    fn load(&self, name: &str) -> Result<Option<Bookmark>, String> {
+        (**self).load(name)
+    }
+
+    fn save(&self, name: &str, bookmark: &Bookmark) -> Result<(), String> {
+        (**self).save(name, bookmark)
+    }
+}
+
+impl<S: CheckpointStore + ?Sized> CheckpointStore for Arc<S> {
+    fn load(&self, name: &str) -> Result<Option<Bookmark>, String> {
+        (**self).load(name)
+    }
+
+    fn save(&self, name: &str, bookmark: &Bookmark) -> Result<(), String> {
+        (**self).save(name, bookmark)
+    }
+}
+
+/// Keeps bookmarks in memory, for tests and short-lived processes
+#[derive(Debug, Default)]
+pub struct MemoryCheckpointStore {
+    bookmarks: Mutex<HashMap<String, Bookmark>>,
+}
+
+impl MemoryCheckpointStore {
+    pub fn new() -> MemoryCheckpointStore {
+        Default::default()
+    }
+}
+
+impl CheckpointStore for MemoryCheckpointStore {
+    fn load(&self, name: &str) -> Result<Option<Bookmark>, String> {
+        let bookmarks = self
+            .bookmarks
+            .lock()
+            .map_err(|_| "Checkpoint store lock is poisoned".to_owned())?;
+        Ok(bookmarks.get(name).cloned())
+    }
+
+    fn save(&self, name: &str, bookmark: &Bookmark) -> Result<(), String> {
+        let mut bookmarks = self
+            .bookmarks
+            .lock()
+            .map_err(|_| "Checkpoint store lock is poisoned".to_owned())?;
+        bookmarks.insert(name.to_owned(), bookmark.clone());
+        Ok(())
+    }
+}
+
+/// Keeps each bookmark as XML in its own file in a directory. A save writes a
+/// temporary file, flushes it to disk and renames it over the old one, so a crash
+/// leaves either the old bookmark or the new one, never a mix.
+#[derive(Clone, Debug)]
+pub struct FileCheckpointStore {
+    directory: PathBuf,
+}
+
+impl FileCheckpointStore {
+    /// Uses `directory` for the bookmarks, creating it if needed
+    pub fn new<P: AsRef<Path>>(directory: P) -> Result<FileCheckpointStore, String> {
+        let directory = directory.as_ref();
+        fs::create_dir_all(directory)
+            .map_err(|err| format!("Could not create {}: {err}", directory.display()))?;
+        Ok(FileCheckpointStore {
+            directory: directory.to_owned(),
+        })
+    }
+
+    /// The file `name` is saved in. Anything but letters, digits, `-` and `_` is
+    /// escaped, so every name maps to its own file.
+    pub fn path(&self, name: &str) -> PathBuf {
+        let mut file = String::new();
+        for byte in name.bytes() {
+            match byte {
+                b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' => file.push(byte as char),
+                _ => file.push_str(&format!("%{byte:02X}")),
+            }
+        }
+        file.push_str(".bookmark");
+        self.directory.join(file)
+    }
+}
+
+impl CheckpointStore for FileCheckpointStore {
+    fn load(&self, name: &str) -> Result<Option<Bookmark>, String> {
+        let path = self.path(name);
+        match fs::read_to_string(&path) {
+            Ok(xml) => Bookmark::from_xml(&xml)
+                .map(Some)
+                .map_err(|err| format!("Could not load {}: {err}", path.display())),
+            Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(format!("Could not load {}: {err}", path.display())),
+        }
+    }
+
+    fn save(&self, name: &str, bookmark: &Bookmark) -> Result<(), String> {
+        let path = self.path(name);
+        let mut temporary = path.clone().into_os_string();
+        temporary.push(".tmp");
+        let temporary = PathBuf::from(temporary);
+        let err = |err: std::io::Error| format!("Could not save {}: {err}", path.display());
+        let mut file = File::create(&temporary).map_err(err)?;
+        file.write_all(bookmark.to_xml().as_bytes())
+            .and_then(|_| file.sync_all())
+            .map_err(err)?;
+        drop(file);
+        fs::rename(&temporary, &path).map_err(err)?;
+        // the rename itself is only durable once the directory is flushed
+        #[cfg(unix)]
+        File::open(&self.directory)
+            .and_then(|directory| directory.sync_all())
+            .map_err(err)?;
+        Ok(())
+    }
+}
+
+/// When `Checkpointed` commits its bookmark
+#[derive(Clone, Debug)]
+pub struct CheckpointPolicy {
+    events: usize,
+    interval: Option<Duration>,
+}
+
+impl<'a> CheckpointPolicy {
+    /// Commits every 100 events and every 10 seconds
+    pub fn new() -> CheckpointPolicy {
+        CheckpointPolicy {
+            events: 100,
+            interval: Some(Duration::from_secs(10)),
+        }
+    }
+
+    /// Commits after this many events have been handled, at least one
+    pub fn every_events(&'a mut self, events: usize) -> &'a mut Self {
+        self.events = events.max(1);
+        self
+    }
+
+    /// Commits when this long has passed since the last commit, checked as events
+    /// arrive. `None` commits on the event count alone.
+    pub fn every(&'a mut self, interval: Option<Duration>) -> &'a mut Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn build(&self) -> CheckpointPolicy {
+        self.clone()
+    }
+}
+
+impl Default for CheckpointPolicy {
+    fn default() -> CheckpointPolicy {
+        CheckpointPolicy::new()
+    }
+}
+
+/// Wraps an event iterator, recording each event in a bookmark and committing it
+/// to a `CheckpointStore`
+///
+/// An event counts as handled once the next one is asked for, or once the wrapper
+/// is dropped other than by a panic. After a crash, the events being handled at
+/// the time are delivered again rather than lost.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use win_event_log::prelude::*;
+///
+/// let store = FileCheckpointStore::new("checkpoints").unwrap();
+/// let bookmark = store.load("security").unwrap().unwrap_or_default();
+/// # #[cfg(windows)]
+/// # {
+/// let events = WinEvents::resume("*", &bookmark).unwrap();
+/// let policy = CheckpointPolicy::new();
+/// for event in Checkpointed::new(events, &store, "security", &policy).unwrap() {
+///     println!("{}", event);
+/// }
+/// # }
+/// ```
+pub struct Checkpointed<I, S: CheckpointStore> {
+    events: I,
+    store: S,
+    name: String,
+    policy: CheckpointPolicy,
+    bookmark: Bookmark,
+    /// The event returned last, not yet handled
+    returned: Option<Event>,
+    uncommitted: usize,
+    last_commit: Instant,
+    error: Option<String>,
+}
+
+impl<I, S> Checkpointed<I, S>
+where
+    I: IntoIterator<Item = Event>,
+    S: CheckpointStore,
+{
+    /// Wraps `events`, starting from the bookmark saved under `name` so entries
+    /// for other channels are kept
+    pub fn new(
+        events: I,
+        store: S,
+        name: &str,
+        policy: &CheckpointPolicy,
+    ) -> Result<Checkpointed<I::IntoIter, S>, String> {
+        let bookmark = store.load(name)?.unwrap_or_default();
+        Ok(Checkpointed {
+            events: events.into_iter(),
+            store,
+            name: name.to_owned(),
+            policy: policy.clone(),
+            bookmark,
+            returned: None,
+            uncommitted: 0,
+            last_commit: Instant::now(),
+            error: None,
+        })
+    }
+}
+
+impl<I, S: CheckpointStore> Checkpointed<I, S> {
+    /// The position so far, including events not committed yet
+    pub fn bookmark(&self) -> &Bookmark {
+        &self.bookmark
+    }
+
+    /// The last error from an automatic commit. Events keep flowing when a commit
+    /// fails; the next commit tries again.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Saves the bookmark now. The event returned last is not included.
+    pub fn commit(&mut self) -> Result<(), String> {
+        self.store.save(&self.name, &self.bookmark)?;
+        self.uncommitted = 0;
+        self.last_commit = Instant::now();
+        self.error = None;
+        Ok(())
+    }
+
+    /// Records the event returned last as handled
+    fn handled(&mut self) {
+        if let Some(event) = self.returned.take() {
+            // events without a channel or record id cannot be bookmarked
+            if self.bookmark.update(&event).is_ok() {
+                self.uncommitted += 1;
+            }
+        }
+    }
+
+    fn commit_if_due(&mut self) {
+        if self.uncommitted == 0 {
+            return;
+        }
+        let due = self.uncommitted >= self.policy.events
+            || self
+                .policy
+                .interval
+                .is_some_and(|interval| self.last_commit.elapsed() >= interval);
+        if due {
+            if let Err(err) = self.commit() {
+                self.error = Some(err);
+            }
+        }
+    }
+}
+
+impl<I: Iterator<Item = Event>, S: CheckpointStore> Iterator for Checkpointed<I, S> {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        self.handled();
+        self.commit_if_due();
+        let event = self.events.next()?;
+        self.returned = Some(event.clone());
+        Some(event)
+    }
+}
+
+impl<I, S: CheckpointStore> Drop for Checkpointed<I, S> {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            return;
+        }
+        self.handled();
+        if self.uncommitted > 0 {
+            let _ = self.commit();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        CheckpointPolicy, CheckpointStore, Checkpointed, FileCheckpointStore, MemoryCheckpointStore,
+    };
+    use crate::bookmark::Bookmark;
+    use crate::event::Event;
+
+    fn events(channel: &'static str, ids: std::ops::Range<u64>) -> impl Iterator<Item = Event> {
+        ids.map(move |id| {
+            Event(format!(
+                "<Event><System><EventRecordID>{id}</EventRecordID>\
+                 <Channel>{channel}</Channel></System></Event>"
+            ))
+        })
+    }
+
+    #[test]
+    fn file_store() {
+        let directory = std::env::temp_dir().join(format!("checkpoints-{}", std::process::id()));
+        let store = FileCheckpointStore::new(&directory).unwrap();
+        assert_eq!(store.load("agent/security").unwrap(), None);
+
+        let mut bookmark = Bookmark::new();
+        bookmark.set("Security", 42);
+        store.save("agent/security", &bookmark).unwrap();
+        bookmark.set("Security", 43);
+        store.save("agent/security", &bookmark).unwrap();
+        store.save("agent.security", &Bookmark::new()).unwrap();
+        assert_eq!(store.load("agent/security").unwrap(), Some(bookmark));
+        assert_eq!(store.load("agent.security").unwrap(), Some(Bookmark::new()));
+        assert_ne!(store.path("agent/security"), store.path("agent.security"));
+
+        let files = std::fs::read_dir(&directory).unwrap().count();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(files, 2);
+    }
+
+    #[test]
+    fn commits_by_count_and_on_drop() {
+        let store = MemoryCheckpointStore::new();
+        let policy = CheckpointPolicy::new().every_events(10).every(None).build();
+        let mut checkpointed =
+            Checkpointed::new(events("Security", 1..26), &store, "agent", &policy).unwrap();
+        for _ in 0..11 {
+            checkpointed.next().unwrap();
+        }
+        // the 11th event is still being handled
+        let saved = store.load("agent").unwrap().unwrap();
+        assert_eq!(saved.record_id("Security"), Some(10));
+        assert_eq!(checkpointed.bookmark().record_id("Security"), Some(10));
+
+        for _ in 0..5 {
+            checkpointed.next().unwrap();
+        }
+        drop(checkpointed);
+        let saved = store.load("agent").unwrap().unwrap();
+        assert_eq!(saved.record_id("Security"), Some(16));
+
+        let policy = CheckpointPolicy::new().every_events(1000).build();
+        let checkpointed =
+            Checkpointed::new(events("System", 1..4), &store, "agent", &policy).unwrap();
+        assert_eq!(checkpointed.count(), 3);
+        let saved = store.load("agent").unwrap().unwrap();
+        assert_eq!(saved.record_id("System"), Some(3));
+        assert_eq!(saved.record_id("Security"), Some(16));
+    }
+}
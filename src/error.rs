@@ -0,0 +1,144 @@
+//! The error type for live subscriptions
+
+use std::fmt;
+
+pub const ERROR_ACCESS_DENIED: u32 = 5;
+pub const ERROR_EVT_INVALID_CHANNEL_PATH: u32 = 15000;
+pub const ERROR_EVT_INVALID_QUERY: u32 = 15001;
+pub const ERROR_EVT_CHANNEL_NOT_FOUND: u32 = 15007;
+pub const ERROR_EVT_MALFORMED_XML_TEXT: u32 = 15008;
+/// Reported when a subscription misses events
+pub const ERROR_EVT_QUERY_RESULT_STALE: u32 = 15011;
+
+/// The broad cause of an `Error`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The query, channel path or query XML was rejected
+    InvalidQuery,
+    /// A channel named by the query does not exist
+    ChannelNotFound,
+    /// The caller may not read the channel, such as Security without
+    /// administrator rights
+    AccessDenied,
+    /// Events were missed
+    Stale,
+    Other,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// A Windows API call, or the event log service, failed with a system error code
+    Windows { code: u32, context: String },
+    /// Any other failure
+    Other(String),
+}
+
+impl Error {
+    /// The error from `GetLastError`, after `context` failed
+    #[cfg(windows)]
+    pub(crate) fn last_os(context: &str) -> Error {
+        Error::Windows {
+            code: unsafe { winapi::um::errhandlingapi::GetLastError() },
+            context: context.to_owned(),
+        }
+    }
+
+    /// The system error code, if there is one
+    pub fn code(&self) -> Option<u32> {
+        match *self {
+            Error::Windows { code, .. } => Some(code),
+            Error::Other(_) => None,
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        match self.code() {
+            Some(ERROR_EVT_INVALID_CHANNEL_PATH)
+            | Some(ERROR_EVT_INVALID_QUERY)
+            | Some(ERROR_EVT_MALFORMED_XML_TEXT) => ErrorKind::InvalidQuery,
+            Some(ERROR_EVT_CHANNEL_NOT_FOUND) => ErrorKind::ChannelNotFound,
+            Some(ERROR_ACCESS_DENIED) => ErrorKind::AccessDenied,
+            Some(ERROR_EVT_QUERY_RESULT_STALE) => ErrorKind::Stale,
+            _ => ErrorKind::Other,
+        }
+    }
+
+    /// Whether events were missed, for example because the channel was cleared or
+    /// overwrote them before they were read
+    pub fn is_stale(&self) -> bool {
+        self.kind() == ErrorKind::Stale
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Windows { code, context } => match self.kind() {
+                ErrorKind::InvalidQuery => write!(f, "{context}: the query is invalid ({code})"),
+                ErrorKind::ChannelNotFound => {
+                    write!(f, "{context}: the channel was not found ({code})")
+                }
+                ErrorKind::AccessDenied => write!(f, "{context}: access is denied ({code})"),
+                ErrorKind::Stale => write!(f, "{context}: events were missed ({code})"),
+                ErrorKind::Other => write!(f, "{context}: {code}"),
+            },
+            Error::Other(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<String> for Error {
+    fn from(message: String) -> Error {
+        Error::Other(message)
+    }
+}
+
+impl From<Error> for String {
+    fn from(error: Error) -> String {
+        error.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, ErrorKind, ERROR_EVT_CHANNEL_NOT_FOUND, ERROR_EVT_QUERY_RESULT_STALE};
+
+    #[test]
+    fn codes_and_messages() {
+        let stale = Error::Windows {
+            code: ERROR_EVT_QUERY_RESULT_STALE,
+            context: "The subscription reported an error".to_owned(),
+        };
+        assert!(stale.is_stale());
+        assert_eq!(
+            stale.to_string(),
+            "The subscription reported an error: events were missed (15011)"
+        );
+        let missing = Error::Windows {
+            code: ERROR_EVT_CHANNEL_NOT_FOUND,
+            context: "There was an error subscribing".to_owned(),
+        };
+        assert_eq!(missing.kind(), ErrorKind::ChannelNotFound);
+        assert!(!missing.is_stale());
+        let denied = Error::Windows {
+            code: 5,
+            context: "There was an error subscribing".to_owned(),
+        };
+        assert_eq!(denied.kind(), ErrorKind::AccessDenied);
+        assert_eq!(
+            Error::Windows {
+                code: 1722,
+                context: "There was an error reading events".to_owned(),
+            }
+            .to_string(),
+            "There was an error reading events: 1722"
+        );
+        let other = Error::from("No events".to_owned());
+        assert_eq!(other.code(), None);
+        assert_eq!(other.kind(), ErrorKind::Other);
+        assert!(!other.is_stale());
+        assert_eq!(String::from(other), "No events");
+    }
+}
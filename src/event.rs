@@ -0,0 +1,33 @@
+#[cfg(feature = "xml")]
+use quick_xml::de::from_str;
+#[cfg(feature = "xml")]
+use serde::de::DeserializeOwned;
+use std::fmt;
+
+/// A single rendered event, as XML. Live queries, subscriptions and offline readers
+/// all produce this same type.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Event(pub(crate) String);
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(feature = "xml")]
+impl Event {
+    pub fn into<T>(self) -> T
+    where
+        T: DeserializeOwned + Default,
+    {
+        from_str::<T>(&self.0).unwrap_or_default()
+    }
+
+    pub fn try_into<T>(self) -> Result<T, String>
+    where
+        T: DeserializeOwned + Default,
+    {
+        from_str::<T>(&self.0).map_err(|err| format!("Deserialization error: {err}"))
+    }
+}
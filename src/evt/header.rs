@@ -0,0 +1,139 @@
+use crate::evtx::cursor::Cursor;
+
+/// `LfLe`, stored in the log header and in every record
+pub const LOG_SIGNATURE: u32 = 0x654c_664c;
+/// Size of the `ELF_LOGFILE_HEADER` at the start of the file. Records wrap around
+/// to the offset right after it.
+pub const LOG_HEADER_SIZE: usize = 0x30;
+/// Size of the `ELF_EOF_RECORD` written after the newest record
+pub const EOF_RECORD_SIZE: usize = 0x28;
+/// The four marker values that follow the size field of the EOF record
+const EOF_MARKER: [u32; 4] = [0x1111_1111, 0x2222_2222, 0x3333_3333, 0x4444_4444];
+
+bitflags! {
+    /// Flags stored in the log header
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct LogFlags: u32 {
+        /// The log was not closed cleanly, so the offsets in the header may be stale
+        const Dirty = 0x1;
+        /// Records have wrapped around the end of the file
+        const Wrapped = 0x2;
+        const Full = 0x4;
+        const Archived = 0x8;
+    }
+}
+
+/// The `ELF_LOGFILE_HEADER` at the start of an `.evt` file
+#[derive(Clone, Debug)]
+pub struct LogHeader {
+    pub major_version: u32,
+    pub minor_version: u32,
+    /// Offset of the oldest record
+    pub start_offset: u32,
+    /// Offset of the EOF record
+    pub end_offset: u32,
+    /// Number the next record will get
+    pub current_record_number: u32,
+    pub oldest_record_number: u32,
+    pub max_size: u32,
+    pub flags: LogFlags,
+    pub retention: u32,
+}
+
+impl LogHeader {
+    pub fn parse(data: &[u8]) -> Result<LogHeader, String> {
+        let mut cursor = Cursor::new(data, 0);
+        let header_size = cursor.u32()?;
+        if cursor.u32()? != LOG_SIGNATURE {
+            return Err("Not an evt file: missing LfLe signature".to_owned());
+        }
+        if header_size as usize != LOG_HEADER_SIZE {
+            return Err(format!("Unexpected evt header size {header_size}"));
+        }
+        let header = LogHeader {
+            major_version: cursor.u32()?,
+            minor_version: cursor.u32()?,
+            start_offset: cursor.u32()?,
+            end_offset: cursor.u32()?,
+            current_record_number: cursor.u32()?,
+            oldest_record_number: cursor.u32()?,
+            max_size: cursor.u32()?,
+            flags: LogFlags::from_bits_retain(cursor.u32()?),
+            retention: cursor.u32()?,
+        };
+        if cursor.u32()? != header_size {
+            return Err("Evt header size fields do not match".to_owned());
+        }
+        if header.major_version != 1 {
+            return Err(format!(
+                "Unsupported evt version {}.{}",
+                header.major_version, header.minor_version
+            ));
+        }
+        Ok(header)
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.flags.contains(LogFlags::Dirty)
+    }
+
+    pub fn is_wrapped(&self) -> bool {
+        self.flags.contains(LogFlags::Wrapped)
+    }
+}
+
+/// The `ELF_EOF_RECORD` marking the end of the newest record. Unlike the header it
+/// is rewritten after every record, so it is current even when the log is dirty.
+#[derive(Clone, Debug)]
+pub struct EofRecord {
+    /// Offset of the EOF record itself
+    pub offset: u32,
+    /// Offset of the oldest record
+    pub begin_record: u32,
+    /// Offset of the EOF record, as recorded in it
+    pub end_record: u32,
+    pub current_record_number: u32,
+    pub oldest_record_number: u32,
+}
+
+impl EofRecord {
+    /// Parses the EOF record at `offset`. It may wrap around the end of the file.
+    pub fn parse(record: &[u8], offset: u32) -> Result<EofRecord, String> {
+        let mut cursor = Cursor::new(record, 0);
+        if cursor.u32()? as usize != EOF_RECORD_SIZE {
+            return Err(format!("No EOF record at offset {offset:#x}"));
+        }
+        for marker in EOF_MARKER.iter() {
+            if cursor.u32()? != *marker {
+                return Err(format!("No EOF record at offset {offset:#x}"));
+            }
+        }
+        Ok(EofRecord {
+            offset,
+            begin_record: cursor.u32()?,
+            end_record: cursor.u32()?,
+            current_record_number: cursor.u32()?,
+            oldest_record_number: cursor.u32()?,
+        })
+    }
+
+    /// Searches the file for the EOF record, for logs whose header is dirty. The
+    /// record may wrap around the end of the file, like any other.
+    pub fn find(data: &[u8]) -> Option<EofRecord> {
+        let mut marker = Vec::with_capacity(20);
+        marker.extend_from_slice(&(EOF_RECORD_SIZE as u32).to_le_bytes());
+        for value in EOF_MARKER.iter() {
+            marker.extend_from_slice(&value.to_le_bytes());
+        }
+        // the records, followed by the start of the ring again for a record
+        // that wraps
+        let records = data.get(LOG_HEADER_SIZE..)?;
+        let mut ring = records.to_vec();
+        ring.extend_from_slice(&records[..records.len().min(EOF_RECORD_SIZE - 1)]);
+        let position = ring
+            .windows(EOF_RECORD_SIZE)
+            .position(|window| window.starts_with(&marker))?;
+        let record = &ring[position..position + EOF_RECORD_SIZE];
+        EofRecord::parse(record, (LOG_HEADER_SIZE + position) as u32).ok()
+    }
+}
@@ -0,0 +1,499 @@
+//! A pure Rust reader for the classic `.evt` logs written by Windows XP and
+//! Server 2003
+//!
+//! The file is a ring buffer: records are read from the oldest one onwards,
+//! following them around the end of the file back to the first byte after the
+//! header, until the EOF record is reached. Each record becomes the same `Event`
+//! model the modern paths produce, using the XML Windows renders for legacy events.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use win_event_log::prelude::*;
+//!
+//! let reader = EvtReader::open("AppEvent.Evt").unwrap();
+//! for event in reader {
+//!     println!("{}", event);
+//! }
+//! ```
+
+use crate::event::Event;
+use crate::query_list::{QueryFilter, QueryList};
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+mod header;
+mod record;
+
+pub use self::header::{EofRecord, LogFlags, LogHeader};
+pub use self::record::{EventType, EvtRecord};
+
+use self::header::{EOF_RECORD_SIZE, LOG_HEADER_SIZE, LOG_SIGNATURE};
+
+/// Reads a legacy `.evt` file, which is held in memory since it has to be walked
+/// as a ring
+pub struct EvtReader {
+    data: Vec<u8>,
+    header: LogHeader,
+    /// Offset of the oldest record
+    start: usize,
+    /// Offset of the EOF record
+    end: usize,
+}
+
+impl EvtReader {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<EvtReader, String> {
+        let path = path.as_ref();
+        let data =
+            fs::read(path).map_err(|err| format!("Could not open {}: {err}", path.display()))?;
+        EvtReader::from_bytes(data)
+    }
+
+    pub fn new<R: Read>(mut reader: R) -> Result<EvtReader, String> {
+        let mut data = Vec::new();
+        reader
+            .read_to_end(&mut data)
+            .map_err(|err| format!("Could not read evt file: {err}"))?;
+        EvtReader::from_bytes(data)
+    }
+
+    /// Parses the header of the log in `data`. When the log is dirty the offsets
+    /// are taken from the EOF record instead, since the header may be stale.
+    pub fn from_bytes(data: Vec<u8>) -> Result<EvtReader, String> {
+        let header = LogHeader::parse(&data)?;
+        let eof = match header.is_dirty() {
+            true => EofRecord::find(&data),
+            false => None,
+        };
+        let (start, end) = match eof {
+            Some(eof) => (eof.begin_record as usize, eof.offset as usize),
+            None => (header.start_offset as usize, header.end_offset as usize),
+        };
+        for offset in [start, end] {
+            if offset < LOG_HEADER_SIZE || offset >= data.len() {
+                return Err(format!("Record offset {offset:#x} is outside the file"));
+            }
+        }
+        Ok(EvtReader {
+            data,
+            header,
+            start,
+            end,
+        })
+    }
+
+    pub fn header(&self) -> &LogHeader {
+        &self.header
+    }
+
+    /// Iterates over the records from oldest to newest. The first error ends the
+    /// iteration.
+    pub fn records(&self) -> EvtRecords<'_> {
+        EvtRecords {
+            reader: self,
+            walk: Walk::new(self.start),
+        }
+    }
+
+    /// Iterates over the records as events. The first error ends the iteration.
+    pub fn events(&self) -> EvtEvents<'_> {
+        EvtEvents {
+            records: self.records(),
+        }
+    }
+
+    /// Iterates over the events `list` selects, as `events` does
+    pub fn query(&self, list: &QueryList) -> QueryFilter<EvtEvents<'_>> {
+        list.filter(self.events())
+    }
+
+    /// Copies `len` bytes starting at `offset`, wrapping around the end of the file
+    fn ring(&self, offset: usize, len: usize) -> Result<Vec<u8>, String> {
+        if len > self.data.len() - LOG_HEADER_SIZE {
+            return Err(format!(
+                "Record at offset {offset:#x} is larger than the log"
+            ));
+        }
+        let first = len.min(self.data.len() - offset);
+        let mut out = self.data[offset..offset + first].to_vec();
+        out.extend_from_slice(&self.data[LOG_HEADER_SIZE..LOG_HEADER_SIZE + len - first]);
+        Ok(out)
+    }
+
+    /// The offset `len` bytes after `offset`, wrapping around the end of the file
+    fn advance(&self, offset: usize, len: usize) -> usize {
+        match offset + len {
+            next if next >= self.data.len() => next - self.data.len() + LOG_HEADER_SIZE,
+            next => next,
+        }
+    }
+
+    /// Whether everything from `offset` to the end of the file is the filler
+    /// written when the next record did not fit
+    fn is_padding(&self, offset: usize) -> bool {
+        self.data[offset..]
+            .chunks(4)
+            .all(|word| word.iter().all(|b| *b == 0) || word == [0x27, 0, 0, 0])
+    }
+}
+
+/// Position while walking the ring
+struct Walk {
+    offset: usize,
+    /// Bytes walked so far, so a corrupt ring cannot loop forever
+    walked: usize,
+    done: bool,
+}
+
+impl Walk {
+    fn new(offset: usize) -> Walk {
+        Walk {
+            offset,
+            walked: 0,
+            done: false,
+        }
+    }
+
+    fn next(&mut self, reader: &EvtReader) -> Option<Result<EvtRecord, String>> {
+        while !self.done {
+            if self.offset == reader.end {
+                self.done = true;
+                return None;
+            }
+            if self.walked > reader.data.len() {
+                self.done = true;
+                return Some(Err(format!(
+                    "EOF record at offset {:#x} was never reached",
+                    reader.end
+                )));
+            }
+            let record = self.record(reader);
+            self.done = record.is_err();
+            if let Some(record) = record.transpose() {
+                return Some(record);
+            }
+        }
+        None
+    }
+
+    /// Reads the record at the current offset, or wraps around past the padding
+    /// at the end of the file
+    fn record(&mut self, reader: &EvtReader) -> Result<Option<EvtRecord>, String> {
+        let prefix = reader.ring(self.offset, 8)?;
+        let length = u32::from_le_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]) as usize;
+        if prefix[4..] != LOG_SIGNATURE.to_le_bytes() {
+            if length == EOF_RECORD_SIZE {
+                return Err(format!(
+                    "Unexpected EOF record at offset {:#x}",
+                    self.offset
+                ));
+            }
+            if !reader.is_padding(self.offset) {
+                return Err(format!("No record at offset {:#x}", self.offset));
+            }
+            self.walked += reader.data.len() - self.offset;
+            self.offset = LOG_HEADER_SIZE;
+            return Ok(None);
+        }
+        let record = EvtRecord::parse(&reader.ring(self.offset, length)?, self.offset as u32)?;
+        self.walked += length;
+        self.offset = reader.advance(self.offset, length);
+        Ok(Some(record))
+    }
+}
+
+/// Iterator over the records of an `EvtReader`
+pub struct EvtRecords<'a> {
+    reader: &'a EvtReader,
+    walk: Walk,
+}
+
+impl Iterator for EvtRecords<'_> {
+    type Item = Result<EvtRecord, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.walk.next(self.reader)
+    }
+}
+
+/// Iterator over the records of an `EvtReader`, rendered as events
+pub struct EvtEvents<'a> {
+    records: EvtRecords<'a>,
+}
+
+impl Iterator for EvtEvents<'_> {
+    type Item = Result<Event, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.records
+            .next()
+            .map(|record| record.and_then(|record| record.to_event()))
+    }
+}
+
+impl IntoIterator for EvtReader {
+    type Item = Event;
+    type IntoIter = EvtIntoIterator;
+
+    fn into_iter(self) -> Self::IntoIter {
+        EvtIntoIterator {
+            walk: Walk::new(self.start),
+            reader: self,
+        }
+    }
+}
+
+/// Yields events like `EvtxIntoIterator`, stopping at the first record that cannot
+/// be read. Use `EvtReader::records` to see the error.
+pub struct EvtIntoIterator {
+    reader: EvtReader,
+    walk: Walk,
+}
+
+impl Iterator for EvtIntoIterator {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        self.walk
+            .next(&self.reader)
+            .and_then(|record| record.and_then(|record| record.to_event()).ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::header::{LOG_HEADER_SIZE, LOG_SIGNATURE};
+    use super::{EventType, EvtReader};
+
+    const SYSTEM: [u8; 12] = [1, 1, 0, 0, 0, 0, 0, 5, 18, 0, 0, 0];
+
+    fn utf16z(s: &str, out: &mut Vec<u8>) {
+        for c in s.encode_utf16().chain(Some(0)) {
+            out.extend_from_slice(&c.to_le_bytes());
+        }
+    }
+
+    fn record(number: u32, strings: &[&str], sid: Option<&[u8]>, data: &[u8]) -> Vec<u8> {
+        let mut names = Vec::new();
+        utf16z("Service Control Manager", &mut names);
+        utf16z("HOST", &mut names);
+        while names.len() % 4 != 0 {
+            names.push(0);
+        }
+        let sid = sid.unwrap_or(&[]);
+        let sid_offset = 0x38 + names.len();
+        let string_offset = sid_offset + sid.len();
+        let mut text = Vec::new();
+        for s in strings {
+            utf16z(s, &mut text);
+        }
+        let data_offset = string_offset + text.len();
+        let length = (data_offset + data.len() + 4) as u32;
+
+        let mut out = Vec::new();
+        for v in [length, LOG_SIGNATURE, number, 1_555_526_400, 1_555_526_401] {
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        out.extend_from_slice(&0xc000_1b58u32.to_le_bytes());
+        for v in [1u16, strings.len() as u16, 7, 0] {
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        for v in [
+            0,
+            string_offset,
+            sid.len(),
+            sid_offset,
+            data.len(),
+            data_offset,
+        ] {
+            out.extend_from_slice(&(v as u32).to_le_bytes());
+        }
+        out.extend(names);
+        out.extend_from_slice(sid);
+        out.extend(text);
+        out.extend_from_slice(data);
+        out.extend_from_slice(&length.to_le_bytes());
+        out
+    }
+
+    /// A log of `size` bytes, writing from `start` around the ring
+    struct Log {
+        data: Vec<u8>,
+        start: usize,
+        pos: usize,
+    }
+
+    impl Log {
+        fn new(size: usize, start: usize) -> Log {
+            Log {
+                data: vec![0; size],
+                start,
+                pos: start,
+            }
+        }
+
+        fn write(&mut self, bytes: &[u8]) -> &mut Self {
+            for b in bytes {
+                self.data[self.pos] = *b;
+                self.pos += 1;
+                if self.pos == self.data.len() {
+                    self.pos = LOG_HEADER_SIZE;
+                }
+            }
+            self
+        }
+
+        fn pad(&mut self) -> &mut Self {
+            while self.pos != LOG_HEADER_SIZE {
+                self.write(&[0x27, 0, 0, 0]);
+            }
+            self
+        }
+
+        /// Writes the EOF record and the header, returning the file
+        fn finish(&mut self, end: Option<usize>, flags: u32) -> Vec<u8> {
+            let eof = self.pos as u32;
+            let mut record = Vec::new();
+            for v in [0x28, 0x1111_1111, 0x2222_2222, 0x3333_3333, 0x4444_4444] {
+                record.extend_from_slice(&(v as u32).to_le_bytes());
+            }
+            for v in [self.start as u32, eof, 3, 1, 0x28] {
+                record.extend_from_slice(&v.to_le_bytes());
+            }
+            self.write(&record);
+            let end = end.map(|end| end as u32).unwrap_or(eof);
+            let size = self.data.len() as u32;
+            let header = [
+                0x30,
+                LOG_SIGNATURE,
+                1,
+                1,
+                self.start as u32,
+                end,
+                3,
+                1,
+                size,
+                flags,
+                0,
+                0x30,
+            ];
+            for (i, v) in header.iter().enumerate() {
+                self.data[i * 4..i * 4 + 4].copy_from_slice(&v.to_le_bytes());
+            }
+            self.data.clone()
+        }
+    }
+
+    fn events(reader: EvtReader) -> Vec<String> {
+        reader.into_iter().map(|event| event.to_string()).collect()
+    }
+
+    #[test]
+    fn read_records() {
+        let file = Log::new(0x1000, LOG_HEADER_SIZE)
+            .write(&record(1, &["Telnet", "a<b"], Some(&SYSTEM), &[0xde, 0xad]))
+            .write(&record(2, &[], None, &[]))
+            .finish(None, 0);
+        let reader = EvtReader::from_bytes(file).unwrap();
+        let records = reader
+            .records()
+            .collect::<Result<Vec<_>, String>>()
+            .unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].event_id & 0xffff, 7000);
+        assert_eq!(records[0].event_type, EventType::Error);
+        assert_eq!(records[0].strings, vec!["Telnet", "a<b"]);
+        assert_eq!(records[0].user_sid.as_deref(), Some(&SYSTEM[..]));
+        assert_eq!(
+            events(reader),
+            vec![
+                "<Event xmlns='http://schemas.microsoft.com/win/2004/08/events/event'><System>\
+                 <Provider Name='Service Control Manager'/>\
+                 <EventID Qualifiers='49152'>7000</EventID><Level>2</Level><Task>7</Task>\
+                 <Keywords>0x80000000000000</Keywords>\
+                 <TimeCreated SystemTime='2019-04-17T18:40:00.0000000Z'/>\
+                 <EventRecordID>1</EventRecordID><Computer>HOST</Computer>\
+                 <Security UserID='S-1-5-18'/></System><EventData><Data>Telnet</Data>\
+                 <Data>a&lt;b</Data><Binary>DEAD</Binary></EventData></Event>",
+                "<Event xmlns='http://schemas.microsoft.com/win/2004/08/events/event'><System>\
+                 <Provider Name='Service Control Manager'/>\
+                 <EventID Qualifiers='49152'>7000</EventID><Level>2</Level><Task>7</Task>\
+                 <Keywords>0x80000000000000</Keywords>\
+                 <TimeCreated SystemTime='2019-04-17T18:40:00.0000000Z'/>\
+                 <EventRecordID>2</EventRecordID><Computer>HOST</Computer>\
+                 <Security/></System><EventData></EventData></Event>",
+            ]
+        );
+    }
+
+    #[test]
+    fn wrap_around() {
+        let first = record(5, &["first"], None, &[]);
+        let second = record(6, &["second"], None, &[1, 2, 3]);
+        let third = record(7, &["third"], None, &[]);
+        // the second record is split across the end of the file
+        let size = 0x200 + first.len() + second.len() / 2;
+        let file = Log::new(size, 0x200)
+            .write(&first)
+            .write(&second)
+            .write(&third)
+            .finish(None, 0x2);
+        let reader = EvtReader::from_bytes(file).unwrap();
+        assert!(reader.header().is_wrapped());
+        let numbers = reader
+            .records()
+            .map(|record| record.unwrap().record_number)
+            .collect::<Vec<u32>>();
+        assert_eq!(numbers, vec![5, 6, 7]);
+
+        // padding instead of a split record
+        let size = 0x100 + first.len() + 16;
+        let file = Log::new(size, 0x100)
+            .write(&first)
+            .pad()
+            .write(&second)
+            .finish(None, 0x2);
+        let strings = EvtReader::from_bytes(file)
+            .unwrap()
+            .records()
+            .map(|record| record.unwrap().strings)
+            .collect::<Vec<Vec<String>>>();
+        assert_eq!(strings, vec![vec!["first"], vec!["second"]]);
+    }
+
+    #[test]
+    fn dirty_header() {
+        let first = record(1, &[], None, &[]);
+        let stale = LOG_HEADER_SIZE + first.len();
+        let file = Log::new(0x1000, LOG_HEADER_SIZE)
+            .write(&first)
+            .write(&record(2, &[], None, &[]))
+            .finish(Some(stale), 0x1);
+        let reader = EvtReader::from_bytes(file).unwrap();
+        assert!(reader.header().is_dirty());
+        assert_eq!(reader.records().count(), 2);
+
+        // the EOF record is split across the end of the file
+        let second = record(2, &[], None, &[]);
+        let size = 0x200 + first.len() + second.len() + 20;
+        let file = Log::new(size, 0x200)
+            .write(&first)
+            .write(&second)
+            .finish(Some(0x200 + first.len()), 0x3);
+        let reader = EvtReader::from_bytes(file).unwrap();
+        let numbers = reader
+            .records()
+            .map(|record| record.unwrap().record_number)
+            .collect::<Vec<u32>>();
+        assert_eq!(numbers, vec![1, 2]);
+    }
+
+    #[test]
+    fn not_an_evt_file() {
+        assert!(EvtReader::from_bytes(vec![0; 0x100]).is_err());
+        let mut file = Log::new(0x100, LOG_HEADER_SIZE).finish(None, 0);
+        file[16..20].copy_from_slice(&0x1000u32.to_le_bytes());
+        assert!(EvtReader::from_bytes(file).is_err());
+    }
+}
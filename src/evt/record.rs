@@ -0,0 +1,193 @@
+use crate::event::Event;
+use crate::evt::header::LOG_SIGNATURE;
+use crate::evtx::cursor::Cursor;
+use crate::evtx::value::{escape_xml, format_filetime, format_sid};
+
+/// Size of the fixed part of an `EVENTLOGRECORD`
+pub const RECORD_FIXED_SIZE: usize = 0x38;
+/// Seconds between 1601-01-01 and 1970-01-01
+const UNIX_EPOCH_SECONDS: u64 = 11_644_473_600;
+
+/// The `EventType` of a record
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventType {
+    Success,
+    Error,
+    Warning,
+    Information,
+    AuditSuccess,
+    AuditFailure,
+    Other(u16),
+}
+
+impl EventType {
+    fn from_u16(value: u16) -> EventType {
+        match value {
+            0x00 => EventType::Success,
+            0x01 => EventType::Error,
+            0x02 => EventType::Warning,
+            0x04 => EventType::Information,
+            0x08 => EventType::AuditSuccess,
+            0x10 => EventType::AuditFailure,
+            other => EventType::Other(other),
+        }
+    }
+
+    /// The `Level` Windows gives converted legacy events
+    pub fn level(self) -> u8 {
+        match self {
+            EventType::Error => 2,
+            EventType::Warning => 3,
+            EventType::AuditSuccess | EventType::AuditFailure => 0,
+            _ => 4,
+        }
+    }
+
+    /// The `Keywords` Windows gives converted legacy events
+    pub fn keywords(self) -> u64 {
+        match self {
+            EventType::AuditSuccess => 0x8020_0000_0000_0000,
+            EventType::AuditFailure => 0x8010_0000_0000_0000,
+            _ => 0x0080_0000_0000_0000,
+        }
+    }
+}
+
+/// One `EVENTLOGRECORD` from an `.evt` file
+#[derive(Clone, Debug)]
+pub struct EvtRecord {
+    /// Offset of the record in the file
+    pub offset: u32,
+    pub record_number: u32,
+    /// Seconds since 1970-01-01 UTC
+    pub time_generated: u32,
+    pub time_written: u32,
+    /// The full event identifier, including the qualifiers in the upper 16 bits
+    pub event_id: u32,
+    pub event_type: EventType,
+    pub category: u16,
+    pub source: String,
+    pub computer: String,
+    /// Binary SID of the user, if one was recorded
+    pub user_sid: Option<Vec<u8>>,
+    /// Insertion strings for the message
+    pub strings: Vec<String>,
+    pub data: Vec<u8>,
+}
+
+impl EvtRecord {
+    /// Parses a complete record, which has already been unwrapped if it crossed the
+    /// end of the file
+    pub fn parse(record: &[u8], offset: u32) -> Result<EvtRecord, String> {
+        let err = |message: String| format!("Record at offset {offset:#x}: {message}");
+        let mut cursor = Cursor::new(record, 0);
+        let length = cursor.u32()? as usize;
+        if cursor.u32()? != LOG_SIGNATURE {
+            return Err(err("missing LfLe signature".to_owned()));
+        }
+        if length < RECORD_FIXED_SIZE + 4 || length != record.len() {
+            return Err(err(format!("invalid length {length}")));
+        }
+        if Cursor::new(record, length - 4).u32()? as usize != length {
+            return Err(err("trailing length does not match".to_owned()));
+        }
+        let record_number = cursor.u32()?;
+        let time_generated = cursor.u32()?;
+        let time_written = cursor.u32()?;
+        let event_id = cursor.u32()?;
+        let event_type = EventType::from_u16(cursor.u16()?);
+        let string_count = cursor.u16()?;
+        let category = cursor.u16()?;
+        let _reserved_flags = cursor.u16()?;
+        let _closing_record_number = cursor.u32()?;
+        let string_offset = cursor.u32()? as usize;
+        let sid_length = cursor.u32()? as usize;
+        let sid_offset = cursor.u32()? as usize;
+        let data_length = cursor.u32()? as usize;
+        let data_offset = cursor.u32()? as usize;
+
+        let source = cursor.utf16z().map_err(err)?;
+        let computer = cursor.utf16z().map_err(err)?;
+        let user_sid = match sid_length {
+            0 => None,
+            len => Some(
+                Cursor::new(record, sid_offset)
+                    .bytes(len)
+                    .map_err(err)?
+                    .to_vec(),
+            ),
+        };
+        let mut cursor = Cursor::new(record, string_offset);
+        let strings = (0..string_count)
+            .map(|_| cursor.utf16z())
+            .collect::<Result<Vec<String>, String>>()
+            .map_err(err)?;
+        let data = Cursor::new(record, data_offset)
+            .bytes(data_length)
+            .map_err(err)?
+            .to_vec();
+
+        Ok(EvtRecord {
+            offset,
+            record_number,
+            time_generated,
+            time_written,
+            event_id,
+            event_type,
+            category,
+            source,
+            computer,
+            user_sid,
+            strings,
+            data,
+        })
+    }
+
+    /// Renders the record the way Windows renders a legacy event, with the
+    /// insertion strings as unnamed `Data` elements and any binary data as hex.
+    /// Legacy logs do not record their channel, so `Channel` is left out.
+    pub fn to_xml(&self) -> Result<String, String> {
+        let mut out = String::from(
+            "<Event xmlns='http://schemas.microsoft.com/win/2004/08/events/event'><System>",
+        );
+        out.push_str("<Provider Name='");
+        escape_xml(&self.source, &mut out);
+        out.push_str(&format!(
+            "'/><EventID Qualifiers='{}'>{}</EventID><Level>{}</Level><Task>{}</Task>\
+             <Keywords>{:#x}</Keywords><TimeCreated SystemTime='{}'/>\
+             <EventRecordID>{}</EventRecordID><Computer>",
+            self.event_id >> 16,
+            self.event_id & 0xffff,
+            self.event_type.level(),
+            self.category,
+            self.event_type.keywords(),
+            format_filetime((self.time_generated as u64 + UNIX_EPOCH_SECONDS) * 10_000_000),
+            self.record_number
+        ));
+        escape_xml(&self.computer, &mut out);
+        out.push_str("</Computer>");
+        match self.user_sid {
+            Some(ref sid) => out.push_str(&format!("<Security UserID='{}'/>", format_sid(sid)?)),
+            None => out.push_str("<Security/>"),
+        }
+        out.push_str("</System><EventData>");
+        for string in self.strings.iter() {
+            out.push_str("<Data>");
+            escape_xml(string, &mut out);
+            out.push_str("</Data>");
+        }
+        if !self.data.is_empty() {
+            out.push_str("<Binary>");
+            for byte in self.data.iter() {
+                out.push_str(&format!("{byte:02X}"));
+            }
+            out.push_str("</Binary>");
+        }
+        out.push_str("</EventData></Event>");
+        Ok(out)
+    }
+
+    pub fn to_event(&self) -> Result<Event, String> {
+        self.to_xml().map(Event)
+    }
+}
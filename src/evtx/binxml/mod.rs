@@ -0,0 +1,453 @@
+//! Decoding of Windows Binary XML (BinXml), the encoding used for event data in
+//! `.evtx` chunks
+//!
+//! Fragments decode into a tree of `XmlNode`s that still refers to the templates
+//! used by the record, which makes rendering to the same XML text `EvtRender`
+//! produces cheap. `XmlNode::expand` resolves the templates into a plain tree for
+//! callers that want to inspect the event structurally.
+
+use crate::evtx::cursor::Cursor;
+use crate::evtx::value::{XmlValue, BINXML_TYPE, WSTRING_TYPE};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+mod tree;
+
+pub(crate) use self::tree::render_lossy;
+pub use self::tree::{Template, XmlAttribute, XmlElement, XmlNode};
+
+const TOKEN_EOF: u8 = 0x00;
+const TOKEN_OPEN_START_ELEMENT: u8 = 0x01;
+const TOKEN_CLOSE_START_ELEMENT: u8 = 0x02;
+const TOKEN_CLOSE_EMPTY_ELEMENT: u8 = 0x03;
+const TOKEN_END_ELEMENT: u8 = 0x04;
+const TOKEN_VALUE: u8 = 0x05;
+const TOKEN_ATTRIBUTE: u8 = 0x06;
+const TOKEN_CDATA_SECTION: u8 = 0x07;
+const TOKEN_CHAR_REFERENCE: u8 = 0x08;
+const TOKEN_ENTITY_REFERENCE: u8 = 0x09;
+const TOKEN_PI_TARGET: u8 = 0x0a;
+const TOKEN_PI_DATA: u8 = 0x0b;
+const TOKEN_TEMPLATE_INSTANCE: u8 = 0x0c;
+const TOKEN_NORMAL_SUBSTITUTION: u8 = 0x0d;
+const TOKEN_OPTIONAL_SUBSTITUTION: u8 = 0x0e;
+const TOKEN_FRAGMENT_HEADER: u8 = 0x0f;
+/// Set on element and attribute tokens when more data of the same kind follows
+const TOKEN_HAS_MORE: u8 = 0x40;
+
+/// Size of a template definition header: next offset, GUID and data size
+const TEMPLATE_HEADER_SIZE: usize = 24;
+/// Nesting limit for elements, templates and embedded BinXml, so corrupt data
+/// cannot recurse forever or exhaust the stack
+pub(crate) const MAX_DEPTH: usize = 64;
+
+/// Templates already decoded from one chunk, keyed by definition offset. Records
+/// using the same template share one parsed copy.
+#[derive(Default)]
+pub struct TemplateCache {
+    templates: RefCell<HashMap<u32, Arc<Template>>>,
+}
+
+impl TemplateCache {
+    pub fn new() -> TemplateCache {
+        Default::default()
+    }
+
+    /// Number of templates decoded so far
+    pub fn len(&self) -> usize {
+        self.templates.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn get(&self, offset: u32) -> Option<Arc<Template>> {
+        self.templates.borrow().get(&offset).cloned()
+    }
+
+    fn insert(&self, template: Arc<Template>) {
+        self.templates
+            .borrow_mut()
+            .insert(template.offset, template);
+    }
+}
+
+/// Decodes BinXml fragments stored in a chunk. All offsets, including those of
+/// element names and template definitions, are relative to the start of the chunk.
+pub struct Decoder<'a> {
+    chunk: &'a [u8],
+    strings: &'a HashMap<u32, String>,
+    templates: &'a TemplateCache,
+}
+
+impl<'a> Decoder<'a> {
+    /// `strings` holds names already read from the chunk's string table, so common
+    /// names are not decoded again for every record
+    pub fn new(
+        chunk: &'a [u8],
+        strings: &'a HashMap<u32, String>,
+        templates: &'a TemplateCache,
+    ) -> Decoder<'a> {
+        Decoder {
+            chunk,
+            strings,
+            templates,
+        }
+    }
+
+    /// Decodes the fragment starting at `offset`
+    pub fn decode(&self, offset: usize) -> Result<Vec<XmlNode>, String> {
+        self.fragment(&mut Cursor::new(self.chunk, offset), 0, false)
+    }
+
+    /// Decodes the fragment starting at `offset` and renders it as XML text
+    pub fn render(&self, offset: usize) -> Result<String, String> {
+        XmlNode::to_xml(&self.decode(offset)?)
+    }
+
+    /// Decodes nodes up to the end of the fragment. `embedded` is set for BinXml
+    /// stored in a value, whose elements have no dependency identifier.
+    fn fragment(
+        &self,
+        cursor: &mut Cursor,
+        depth: usize,
+        embedded: bool,
+    ) -> Result<Vec<XmlNode>, String> {
+        too_deep(cursor, depth)?;
+        let mut nodes = Vec::new();
+        loop {
+            match peek(cursor)? & !TOKEN_HAS_MORE {
+                TOKEN_EOF => {
+                    cursor.u8()?;
+                    return Ok(nodes);
+                }
+                TOKEN_FRAGMENT_HEADER => {
+                    cursor.bytes(4)?;
+                }
+                _ => nodes.push(self.node(cursor, depth, embedded)?),
+            }
+        }
+    }
+
+    /// Decodes an element, template instance or content token
+    fn node(&self, cursor: &mut Cursor, depth: usize, embedded: bool) -> Result<XmlNode, String> {
+        match peek(cursor)? & !TOKEN_HAS_MORE {
+            TOKEN_OPEN_START_ELEMENT => {
+                Ok(XmlNode::Element(self.element(cursor, depth, embedded)?))
+            }
+            TOKEN_TEMPLATE_INSTANCE => self.template_instance(cursor, depth),
+            _ => self.content(cursor, depth),
+        }
+    }
+
+    fn element(
+        &self,
+        cursor: &mut Cursor,
+        depth: usize,
+        embedded: bool,
+    ) -> Result<XmlElement, String> {
+        too_deep(cursor, depth)?;
+        let token = cursor.u8()?;
+        if !embedded {
+            let _dependency_id = cursor.u16()?;
+        }
+        let _size = cursor.u32()?;
+        let name = self.name(cursor)?;
+
+        let mut attributes = Vec::new();
+        if token & TOKEN_HAS_MORE != 0 {
+            let _attribute_list_size = cursor.u32()?;
+            loop {
+                let token = cursor.u8()?;
+                if token & !TOKEN_HAS_MORE != TOKEN_ATTRIBUTE {
+                    return Err(format!(
+                        "Expected attribute token at offset {:#x}, found {token:#04x}",
+                        cursor.position() - 1
+                    ));
+                }
+                let name = self.name(cursor)?;
+                let mut value = Vec::new();
+                while let TOKEN_VALUE
+                | TOKEN_NORMAL_SUBSTITUTION
+                | TOKEN_OPTIONAL_SUBSTITUTION
+                | TOKEN_CHAR_REFERENCE
+                | TOKEN_ENTITY_REFERENCE = peek(cursor)? & !TOKEN_HAS_MORE
+                {
+                    value.push(self.content(cursor, depth + 1)?);
+                }
+                attributes.push(XmlAttribute { name, value });
+                if token & TOKEN_HAS_MORE == 0 {
+                    break;
+                }
+            }
+        }
+
+        let mut children = Vec::new();
+        match cursor.u8()? {
+            TOKEN_CLOSE_EMPTY_ELEMENT => {}
+            TOKEN_CLOSE_START_ELEMENT => loop {
+                match peek(cursor)? & !TOKEN_HAS_MORE {
+                    TOKEN_END_ELEMENT => {
+                        cursor.u8()?;
+                        break;
+                    }
+                    TOKEN_EOF => return Err(format!("Unterminated element <{name}>")),
+                    _ => children.push(self.node(cursor, depth + 1, embedded)?),
+                }
+            },
+            other => {
+                return Err(format!(
+                    "Unexpected token {other:#04x} closing <{name}> at offset {:#x}",
+                    cursor.position() - 1
+                ))
+            }
+        }
+        Ok(XmlElement {
+            name,
+            attributes,
+            children,
+        })
+    }
+
+    fn content(&self, cursor: &mut Cursor, depth: usize) -> Result<XmlNode, String> {
+        let offset = cursor.position();
+        let token = cursor.u8()?;
+        match token & !TOKEN_HAS_MORE {
+            TOKEN_VALUE => {
+                let value_type = cursor.u8()?;
+                if value_type == WSTRING_TYPE {
+                    let len = cursor.u16()? as usize;
+                    Ok(XmlNode::Text(cursor.utf16(len)?))
+                } else {
+                    // other types are rare outside substitutions, and stored with a
+                    // size prefix like strings
+                    let size = cursor.u16()? as usize;
+                    let data = cursor.bytes(size)?;
+                    Ok(XmlNode::Value(self.value(
+                        value_type,
+                        offset + 4,
+                        data,
+                        depth,
+                    )?))
+                }
+            }
+            TOKEN_NORMAL_SUBSTITUTION | TOKEN_OPTIONAL_SUBSTITUTION => {
+                let index = cursor.u16()?;
+                let _value_type = cursor.u8()?;
+                Ok(XmlNode::Substitution {
+                    index,
+                    optional: token & !TOKEN_HAS_MORE == TOKEN_OPTIONAL_SUBSTITUTION,
+                })
+            }
+            TOKEN_CDATA_SECTION => {
+                let len = cursor.u16()? as usize;
+                Ok(XmlNode::CData(cursor.utf16(len)?))
+            }
+            TOKEN_CHAR_REFERENCE => Ok(XmlNode::CharRef(cursor.u16()?)),
+            TOKEN_ENTITY_REFERENCE => Ok(XmlNode::EntityRef(self.name(cursor)?)),
+            TOKEN_PI_TARGET => {
+                let target = self.name(cursor)?;
+                let mut data = String::new();
+                if peek(cursor)? == TOKEN_PI_DATA {
+                    cursor.u8()?;
+                    let len = cursor.u16()? as usize;
+                    data = cursor.utf16(len)?;
+                }
+                Ok(XmlNode::ProcessingInstruction { target, data })
+            }
+            other => Err(format!(
+                "Unexpected BinXml token {other:#04x} at offset {offset:#x}"
+            )),
+        }
+    }
+
+    fn template_instance(&self, cursor: &mut Cursor, depth: usize) -> Result<XmlNode, String> {
+        cursor.u8()?;
+        let _unknown = cursor.u8()?;
+        let _template_id = cursor.u32()?;
+        let definition = cursor.u32()?;
+        if definition as usize == cursor.position() {
+            // the definition is stored inline, the first time the template is used
+            cursor.bytes(TEMPLATE_HEADER_SIZE - 4)?;
+            let size = cursor.u32()? as usize;
+            cursor.bytes(size)?;
+        }
+        let template = self.template(definition, depth)?;
+
+        let count = cursor.u32()? as usize;
+        let mut descriptors = Vec::with_capacity(count.min(1024));
+        for _ in 0..count {
+            let size = cursor.u16()? as usize;
+            let value_type = cursor.u8()?;
+            let _padding = cursor.u8()?;
+            descriptors.push((size, value_type));
+        }
+        let mut values = Vec::with_capacity(descriptors.len());
+        for (size, value_type) in descriptors {
+            let offset = cursor.position();
+            let data = cursor.bytes(size)?;
+            values.push(self.value(value_type, offset, data, depth)?);
+        }
+        Ok(XmlNode::TemplateInstance { template, values })
+    }
+
+    /// Returns the template defined at `offset`, decoding it on first use
+    fn template(&self, offset: u32, depth: usize) -> Result<Arc<Template>, String> {
+        if let Some(template) = self.templates.get(offset) {
+            return Ok(template);
+        }
+        let mut cursor = Cursor::new(self.chunk, offset as usize);
+        let _next = cursor.u32()?;
+        let mut guid = [0u8; 16];
+        guid.copy_from_slice(cursor.bytes(16)?);
+        let _size = cursor.u32()?;
+        let template = Arc::new(Template {
+            offset,
+            guid,
+            nodes: self.fragment(&mut cursor, depth + 1, false)?,
+        });
+        self.templates.insert(template.clone());
+        Ok(template)
+    }
+
+    /// Decodes a value stored at `offset`; embedded BinXml is decoded in place so
+    /// its name and template references resolve against this chunk
+    fn value(
+        &self,
+        value_type: u8,
+        offset: usize,
+        data: &[u8],
+        depth: usize,
+    ) -> Result<XmlValue, String> {
+        if value_type == BINXML_TYPE {
+            let mut cursor = Cursor::new(&self.chunk[..offset + data.len()], offset);
+            self.fragment(&mut cursor, depth + 1, true)
+                .map(XmlValue::BinXml)
+        } else {
+            XmlValue::parse(value_type, data).map_err(|err| format!("{err} at offset {offset:#x}"))
+        }
+    }
+
+    /// Reads a name reference. Names are stored once per chunk; the first use is
+    /// followed by the name itself, later uses only refer to it.
+    fn name(&self, cursor: &mut Cursor) -> Result<String, String> {
+        let offset = cursor.u32()?;
+        if offset as usize == cursor.position() {
+            let len = Cursor::new(self.chunk, offset as usize + 6).u16()? as usize;
+            let name = read_name(self.chunk, offset)?;
+            cursor.bytes(8 + (len + 1) * 2)?;
+            Ok(name)
+        } else if let Some(name) = self.strings.get(&offset) {
+            Ok(name.clone())
+        } else {
+            read_name(self.chunk, offset)
+        }
+    }
+}
+
+fn peek(cursor: &Cursor) -> Result<u8, String> {
+    cursor.clone().u8()
+}
+
+fn too_deep(cursor: &Cursor, depth: usize) -> Result<(), String> {
+    if depth > MAX_DEPTH {
+        return Err(format!(
+            "BinXml nesting too deep at offset {:#x}",
+            cursor.position()
+        ));
+    }
+    Ok(())
+}
+
+/// Reads the name structure at `offset`: next offset, hash, length and UTF-16 text
+pub fn read_name(chunk: &[u8], offset: u32) -> Result<String, String> {
+    let mut cursor = Cursor::new(chunk, offset as usize + 6);
+    let len = cursor.u16()? as usize;
+    cursor.utf16(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decoder, TemplateCache, XmlElement, XmlNode};
+    use crate::evtx::testdata::Writer;
+    use std::collections::HashMap;
+
+    #[test]
+    fn embedded_binxml_substitution() {
+        let base = 512;
+        let mut w = Writer::new(base);
+        w.fragment_header();
+        w.u8(0x0c).u8(0x01).u32(1);
+        let definition = w.pos() as u32 + 4;
+        w.u32(definition).u32(0).bytes(&[0; 16]);
+        let mut body = Writer::new(w.pos() + 4);
+        body.fragment_header();
+        body.open("Event", false);
+        body.u8(0x02).substitution(false, 0, 0x21).u8(0x04).u8(0x00);
+        w.u32(body.buf.len() as u32).bytes(&body.buf);
+
+        let mut value = Writer::new(w.pos() + 8);
+        value.fragment_header();
+        // elements embedded in a value have no dependency identifier
+        value.open_embedded("Data", true);
+        value.attribute("Name", false);
+        value.text("Param");
+        value.u8(0x02).text("x & y").u8(0x04).u8(0x00);
+        w.u32(1).u16(value.buf.len() as u16).u8(0x21).u8(0);
+        w.bytes(&value.buf).u8(0x00);
+
+        let mut chunk = vec![0u8; base];
+        chunk.extend_from_slice(&w.buf);
+        let strings = HashMap::new();
+        let templates = TemplateCache::new();
+        let decoder = Decoder::new(&chunk, &strings, &templates);
+        assert_eq!(
+            decoder.render(base).unwrap(),
+            "<Event><Data Name='Param'>x &amp; y</Data></Event>"
+        );
+        let tree = XmlNode::expand(&decoder.decode(base).unwrap()).unwrap();
+        let event = tree[0].as_element().unwrap();
+        let data = event.child("Data").unwrap();
+        assert_eq!(data.text(), "x & y");
+        assert_eq!(data.attribute_value("Name"), Some("Param".to_owned()));
+        assert_eq!(templates.len(), 1);
+    }
+
+    #[test]
+    fn deeply_nested_elements() {
+        let base = 512;
+        let mut w = Writer::new(base);
+        w.fragment_header();
+        for _ in 0..1000 {
+            w.open("a", false);
+            w.u8(0x02);
+        }
+        for _ in 0..1000 {
+            w.u8(0x04);
+        }
+        w.u8(0x00);
+
+        let mut chunk = vec![0u8; base];
+        chunk.extend_from_slice(&w.buf);
+        let strings = HashMap::new();
+        let templates = TemplateCache::new();
+        let decoder = Decoder::new(&chunk, &strings, &templates);
+        let err = decoder.decode(base).unwrap_err();
+        assert!(err.contains("too deep"), "{}", err);
+
+        let mut node = XmlNode::Text("x".to_owned());
+        for _ in 0..1000 {
+            node = XmlNode::Element(XmlElement {
+                name: "a".to_owned(),
+                attributes: Vec::new(),
+                children: vec![node],
+            });
+        }
+        let nodes = [node];
+        assert!(XmlNode::to_xml(&nodes).is_err());
+        assert!(XmlNode::expand(&nodes).is_err());
+        assert_eq!(nodes[0].as_element().unwrap().text(), "");
+    }
+}
@@ -0,0 +1,340 @@
+//! The structured form of decoded BinXml
+
+use super::MAX_DEPTH;
+use crate::evtx::value::{escape_xml, XmlValue};
+use std::sync::Arc;
+
+/// A node of a decoded BinXml fragment
+#[derive(Clone, Debug, PartialEq)]
+pub enum XmlNode {
+    Element(XmlElement),
+    /// Literal text from a value token
+    Text(String),
+    /// A typed value filled in from a template substitution
+    Value(XmlValue),
+    CData(String),
+    CharRef(u16),
+    EntityRef(String),
+    ProcessingInstruction {
+        target: String,
+        data: String,
+    },
+    /// A placeholder inside a template, replaced by the instance's value `index`.
+    /// Optional substitutions produce nothing when their value is empty.
+    Substitution {
+        index: u16,
+        optional: bool,
+    },
+    /// A use of a template, as stored in the record. `XmlNode::expand` replaces it
+    /// with the template's nodes filled in with `values`.
+    TemplateInstance {
+        template: Arc<Template>,
+        values: Vec<XmlValue>,
+    },
+}
+
+/// An element with its attributes and children
+#[derive(Clone, Debug, PartialEq)]
+pub struct XmlElement {
+    pub name: String,
+    pub attributes: Vec<XmlAttribute>,
+    pub children: Vec<XmlNode>,
+}
+
+/// An attribute; its value is made of text, value and substitution nodes
+#[derive(Clone, Debug, PartialEq)]
+pub struct XmlAttribute {
+    pub name: String,
+    pub value: Vec<XmlNode>,
+}
+
+/// A template definition, shared by every record in the chunk that uses it
+#[derive(Debug, PartialEq)]
+pub struct Template {
+    /// Offset of the definition within its chunk
+    pub offset: u32,
+    pub guid: [u8; 16],
+    pub nodes: Vec<XmlNode>,
+}
+
+impl XmlNode {
+    /// Resolves template instances and substitutions, producing a plain tree
+    pub fn expand(nodes: &[XmlNode]) -> Result<Vec<XmlNode>, String> {
+        let mut out = Vec::with_capacity(nodes.len());
+        expand_into(nodes, &[], &mut out, 0)?;
+        Ok(out)
+    }
+
+    /// Renders nodes as XML text
+    pub fn to_xml(nodes: &[XmlNode]) -> Result<String, String> {
+        let mut out = String::new();
+        render(nodes, &[], &mut out)?;
+        Ok(out)
+    }
+
+    /// The element, if this node is one
+    pub fn as_element(&self) -> Option<&XmlElement> {
+        match self {
+            XmlNode::Element(element) => Some(element),
+            _ => None,
+        }
+    }
+}
+
+impl XmlElement {
+    /// The first child element called `name`
+    pub fn child(&self, name: &str) -> Option<&XmlElement> {
+        self.elements().find(|element| element.name == name)
+    }
+
+    /// Child elements, skipping text
+    pub fn elements(&self) -> impl Iterator<Item = &XmlElement> {
+        self.children.iter().filter_map(XmlNode::as_element)
+    }
+
+    /// The attribute called `name`
+    pub fn attribute(&self, name: &str) -> Option<&XmlAttribute> {
+        self.attributes
+            .iter()
+            .find(|attribute| attribute.name == name)
+    }
+
+    /// The rendered value of the attribute called `name`
+    pub fn attribute_value(&self, name: &str) -> Option<String> {
+        self.attribute(name).map(XmlAttribute::text)
+    }
+
+    /// The text content of the element, without markup. Only meaningful on an
+    /// expanded tree.
+    pub fn text(&self) -> String {
+        let mut out = String::new();
+        text(&self.children, &mut out, 0);
+        out
+    }
+}
+
+impl XmlAttribute {
+    /// The rendered value. Only meaningful on an expanded tree.
+    pub fn text(&self) -> String {
+        let mut out = String::new();
+        text(&self.value, &mut out, 0);
+        out
+    }
+}
+
+/// Appends the text of `nodes`. Elements nested deeper than `MAX_DEPTH` are left
+/// out rather than recursed into.
+fn text(nodes: &[XmlNode], out: &mut String, depth: usize) {
+    for node in nodes {
+        match node {
+            XmlNode::Element(element) if depth < MAX_DEPTH => {
+                text(&element.children, out, depth + 1)
+            }
+            XmlNode::Text(s) | XmlNode::CData(s) => out.push_str(s),
+            XmlNode::Value(value) => out.push_str(&value.to_string()),
+            XmlNode::CharRef(c) => out.extend(char::from_u32(*c as u32)),
+            _ => {}
+        }
+    }
+}
+
+fn too_deep(depth: usize) -> Result<(), String> {
+    if depth > MAX_DEPTH {
+        return Err("BinXml nesting too deep to render".to_owned());
+    }
+    Ok(())
+}
+
+fn substitution(values: &[XmlValue], index: u16) -> Result<&XmlValue, String> {
+    values
+        .get(index as usize)
+        .ok_or_else(|| format!("Substitution {index} out of range"))
+}
+
+/// The value to render for a substitution, or `None` to leave it out: when it is
+/// optional and empty or, if `lossy`, when there is no value for it
+fn rendered_substitution(
+    values: &[XmlValue],
+    index: u16,
+    optional: bool,
+    lossy: bool,
+) -> Result<Option<&XmlValue>, String> {
+    let value = match substitution(values, index) {
+        Ok(value) => value,
+        Err(_) if lossy => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    Ok((!(optional && value.is_empty())).then_some(value))
+}
+
+fn expand_into(
+    nodes: &[XmlNode],
+    values: &[XmlValue],
+    out: &mut Vec<XmlNode>,
+    depth: usize,
+) -> Result<(), String> {
+    too_deep(depth)?;
+    for node in nodes {
+        match node {
+            XmlNode::Element(element) => {
+                let mut attributes = Vec::with_capacity(element.attributes.len());
+                for attribute in element.attributes.iter() {
+                    let mut value = Vec::with_capacity(attribute.value.len());
+                    expand_into(&attribute.value, values, &mut value, depth + 1)?;
+                    if !value.is_empty() {
+                        attributes.push(XmlAttribute {
+                            name: attribute.name.clone(),
+                            value,
+                        });
+                    }
+                }
+                let mut children = Vec::with_capacity(element.children.len());
+                expand_into(&element.children, values, &mut children, depth + 1)?;
+                out.push(XmlNode::Element(XmlElement {
+                    name: element.name.clone(),
+                    attributes,
+                    children,
+                }));
+            }
+            XmlNode::Substitution { index, optional } => {
+                let value = substitution(values, *index)?;
+                if *optional && value.is_empty() {
+                    continue;
+                }
+                match value {
+                    XmlValue::BinXml(nodes) => expand_into(nodes, &[], out, depth + 1)?,
+                    value => out.push(XmlNode::Value(value.clone())),
+                }
+            }
+            XmlNode::TemplateInstance { template, values } => {
+                expand_into(&template.nodes, values, out, depth + 1)?;
+            }
+            node => out.push(node.clone()),
+        }
+    }
+    Ok(())
+}
+
+/// Renders `nodes` as XML text, filling substitutions from `values`
+pub fn render(nodes: &[XmlNode], values: &[XmlValue], out: &mut String) -> Result<(), String> {
+    render_at(nodes, values, out, 0, false)
+}
+
+/// Renders `nodes` without failing, for `Display`: substitutions without a value
+/// are left out, and so are elements nested deeper than `MAX_DEPTH`
+pub(crate) fn render_lossy(nodes: &[XmlNode], out: &mut String) {
+    // both of the errors `render` reports are tolerated when lossy
+    let _ = render_at(nodes, &[], out, 0, true);
+}
+
+fn render_at(
+    nodes: &[XmlNode],
+    values: &[XmlValue],
+    out: &mut String,
+    depth: usize,
+    lossy: bool,
+) -> Result<(), String> {
+    if lossy && depth > MAX_DEPTH {
+        return Ok(());
+    }
+    too_deep(depth)?;
+    for node in nodes {
+        match node {
+            XmlNode::Element(element) => {
+                out.push('<');
+                out.push_str(&element.name);
+                for attribute in element.attributes.iter() {
+                    let mut value = String::new();
+                    if render_attribute(&attribute.value, values, &mut value, depth + 1, lossy)? {
+                        out.push(' ');
+                        out.push_str(&attribute.name);
+                        out.push_str("='");
+                        out.push_str(&value);
+                        out.push('\'');
+                    }
+                }
+                out.push('>');
+                let start = out.len();
+                render_at(&element.children, values, out, depth + 1, lossy)?;
+                if out.len() == start {
+                    out.pop();
+                    out.push_str("/>");
+                } else {
+                    out.push_str("</");
+                    out.push_str(&element.name);
+                    out.push('>');
+                }
+            }
+            XmlNode::Text(s) => escape_xml(s, out),
+            XmlNode::Value(value) => render_value(value, out, depth + 1, lossy)?,
+            XmlNode::CData(s) => {
+                out.push_str("<![CDATA[");
+                out.push_str(s);
+                out.push_str("]]>");
+            }
+            XmlNode::CharRef(c) => out.push_str(&format!("&#{c};")),
+            XmlNode::EntityRef(name) => {
+                out.push('&');
+                out.push_str(name);
+                out.push(';');
+            }
+            XmlNode::ProcessingInstruction { target, data } => {
+                out.push_str("<?");
+                out.push_str(target);
+                if !data.is_empty() {
+                    out.push(' ');
+                    out.push_str(data);
+                }
+                out.push_str("?>");
+            }
+            XmlNode::Substitution { index, optional } => {
+                if let Some(value) = rendered_substitution(values, *index, *optional, lossy)? {
+                    render_value(value, out, depth + 1, lossy)?;
+                }
+            }
+            XmlNode::TemplateInstance { template, values } => {
+                render_at(&template.nodes, values, out, depth + 1, lossy)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Renders an attribute value. Returns `false` when it consists only of optional
+/// substitutions without a value, in which case the attribute is left out.
+fn render_attribute(
+    nodes: &[XmlNode],
+    values: &[XmlValue],
+    out: &mut String,
+    depth: usize,
+    lossy: bool,
+) -> Result<bool, String> {
+    let mut present = false;
+    for node in nodes {
+        present |= match node {
+            XmlNode::Substitution { index, optional } => {
+                rendered_substitution(values, *index, *optional, lossy)?.is_some()
+            }
+            _ => true,
+        };
+    }
+    if present {
+        render_at(nodes, values, out, depth, lossy)?;
+    }
+    Ok(present)
+}
+
+fn render_value(
+    value: &XmlValue,
+    out: &mut String,
+    depth: usize,
+    lossy: bool,
+) -> Result<(), String> {
+    match value {
+        XmlValue::BinXml(nodes) => render_at(nodes, &[], out, depth, lossy),
+        value => {
+            escape_xml(&value.to_string(), out);
+            Ok(())
+        }
+    }
+}
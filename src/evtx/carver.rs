@@ -0,0 +1,311 @@
+//! Carving of event records from raw data such as disk images, memory dumps and
+//! pagefiles
+//!
+//! The stream is scanned for `ElfChnk` chunk signatures and `**` record
+//! signatures. A chunk whose header checksum holds is decoded as a whole. Records
+//! found anywhere else, including the slack after a chunk's last record, are
+//! checked by their size fields and decoded on their own.
+
+use crate::event::Event;
+use crate::evtx::binxml::{Decoder, TemplateCache};
+use crate::evtx::chunk::{
+    self, Chunk, ChunkHeader, CHUNK_HEADER_SIZE, CHUNK_SIGNATURE, CHUNK_SIZE,
+};
+use crate::evtx::cursor::Cursor;
+use crate::evtx::record::{RecordHeader, RECORD_HEADER_SIZE, RECORD_SIGNATURE};
+use crate::evtx::recovery::{self, Diagnostics};
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use std::path::Path;
+
+/// Chunks of files on disk, and pages in memory, start on a 4 KiB boundary
+const ALIGNMENT: u64 = 0x1000;
+
+/// An event recovered by the `Carver`
+#[derive(Clone, Debug)]
+pub struct CarvedEvent {
+    /// Offset of the record in the stream
+    pub offset: u64,
+    /// Offset of the chunk holding the record, when the chunk header was intact
+    pub chunk_offset: Option<u64>,
+    pub record_id: u64,
+    /// Time the record was written, as a FILETIME
+    pub written: u64,
+    pub event: Event,
+}
+
+/// Scans an arbitrary byte stream for event records
+///
+/// ```rust,no_run
+/// use win_event_log::prelude::*;
+///
+/// for carved in Carver::open("disk.img").unwrap() {
+///     let carved = carved.unwrap();
+///     println!("{:#x}: {}", carved.offset, carved.event);
+/// }
+/// ```
+pub struct Carver<R> {
+    reader: R,
+    /// Data read so far that may still be needed, starting at `buf_start`
+    buf: Vec<u8>,
+    buf_start: u64,
+    /// Scan position within `buf`
+    pos: usize,
+    eof: bool,
+    pending: VecDeque<CarvedEvent>,
+}
+
+impl Carver<File> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Carver<File>, String> {
+        let path = path.as_ref();
+        let file =
+            File::open(path).map_err(|err| format!("Could not open {}: {err}", path.display()))?;
+        Ok(Carver::new(file))
+    }
+}
+
+impl<R: Read> Carver<R> {
+    pub fn new(reader: R) -> Carver<R> {
+        Carver {
+            reader,
+            buf: Vec::new(),
+            buf_start: 0,
+            pos: 0,
+            eof: false,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Keeps one chunk of data behind the scan position, so records can refer to
+    /// templates earlier in their chunk, and two chunks ahead of it
+    fn fill(&mut self) -> Result<(), String> {
+        if self.pos > 2 * CHUNK_SIZE {
+            let consumed = self.pos - CHUNK_SIZE;
+            self.buf.drain(..consumed);
+            self.buf_start += consumed as u64;
+            self.pos -= consumed;
+        }
+        while !self.eof && self.buf.len() < self.pos + 2 * CHUNK_SIZE {
+            let len = self.buf.len();
+            self.buf.resize(len + CHUNK_SIZE, 0);
+            match self.reader.read(&mut self.buf[len..]) {
+                Ok(read) => {
+                    self.buf.truncate(len + read);
+                    self.eof = read == 0;
+                }
+                Err(err) if err.kind() == ErrorKind::Interrupted => self.buf.truncate(len),
+                Err(err) => {
+                    self.buf.truncate(len);
+                    return Err(format!(
+                        "Could not read at offset {:#x}: {err}",
+                        self.buf_start + len as u64
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Decodes the chunk at `at` if its header checksum holds, returning the
+    /// offset of its free space
+    fn carve_chunk(&mut self, at: usize) -> Option<usize> {
+        let end = (at + CHUNK_SIZE).min(self.buf.len());
+        let mut data = self.buf[at..end].to_vec();
+        data.resize(CHUNK_SIZE, 0);
+        let header = ChunkHeader::parse(&data).ok()?;
+        let strings = chunk::read_strings(&data, &header).unwrap_or_default();
+        let chunk = Chunk::from_parts(header, data, strings);
+        if chunk.header_checksum() != chunk.header.header_checksum {
+            return None;
+        }
+        let offset = self.buf_start + at as u64;
+        let records = recovery::recover_records(&chunk, offset, &Diagnostics::none());
+        self.pending
+            .extend(records.into_iter().map(|(record, event)| CarvedEvent {
+                offset: offset + record.offset as u64,
+                chunk_offset: Some(offset),
+                record_id: record.record_id,
+                written: record.written,
+                event,
+            }));
+        Some(chunk.header.free_space_offset as usize)
+    }
+
+    /// Decodes a record found outside an intact chunk, returning its size
+    fn carve_record(&mut self, at: usize) -> Option<usize> {
+        let limit = (at + CHUNK_SIZE).min(self.buf.len());
+        let record = RecordHeader::parse(&self.buf, at, limit).ok()?;
+        let end = at + record.size as usize;
+        let event = self.chunk_starts(at, end).into_iter().find_map(|base| {
+            let record = RecordHeader {
+                offset: at - base,
+                ..record.clone()
+            };
+            let strings = HashMap::new();
+            let templates = TemplateCache::new();
+            Decoder::new(&self.buf[base..end - 4], &strings, &templates)
+                .render(record.data_range().start)
+                .ok()
+        })?;
+        self.pending.push_back(CarvedEvent {
+            offset: self.buf_start + at as u64,
+            chunk_offset: None,
+            record_id: record.record_id,
+            written: record.written,
+            event: Event(event),
+        });
+        Some(record.size as usize)
+    }
+
+    /// Where the chunk holding the record at `at` may start. Offsets in BinXml are
+    /// relative to the chunk, so a name or template defined inside the record
+    /// gives its chunk away; failing that, aligned offsets are tried nearest first.
+    fn chunk_starts(&self, at: usize, end: usize) -> Vec<usize> {
+        let fits = |base: usize| at - base >= CHUNK_HEADER_SIZE && end - base <= CHUNK_SIZE;
+        let mut starts = Vec::new();
+        if let Some(base) = inline_chunk_start(&self.buf, at + RECORD_HEADER_SIZE) {
+            if base <= at && fits(base) {
+                starts.push(base);
+            }
+        }
+        let latest = match at.checked_sub(CHUNK_HEADER_SIZE) {
+            Some(latest) => self.buf_start + latest as u64,
+            None => return starts,
+        };
+        let mut start = latest - latest % ALIGNMENT;
+        while start >= self.buf_start && fits((start - self.buf_start) as usize) {
+            let base = (start - self.buf_start) as usize;
+            if !starts.contains(&base) {
+                starts.push(base);
+            }
+            match start.checked_sub(ALIGNMENT) {
+                Some(previous) => start = previous,
+                None => break,
+            }
+        }
+        starts
+    }
+}
+
+/// Works out where a chunk starts from the first template or element of the
+/// record data at `data`, if it is defined inline
+fn inline_chunk_start(buf: &[u8], data: usize) -> Option<usize> {
+    let mut cursor = Cursor::new(buf, data);
+    if cursor.bytes(4).ok()? != [0x0f, 0x01, 0x01, 0x00] {
+        return None;
+    }
+    let (field, inline) = match cursor.u8().ok()? & 0xbf {
+        // template instance: token, version, template id, definition offset
+        0x0c => (data + 10, data + 14),
+        // element: token, dependency id, size, name offset
+        0x01 => (data + 11, data + 15),
+        _ => return None,
+    };
+    let offset = Cursor::new(buf, field).u32().ok()? as usize;
+    inline.checked_sub(offset)
+}
+
+impl<R: Read> Iterator for Carver<R> {
+    type Item = Result<CarvedEvent, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(Ok(event));
+            }
+            if let Err(err) = self.fill() {
+                self.eof = true;
+                self.pos = self.buf.len();
+                return Some(Err(err));
+            }
+            if self.pos >= self.buf.len() {
+                return None;
+            }
+            // leave a chunk of lookahead for whatever is found before `limit`
+            let limit = match self.eof {
+                true => self.buf.len(),
+                false => self.buf.len() - CHUNK_SIZE,
+            };
+            let found = (self.pos..limit).find(|i| {
+                self.buf[*i..].starts_with(RECORD_SIGNATURE)
+                    || self.buf[*i..].starts_with(CHUNK_SIGNATURE)
+            });
+            let at = match found {
+                Some(at) => at,
+                None => {
+                    self.pos = limit;
+                    continue;
+                }
+            };
+            let carved = if self.buf[at] == CHUNK_SIGNATURE[0] {
+                self.carve_chunk(at)
+            } else {
+                self.carve_record(at)
+            };
+            self.pos = at + carved.unwrap_or(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Carver;
+    use crate::evtx::header::FILE_HEADER_SIZE;
+    use crate::evtx::testdata::{self, SAMPLE_EVENTS};
+
+    /// Hands out data a few bytes at a time, like a slow device
+    struct Trickle<'a>(&'a [u8]);
+
+    impl std::io::Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(self.0.len()).min(1000);
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn carve_chunks_from_image() {
+        let junk = 150_001;
+        let mut image = vec![0x2a; junk];
+        image.extend(testdata::sample_file());
+        image.extend(vec![0x45; 1000]);
+        let carved = Carver::new(Trickle(&image))
+            .map(|carved| carved.unwrap())
+            .collect::<Vec<_>>();
+        let chunk = (junk + FILE_HEADER_SIZE) as u64;
+        assert_eq!(
+            carved
+                .iter()
+                .map(|carved| carved.event.to_string())
+                .collect::<Vec<String>>(),
+            SAMPLE_EVENTS
+        );
+        assert_eq!(carved[0].chunk_offset, Some(chunk));
+        assert_eq!(carved[0].offset, chunk + 512);
+        assert_eq!(carved[2].record_id, 3);
+    }
+
+    #[test]
+    fn carve_records_without_chunk_header() {
+        let mut chunk = testdata::sample_chunk(7);
+        chunk[..8].copy_from_slice(&[0; 8]);
+        let mut image = vec![0u8; 0x3000];
+        image.extend(chunk);
+        let carved = Carver::new(image.as_slice())
+            .map(|carved| carved.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            carved
+                .iter()
+                .map(|carved| carved.event.to_string())
+                .collect::<Vec<String>>(),
+            SAMPLE_EVENTS
+        );
+        assert!(carved.iter().all(|carved| carved.chunk_offset.is_none()));
+        assert_eq!(carved[1].record_id, 8);
+        assert!(carved[1].offset > 0x3000 + 512);
+    }
+}
@@ -0,0 +1,299 @@
+use crate::event::Event;
+use crate::evtx::binxml::{read_name, Decoder, TemplateCache, XmlNode};
+use crate::evtx::crc32::{self, Crc32};
+use crate::evtx::cursor::Cursor;
+use crate::evtx::record::RecordHeader;
+use std::collections::HashMap;
+
+/// Signature at the start of every chunk
+pub const CHUNK_SIGNATURE: &[u8; 8] = b"ElfChnk\0";
+/// Every chunk occupies exactly 64 KiB of the file
+pub const CHUNK_SIZE: usize = 0x10000;
+/// Size of the chunk header, including the string and template tables
+pub const CHUNK_HEADER_SIZE: usize = 512;
+/// Number of buckets in the common string table
+const STRING_TABLE_SIZE: usize = 64;
+/// Number of buckets in the template pointer table
+const TEMPLATE_TABLE_SIZE: usize = 32;
+
+/// The `ElfChnk` header at the start of each chunk
+#[derive(Clone, Debug)]
+pub struct ChunkHeader {
+    pub first_record_number: u64,
+    pub last_record_number: u64,
+    pub first_record_id: u64,
+    pub last_record_id: u64,
+    pub header_size: u32,
+    /// Offset of the last record in the chunk
+    pub last_record_offset: u32,
+    /// Offset just past the last record
+    pub free_space_offset: u32,
+    pub records_checksum: u32,
+    pub flags: u32,
+    pub header_checksum: u32,
+    /// Heads of the common string hash chains
+    pub string_offsets: Vec<u32>,
+    /// Heads of the template definition hash chains
+    pub template_offsets: Vec<u32>,
+}
+
+impl ChunkHeader {
+    pub fn parse(data: &[u8]) -> Result<ChunkHeader, String> {
+        if data.len() < CHUNK_HEADER_SIZE {
+            return Err(format!(
+                "Chunk header is truncated: {} of {CHUNK_HEADER_SIZE} bytes",
+                data.len()
+            ));
+        }
+        if &data[..8] != CHUNK_SIGNATURE {
+            return Err("Missing ElfChnk signature".to_owned());
+        }
+        let mut cursor = Cursor::new(data, 8);
+        let first_record_number = cursor.u64()?;
+        let last_record_number = cursor.u64()?;
+        let first_record_id = cursor.u64()?;
+        let last_record_id = cursor.u64()?;
+        let header_size = cursor.u32()?;
+        let last_record_offset = cursor.u32()?;
+        let free_space_offset = cursor.u32()?;
+        let records_checksum = cursor.u32()?;
+        cursor.seek(120);
+        let flags = cursor.u32()?;
+        let header_checksum = cursor.u32()?;
+        let string_offsets = (0..STRING_TABLE_SIZE)
+            .map(|_| cursor.u32())
+            .collect::<Result<Vec<u32>, String>>()?;
+        let template_offsets = (0..TEMPLATE_TABLE_SIZE)
+            .map(|_| cursor.u32())
+            .collect::<Result<Vec<u32>, String>>()?;
+
+        if (free_space_offset as usize) < CHUNK_HEADER_SIZE
+            || free_space_offset as usize > CHUNK_SIZE
+        {
+            return Err(format!(
+                "Chunk free space offset {free_space_offset:#x} is out of range"
+            ));
+        }
+
+        Ok(ChunkHeader {
+            first_record_number,
+            last_record_number,
+            first_record_id,
+            last_record_id,
+            header_size,
+            last_record_offset,
+            free_space_offset,
+            records_checksum,
+            flags,
+            header_checksum,
+            string_offsets,
+            template_offsets,
+        })
+    }
+}
+
+/// A template definition found through the chunk's template table
+#[derive(Clone, Debug)]
+pub struct TemplateDefinition {
+    /// Offset of the definition within the chunk
+    pub offset: u32,
+    pub guid: [u8; 16],
+    /// Size of the BinXml fragment following the definition header
+    pub data_size: u32,
+}
+
+/// One 64 KiB chunk of an `.evtx` file, holding a run of event records
+pub struct Chunk {
+    pub header: ChunkHeader,
+    data: Vec<u8>,
+    strings: HashMap<u32, String>,
+    templates: TemplateCache,
+}
+
+impl Chunk {
+    /// Parses a chunk from its raw bytes, reading the header and string table
+    pub fn parse(data: Vec<u8>) -> Result<Chunk, String> {
+        let header = ChunkHeader::parse(&data)?;
+        if header.free_space_offset as usize > data.len() {
+            return Err(format!(
+                "Chunk is truncated: free space starts at {:#x}, after its {:#x} bytes",
+                header.free_space_offset,
+                data.len()
+            ));
+        }
+        let strings = read_strings(&data, &header)?;
+        Ok(Chunk::from_parts(header, data, strings))
+    }
+
+    /// Assembles a chunk from parts that were read separately, for recovering
+    /// chunks whose header or string table is damaged
+    pub(crate) fn from_parts(
+        header: ChunkHeader,
+        data: Vec<u8>,
+        strings: HashMap<u32, String>,
+    ) -> Chunk {
+        Chunk {
+            header,
+            data,
+            strings,
+            templates: TemplateCache::new(),
+        }
+    }
+
+    /// Raw bytes of the chunk
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Checks the header checksum and the checksum over the record data
+    pub fn verify(&self) -> Result<(), String> {
+        let header = self.header_checksum();
+        if header != self.header.header_checksum {
+            return Err(format!(
+                "Chunk header checksum mismatch: stored {:#010x}, computed {header:#010x}",
+                self.header.header_checksum
+            ));
+        }
+        let records = self.records_checksum();
+        if records != self.header.records_checksum {
+            return Err(format!(
+                "Chunk records checksum mismatch: stored {:#010x}, computed {records:#010x}",
+                self.header.records_checksum
+            ));
+        }
+        Ok(())
+    }
+
+    /// CRC32 over the header, skipping the flags and checksum fields
+    pub fn header_checksum(&self) -> u32 {
+        Crc32::new()
+            .update(&self.data[..120])
+            .update(&self.data[128..CHUNK_HEADER_SIZE])
+            .finish()
+    }
+
+    /// CRC32 over the record data, from the end of the header to the free space
+    pub fn records_checksum(&self) -> u32 {
+        crc32::checksum(&self.data[CHUNK_HEADER_SIZE..self.header.free_space_offset as usize])
+    }
+
+    /// Common strings from the string table, keyed by offset
+    pub fn strings(&self) -> &HashMap<u32, String> {
+        &self.strings
+    }
+
+    /// Templates decoded so far while reading records
+    pub fn template_cache(&self) -> &TemplateCache {
+        &self.templates
+    }
+
+    /// Template definitions reachable from the template table
+    pub fn templates(&self) -> Result<Vec<TemplateDefinition>, String> {
+        let mut templates: Vec<TemplateDefinition> = Vec::new();
+        for head in self.header.template_offsets.iter() {
+            let mut offset = *head;
+            while offset != 0 && !templates.iter().any(|t| t.offset == offset) {
+                let mut cursor = Cursor::new(&self.data, offset as usize);
+                let next = cursor.u32()?;
+                let mut guid = [0u8; 16];
+                guid.copy_from_slice(cursor.bytes(16)?);
+                let data_size = cursor.u32()?;
+                templates.push(TemplateDefinition {
+                    offset,
+                    guid,
+                    data_size,
+                });
+                offset = next;
+            }
+        }
+        Ok(templates)
+    }
+
+    /// Iterates over the record headers in the chunk
+    pub fn records(&self) -> ChunkRecords<'_> {
+        ChunkRecords {
+            chunk: self,
+            offset: CHUNK_HEADER_SIZE,
+            failed: false,
+        }
+    }
+
+    /// Decodes a record's event data. The nodes still refer to the templates the
+    /// record uses; `XmlNode::expand` resolves them into a plain tree.
+    pub fn decode(&self, record: &RecordHeader) -> Result<Vec<XmlNode>, String> {
+        self.decoder(record)
+            .decode(record.data_range().start)
+            .map_err(|err| format!("Record {}: {err}", record.record_id))
+    }
+
+    /// Renders a record's event data as XML
+    pub fn render(&self, record: &RecordHeader) -> Result<String, String> {
+        self.decoder(record)
+            .render(record.data_range().start)
+            .map_err(|err| format!("Record {}: {err}", record.record_id))
+    }
+
+    fn decoder(&self, record: &RecordHeader) -> Decoder<'_> {
+        Decoder::new(
+            &self.data[..record.data_range().end],
+            &self.strings,
+            &self.templates,
+        )
+    }
+
+    /// Decodes every record in the chunk
+    pub fn events(&self) -> Vec<Result<Event, String>> {
+        self.records()
+            .map(|record| record.and_then(|record| self.render(&record).map(Event)))
+            .collect()
+    }
+}
+
+/// Reads the common strings reachable from the string table, keyed by offset
+pub(crate) fn read_strings(
+    data: &[u8],
+    header: &ChunkHeader,
+) -> Result<HashMap<u32, String>, String> {
+    let mut strings = HashMap::new();
+    for head in header.string_offsets.iter() {
+        let mut offset = *head;
+        while offset != 0 {
+            if strings.contains_key(&offset) {
+                break;
+            }
+            let next = Cursor::new(data, offset as usize).u32()?;
+            strings.insert(offset, read_name(data, offset)?);
+            offset = next;
+        }
+    }
+    Ok(strings)
+}
+
+/// Iterator over the records of a `Chunk`. A malformed record ends the iteration,
+/// since the position of the following record cannot be known.
+pub struct ChunkRecords<'a> {
+    chunk: &'a Chunk,
+    offset: usize,
+    failed: bool,
+}
+
+impl Iterator for ChunkRecords<'_> {
+    type Item = Result<RecordHeader, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let limit = self.chunk.header.free_space_offset as usize;
+        if self.failed || self.offset >= limit {
+            return None;
+        }
+        match RecordHeader::parse(&self.chunk.data, self.offset, limit) {
+            Ok(record) => {
+                self.offset += record.size as usize;
+                Some(Ok(record))
+            }
+            Err(err) => {
+                self.failed = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
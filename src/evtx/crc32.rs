@@ -0,0 +1,65 @@
+//! CRC32 (IEEE 802.3, reflected) as used by the `.evtx` file and chunk checksums
+
+const POLYNOMIAL: u32 = 0xedb8_8320;
+
+const TABLE: [u32; 256] = build_table();
+
+const fn build_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Incremental CRC32, for checksums computed over several disjoint ranges
+#[derive(Clone, Copy)]
+pub struct Crc32(u32);
+
+impl Crc32 {
+    pub fn new() -> Crc32 {
+        Crc32(0xffff_ffff)
+    }
+
+    pub fn update(mut self, data: &[u8]) -> Crc32 {
+        for byte in data {
+            self.0 = TABLE[((self.0 ^ *byte as u32) & 0xff) as usize] ^ (self.0 >> 8);
+        }
+        self
+    }
+
+    pub fn finish(self) -> u32 {
+        !self.0
+    }
+}
+
+/// CRC32 of a single buffer
+pub fn checksum(data: &[u8]) -> u32 {
+    Crc32::new().update(data).finish()
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn known_values() {
+        use super::{checksum, Crc32};
+        assert_eq!(checksum(b""), 0);
+        assert_eq!(checksum(b"123456789"), 0xcbf4_3926);
+        assert_eq!(
+            Crc32::new().update(b"1234").update(b"56789").finish(),
+            0xcbf4_3926
+        );
+    }
+}
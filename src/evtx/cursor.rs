@@ -0,0 +1,84 @@
+//! Bounds-checked little-endian reads over a byte buffer
+
+/// A position within a buffer. All reads are checked, so malformed input produces an
+/// `Err` describing the offset instead of a panic.
+#[derive(Clone)]
+pub struct Cursor<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    pub fn new(buf: &'a [u8], pos: usize) -> Cursor<'a> {
+        Cursor { buf, pos }
+    }
+
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub fn seek(&mut self, pos: usize) {
+        self.pos = pos;
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        match self.pos.checked_add(len) {
+            Some(end) if end <= self.buf.len() => {
+                let bytes = &self.buf[self.pos..end];
+                self.pos = end;
+                Ok(bytes)
+            }
+            _ => Err(format!(
+                "Unexpected end of data reading {len} bytes at offset {:#x}",
+                self.pos
+            )),
+        }
+    }
+
+    pub fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, String> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, String> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, String> {
+        let b = self.bytes(8)?;
+        let mut le = [0u8; 8];
+        le.copy_from_slice(b);
+        Ok(u64::from_le_bytes(le))
+    }
+
+    /// Reads `chars` UTF-16LE code units
+    pub fn utf16(&mut self, chars: usize) -> Result<String, String> {
+        Ok(utf16_to_string(self.bytes(chars * 2)?))
+    }
+
+    /// Reads a NUL-terminated UTF-16LE string, consuming the terminator
+    pub fn utf16z(&mut self) -> Result<String, String> {
+        let mut units = Vec::new();
+        loop {
+            match self.u16()? {
+                0 => return Ok(String::from_utf16_lossy(&units)),
+                unit => units.push(unit),
+            }
+        }
+    }
+}
+
+/// Decodes UTF-16LE bytes, stopping at the first NUL
+pub fn utf16_to_string(bytes: &[u8]) -> String {
+    let units = bytes
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .take_while(|c| *c != 0)
+        .collect::<Vec<u16>>();
+    String::from_utf16_lossy(&units)
+}
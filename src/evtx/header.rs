@@ -0,0 +1,110 @@
+use crate::evtx::crc32;
+use crate::evtx::cursor::Cursor;
+
+/// Signature at the start of every `.evtx` file
+pub const FILE_SIGNATURE: &[u8; 8] = b"ElfFile\0";
+/// Size of the file header block, chunks start right after it
+pub const FILE_HEADER_SIZE: usize = 4096;
+/// Number of header bytes covered by the header checksum
+const FILE_HEADER_CHECKSUM_LEN: usize = 120;
+
+bitflags! {
+    /// Flags stored in the file header
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct FileFlags: u32 {
+        /// The file was not closed cleanly
+        const Dirty = 0x1;
+        /// The log reached its maximum size
+        const Full = 0x2;
+    }
+}
+
+/// The `ElfFile` header at the start of an `.evtx` file
+#[derive(Clone, Debug)]
+pub struct FileHeader {
+    pub first_chunk_number: u64,
+    pub last_chunk_number: u64,
+    pub next_record_id: u64,
+    pub header_size: u32,
+    pub minor_version: u16,
+    pub major_version: u16,
+    pub header_block_size: u16,
+    pub chunk_count: u16,
+    pub flags: FileFlags,
+    pub checksum: u32,
+    /// Checksum computed over the header as read
+    pub computed_checksum: u32,
+}
+
+impl FileHeader {
+    /// Parses the header from the first 4096 bytes of a file. Structural problems
+    /// are errors; use `verify` to check the checksum.
+    pub fn parse(data: &[u8]) -> Result<FileHeader, String> {
+        if data.len() < FILE_HEADER_SIZE {
+            return Err(format!(
+                "File header is truncated: {} of {FILE_HEADER_SIZE} bytes",
+                data.len()
+            ));
+        }
+        if &data[..8] != FILE_SIGNATURE {
+            return Err("Not an evtx file: missing ElfFile signature".to_owned());
+        }
+        let mut cursor = Cursor::new(data, 8);
+        let first_chunk_number = cursor.u64()?;
+        let last_chunk_number = cursor.u64()?;
+        let next_record_id = cursor.u64()?;
+        let header_size = cursor.u32()?;
+        let minor_version = cursor.u16()?;
+        let major_version = cursor.u16()?;
+        let header_block_size = cursor.u16()?;
+        let chunk_count = cursor.u16()?;
+        cursor.seek(FILE_HEADER_CHECKSUM_LEN);
+        let flags = FileFlags::from_bits_retain(cursor.u32()?);
+        let checksum = cursor.u32()?;
+
+        if major_version != 3 {
+            return Err(format!(
+                "Unsupported evtx version {major_version}.{minor_version}"
+            ));
+        }
+        if header_block_size as usize != FILE_HEADER_SIZE {
+            return Err(format!(
+                "Unexpected evtx header block size {header_block_size}"
+            ));
+        }
+
+        Ok(FileHeader {
+            first_chunk_number,
+            last_chunk_number,
+            next_record_id,
+            header_size,
+            minor_version,
+            major_version,
+            header_block_size,
+            chunk_count,
+            flags,
+            checksum,
+            computed_checksum: crc32::checksum(&data[..FILE_HEADER_CHECKSUM_LEN]),
+        })
+    }
+
+    /// Checks the stored header checksum
+    pub fn verify(&self) -> Result<(), String> {
+        if self.checksum == self.computed_checksum {
+            Ok(())
+        } else {
+            Err(format!(
+                "File header checksum mismatch: stored {:#010x}, computed {:#010x}",
+                self.checksum, self.computed_checksum
+            ))
+        }
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.flags.contains(FileFlags::Dirty)
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.flags.contains(FileFlags::Full)
+    }
+}
@@ -0,0 +1,489 @@
+//! Random access into `.evtx` files by record ID and time
+//!
+//! `EvtxIndex` summarises every chunk: its range of record IDs and the earliest
+//! and latest time a record in it was written. Saved next to the log as a sidecar
+//! file, it lets `EvtxReader::seek_record` and `EvtxReader::seek_time` go straight
+//! to the right chunk. Without one they binary search the chunk headers instead.
+
+use crate::evtx::chunk::{ChunkHeader, CHUNK_HEADER_SIZE};
+use crate::evtx::header::FileHeader;
+use crate::evtx::record::{RecordHeader, RECORD_HEADER_SIZE, RECORD_SIGNATURE};
+use crate::evtx::{chunk_offset, EvtxReader};
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const INDEX_SIGNATURE: &[u8; 8] = b"EvtxIdx\0";
+const INDEX_VERSION: u32 = 1;
+/// Extension appended to the log's file name for the sidecar
+const SIDECAR_EXTENSION: &str = "idx";
+
+/// What `EvtxIndex` knows about one chunk
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChunkSummary {
+    pub first_record_id: u64,
+    pub last_record_id: u64,
+    /// Earliest time a record in the chunk was written, as a FILETIME
+    pub first_written: u64,
+    /// Latest time a record in the chunk was written, as a FILETIME
+    pub last_written: u64,
+}
+
+/// Per-chunk record ID and time ranges of one `.evtx` file
+///
+/// ```rust,no_run
+/// use win_event_log::prelude::*;
+///
+/// let mut reader = EvtxReader::open("Security.evtx").unwrap();
+/// let index = EvtxIndex::build(&mut reader).unwrap();
+/// index.save(EvtxIndex::sidecar_path("Security.evtx")).unwrap();
+///
+/// // later opens pick the sidecar up
+/// let mut reader = EvtxReader::open("Security.evtx").unwrap();
+/// reader.seek_record(123_456).unwrap();
+/// let event = reader.events().next();
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EvtxIndex {
+    /// Checksum of the file header the index was built from, to notice when the
+    /// log has changed since
+    header_checksum: u32,
+    next_record_id: u64,
+    chunks: Vec<ChunkSummary>,
+}
+
+impl EvtxIndex {
+    /// Reads the record headers of every chunk
+    pub fn build<R: Read + Seek>(reader: &mut EvtxReader<R>) -> Result<EvtxIndex, String> {
+        let mut chunks = Vec::with_capacity(reader.chunk_count as usize);
+        for chunk in reader.chunks() {
+            let chunk = chunk?;
+            let mut first_written = u64::MAX;
+            let mut last_written = 0;
+            for record in chunk.records() {
+                let record = record?;
+                first_written = first_written.min(record.written);
+                last_written = last_written.max(record.written);
+            }
+            chunks.push(ChunkSummary {
+                first_record_id: chunk.header.first_record_id,
+                last_record_id: chunk.header.last_record_id,
+                first_written: first_written.min(last_written),
+                last_written,
+            });
+        }
+        Ok(EvtxIndex {
+            header_checksum: reader.header.checksum,
+            next_record_id: reader.header.next_record_id,
+            chunks,
+        })
+    }
+
+    pub fn chunks(&self) -> &[ChunkSummary] {
+        &self.chunks
+    }
+
+    /// Where the sidecar for the log at `path` is kept: `Security.evtx.idx` for
+    /// `Security.evtx`
+    pub fn sidecar_path<P: AsRef<Path>>(path: P) -> PathBuf {
+        let mut name = OsString::from(path.as_ref().as_os_str());
+        name.push(".");
+        name.push(SIDECAR_EXTENSION);
+        PathBuf::from(name)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let file = File::create(path)
+            .map_err(|err| format!("Could not create {}: {err}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        self.write_to(&mut writer)
+            .and_then(|_| writer.flush())
+            .map_err(|err| format!("Could not write {}: {err}", path.display()))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<EvtxIndex, String> {
+        let path = path.as_ref();
+        let file =
+            File::open(path).map_err(|err| format!("Could not open {}: {err}", path.display()))?;
+        EvtxIndex::read_from(BufReader::new(file))
+            .map_err(|err| format!("Could not read {}: {err}", path.display()))
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        writer.write_all(INDEX_SIGNATURE)?;
+        writer.write_all(&INDEX_VERSION.to_le_bytes())?;
+        writer.write_all(&self.header_checksum.to_le_bytes())?;
+        writer.write_all(&self.next_record_id.to_le_bytes())?;
+        writer.write_all(&(self.chunks.len() as u32).to_le_bytes())?;
+        for chunk in self.chunks.iter() {
+            for v in [
+                chunk.first_record_id,
+                chunk.last_record_id,
+                chunk.first_written,
+                chunk.last_written,
+            ] {
+                writer.write_all(&v.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(mut reader: R) -> Result<EvtxIndex, String> {
+        let mut read = |len: usize| {
+            let mut buf = vec![0u8; len];
+            reader
+                .read_exact(&mut buf)
+                .map(|_| buf)
+                .map_err(|err| err.to_string())
+        };
+        if read(8)? != INDEX_SIGNATURE {
+            return Err("Not an evtx index".to_owned());
+        }
+        let u32 = |b: Vec<u8>| u32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+        let u64 = |b: Vec<u8>| {
+            let mut le = [0u8; 8];
+            le.copy_from_slice(&b);
+            u64::from_le_bytes(le)
+        };
+        let version = u32(read(4)?);
+        if version != INDEX_VERSION {
+            return Err(format!("Unsupported evtx index version {version}"));
+        }
+        let header_checksum = u32(read(4)?);
+        let next_record_id = u64(read(8)?);
+        let count = u32(read(4)?);
+        if count > u16::MAX as u32 {
+            return Err(format!("Evtx index lists {count} chunks"));
+        }
+        let chunks = (0..count)
+            .map(|_| {
+                Ok(ChunkSummary {
+                    first_record_id: u64(read(8)?),
+                    last_record_id: u64(read(8)?),
+                    first_written: u64(read(8)?),
+                    last_written: u64(read(8)?),
+                })
+            })
+            .collect::<Result<Vec<ChunkSummary>, String>>()?;
+        Ok(EvtxIndex {
+            header_checksum,
+            next_record_id,
+            chunks,
+        })
+    }
+
+    /// Whether the index describes the file with this header
+    pub fn matches(&self, header: &FileHeader) -> bool {
+        self.header_checksum == header.checksum
+            && self.next_record_id == header.next_record_id
+            && self.chunks.len() == header.chunk_count as usize
+    }
+
+    /// The chunk holding record `id`, or the first one after it
+    pub fn chunk_for_record(&self, id: u64) -> Option<u16> {
+        self.first_chunk(|chunk| chunk.last_record_id >= id)
+    }
+
+    /// The first chunk holding a record written at or after `filetime`
+    pub fn chunk_for_time(&self, filetime: u64) -> Option<u16> {
+        self.first_chunk(|chunk| chunk.last_written >= filetime)
+    }
+
+    /// The chunk with the lowest record IDs among those matching `wanted`. Chunks
+    /// are compared by record ID rather than by place in the file, which differ
+    /// once the log has wrapped around.
+    fn first_chunk<F>(&self, wanted: F) -> Option<u16>
+    where
+        F: Fn(&ChunkSummary) -> bool,
+    {
+        self.chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk)| wanted(chunk))
+            .min_by_key(|(_, chunk)| chunk.first_record_id)
+            .map(|(index, _)| index as u16)
+    }
+}
+
+/// Records to pass over after seeking, before events are returned
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Skip {
+    Nothing,
+    BeforeRecord(u64),
+    BeforeTime(u64),
+}
+
+impl Skip {
+    pub fn skips(self, record: &RecordHeader) -> bool {
+        match self {
+            Skip::Nothing => false,
+            Skip::BeforeRecord(id) => record.record_id < id,
+            Skip::BeforeTime(filetime) => record.written < filetime,
+        }
+    }
+}
+
+impl<R: Read + Seek> EvtxReader<R> {
+    /// Uses `index` for seeking. It is rejected if it was built from a different
+    /// state of the file.
+    pub fn set_index(&mut self, index: EvtxIndex) -> Result<(), String> {
+        if !index.matches(&self.header) {
+            return Err("Evtx index does not match the file".to_owned());
+        }
+        self.index = Some(index);
+        Ok(())
+    }
+
+    pub fn index(&self) -> Option<&EvtxIndex> {
+        self.index.as_ref()
+    }
+
+    /// Makes `events` start at record `id`, or the first record after it if there
+    /// is no such record
+    pub fn seek_record(&mut self, id: u64) -> Result<(), String> {
+        let chunk = match self.index {
+            Some(ref index) => index
+                .chunk_for_record(id)
+                .map_or(self.chunk_count, |index| self.position_of(index)),
+            None => self.partition_chunks(|header, _| header.last_record_id < id)?,
+        };
+        self.seek(chunk, Skip::BeforeRecord(id));
+        Ok(())
+    }
+
+    /// Makes `events` start at the first record written at or after `filetime`.
+    /// Without an index, records are assumed to be written in time order.
+    pub fn seek_time(&mut self, filetime: u64) -> Result<(), String> {
+        let chunk = match self.index {
+            Some(ref index) => index
+                .chunk_for_time(filetime)
+                .map_or(self.chunk_count, |index| self.position_of(index)),
+            // the last chunk starting at or before `filetime` may hold it
+            None => self
+                .partition_chunks(|_, first_written| first_written <= filetime)?
+                .saturating_sub(1),
+        };
+        self.seek(chunk, Skip::BeforeTime(filetime));
+        Ok(())
+    }
+
+    /// Makes `events` start from the first chunk again
+    pub fn rewind(&mut self) {
+        self.seek(0, Skip::Nothing);
+    }
+
+    fn seek(&mut self, chunk: u16, skip: Skip) {
+        self.start_chunk = chunk;
+        self.skip = skip;
+    }
+
+    /// Binary searches the chunk headers, in record order, for the position of the
+    /// first chunk for which `before` is false, reading only the header and first
+    /// record header of each
+    fn partition_chunks<F>(&mut self, mut before: F) -> Result<u16, String>
+    where
+        F: FnMut(&ChunkHeader, u64) -> bool,
+    {
+        let (mut low, mut high) = (0u16, self.chunk_count);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            let (header, first_written) = self.chunk_header(self.chunk_at(middle))?;
+            if before(&header, first_written) {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+        Ok(low)
+    }
+
+    /// Reads a chunk's header and the time its first record was written
+    fn chunk_header(&mut self, index: u16) -> Result<(ChunkHeader, u64), String> {
+        let mut data = vec![0u8; CHUNK_HEADER_SIZE + RECORD_HEADER_SIZE];
+        self.reader
+            .seek(SeekFrom::Start(chunk_offset(index)))
+            .and_then(|_| self.reader.read_exact(&mut data))
+            .map_err(|err| format!("Could not read chunk {index}: {err}"))?;
+        let header = ChunkHeader::parse(&data).map_err(|err| format!("Chunk {index}: {err}"))?;
+        let record = &data[CHUNK_HEADER_SIZE..];
+        if &record[..4] != RECORD_SIGNATURE {
+            return Err(format!("Chunk {index}: missing first record"));
+        }
+        let mut written = [0u8; 8];
+        written.copy_from_slice(&record[16..24]);
+        Ok((header, u64::from_le_bytes(written)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EvtxIndex;
+    use crate::evtx::chunk::CHUNK_SIZE;
+    use crate::evtx::header::FILE_HEADER_SIZE;
+    use crate::evtx::testdata::{self, SAMPLE_EVENTS};
+    use crate::evtx::{EvtxReader, EvtxWriter, ParallelOptions};
+    use std::io::Cursor;
+
+    /// Three chunks of 1000 events, one written every second
+    fn large_file() -> Vec<u8> {
+        let mut writer = EvtxWriter::new(Cursor::new(Vec::new())).unwrap();
+        for i in 0..3000 {
+            writer
+                .write_xml(&format!(
+                    "<Event><System><TimeCreated SystemTime='2019-04-17T{:02}:{:02}:{:02}.0000000Z'/>\
+                     </System><EventData><Data>{i}{}</Data></EventData></Event>",
+                    i / 3600,
+                    i / 60 % 60,
+                    i % 60,
+                    " ".repeat(20)
+                ))
+                .unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    /// `file` as a log that has wrapped around once: the oldest chunk is the second
+    /// in the file and the newest the first
+    fn wrapped(file: &[u8]) -> Vec<u8> {
+        let (header, chunks) = file.split_at(FILE_HEADER_SIZE);
+        let mut chunks = chunks.chunks(CHUNK_SIZE).collect::<Vec<&[u8]>>();
+        chunks.rotate_right(1);
+        let mut data = header.to_vec();
+        data[8..16].copy_from_slice(&1u64.to_le_bytes());
+        data[16..24].copy_from_slice(&0u64.to_le_bytes());
+        testdata::seal_file(&mut data);
+        data.extend(chunks.concat());
+        data
+    }
+
+    fn first_data(reader: EvtxReader<Cursor<Vec<u8>>>) -> Option<String> {
+        let event = reader.events().next()?.unwrap().to_string();
+        let start = event.find("<Data>")? + 6;
+        Some(event[start..].split_whitespace().next()?.to_owned())
+    }
+
+    #[test]
+    fn seek_with_and_without_index() {
+        let file = large_file();
+        let mut reader = EvtxReader::new(Cursor::new(file.clone())).unwrap();
+        assert!(reader.header().chunk_count > 2);
+        let index = EvtxIndex::build(&mut reader).unwrap();
+        assert_eq!(index.chunks().len(), reader.header().chunk_count as usize);
+        assert_eq!(index.chunks()[0].first_record_id, 1);
+
+        let mut saved = Vec::new();
+        index.write_to(&mut saved).unwrap();
+        let loaded = EvtxIndex::read_from(saved.as_slice()).unwrap();
+        assert_eq!(loaded, index);
+
+        for index in [None, Some(loaded)] {
+            let open = || {
+                let mut reader = EvtxReader::new(Cursor::new(file.clone())).unwrap();
+                if let Some(ref index) = index {
+                    reader.set_index(index.clone()).unwrap();
+                }
+                reader
+            };
+            for id in [1u64, 2, 1500, 2999, 3000] {
+                let mut reader = open();
+                reader.seek_record(id).unwrap();
+                assert_eq!(first_data(reader), Some((id - 1).to_string()));
+            }
+            let mut reader = open();
+            reader.seek_record(3001).unwrap();
+            assert!(reader.events().next().is_none());
+
+            // 00:40:00 is record 2400, 2019-04-17T00:40:00.5Z the one after it
+            let time = 131_999_328_000_000_000 + 2400 * 10_000_000;
+            let mut reader = open();
+            reader.seek_time(time).unwrap();
+            assert_eq!(first_data(reader), Some("2400".to_owned()));
+            let mut reader = open();
+            reader.seek_time(time + 5_000_000).unwrap();
+            assert_eq!(first_data(reader), Some("2401".to_owned()));
+            let mut reader = open();
+            reader.seek_time(0).unwrap();
+            assert_eq!(first_data(reader), Some("0".to_owned()));
+        }
+    }
+
+    #[test]
+    fn wrapped_log() {
+        let file = wrapped(&large_file());
+        let mut reader = EvtxReader::new(Cursor::new(file.clone())).unwrap();
+        assert_eq!(reader.chunk(1).unwrap().header.first_record_id, 1);
+        let index = EvtxIndex::build(&mut reader).unwrap();
+        assert_eq!(index.chunk_for_record(1), Some(1));
+        assert_eq!(index.chunk_for_record(3000), Some(0));
+
+        let data = |event: String| {
+            let start = event.find("<Data>").unwrap() + 6;
+            event[start..].split_whitespace().next().unwrap().to_owned()
+        };
+        let expected = (0..3000).map(|i| i.to_string()).collect::<Vec<String>>();
+        let events = EvtxReader::new(Cursor::new(file.clone()))
+            .unwrap()
+            .events()
+            .map(|event| data(event.unwrap().to_string()))
+            .collect::<Vec<String>>();
+        assert_eq!(events, expected);
+        let options = ParallelOptions::new().threads(2).build();
+        let events = EvtxReader::new(Cursor::new(file.clone()))
+            .unwrap()
+            .par_events(&options)
+            .map(|event| data(event.unwrap().to_string()))
+            .collect::<Vec<String>>();
+        assert_eq!(events, expected);
+
+        for index in [None, Some(index)] {
+            let open = || {
+                let mut reader = EvtxReader::new(Cursor::new(file.clone())).unwrap();
+                if let Some(ref index) = index {
+                    reader.set_index(index.clone()).unwrap();
+                }
+                reader
+            };
+            for id in [1u64, 1500, 2999, 3000] {
+                let mut reader = open();
+                reader.seek_record(id).unwrap();
+                let events = reader.events().collect::<Vec<_>>();
+                assert_eq!(events.len() as u64, 3001 - id);
+                assert_eq!(
+                    data(events[0].clone().unwrap().to_string()),
+                    (id - 1).to_string()
+                );
+            }
+            let time = 131_999_328_000_000_000 + 2400 * 10_000_000;
+            let mut reader = open();
+            reader.seek_time(time).unwrap();
+            assert_eq!(first_data(reader), Some("2400".to_owned()));
+        }
+    }
+
+    #[test]
+    fn stale_index() {
+        let mut reader = EvtxReader::new(Cursor::new(testdata::sample_file())).unwrap();
+        let index = EvtxIndex::build(&mut reader).unwrap();
+        let mut other = EvtxReader::new(Cursor::new(large_file())).unwrap();
+        assert!(other.set_index(index.clone()).is_err());
+        assert!(EvtxIndex::read_from(&b"EvtxIdx\0\x02\0\0\0"[..]).is_err());
+
+        reader.set_index(index).unwrap();
+        reader.seek_record(3).unwrap();
+        let events = reader
+            .events()
+            .map(|event| event.unwrap().to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(events, SAMPLE_EVENTS[2..]);
+    }
+
+    #[test]
+    fn sidecar_path() {
+        assert_eq!(
+            EvtxIndex::sidecar_path("logs/Security.evtx"),
+            std::path::PathBuf::from("logs/Security.evtx.idx")
+        );
+    }
+}
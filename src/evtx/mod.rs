@@ -0,0 +1,589 @@
+//! A pure Rust reader for `.evtx` files, usable on any platform
+//!
+//! The file is read chunk by chunk, with header and chunk checksums verified, and
+//! each record's BinXml is rendered to the same XML `EvtRender` produces. Events
+//! come out as the crate's `Event` type, so code consuming live queries works on
+//! offline files unchanged.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use win_event_log::prelude::*;
+//!
+//! let reader = EvtxReader::open("Security.evtx").unwrap();
+//! for event in reader {
+//!     println!("{}", event);
+//! }
+//! ```
+//!
+//! Damaged files can be read with `EvtxReader::lenient`, which salvages what it
+//! can and reports every problem it finds on a channel instead of failing. Records
+//! outside of any `.evtx` file, in disk images or memory dumps, are found by
+//! `Carver`. `EvtxWriter` goes the other way, building `.evtx` files from events.
+//!
+//! `EvtxReader::par_events` decodes chunks on a pool of threads, for large files.
+
+use self::index::Skip;
+use self::recovery::Diagnostics;
+use crate::event::Event;
+use crate::query_list::{QueryFilter, QueryList};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::mpsc::Sender;
+
+mod binxml;
+mod carver;
+mod chunk;
+mod crc32;
+pub(crate) mod cursor;
+mod header;
+mod index;
+mod parallel;
+mod record;
+mod recovery;
+#[cfg(test)]
+mod testdata;
+pub(crate) mod value;
+mod writer;
+mod xml;
+
+pub use self::binxml::{Template, TemplateCache, XmlAttribute, XmlElement, XmlNode};
+pub use self::carver::{CarvedEvent, Carver};
+pub use self::chunk::{Chunk, ChunkHeader, ChunkRecords, TemplateDefinition, CHUNK_SIZE};
+pub use self::header::{FileFlags, FileHeader, FILE_HEADER_SIZE};
+pub use self::index::{ChunkSummary, EvtxIndex};
+pub use self::parallel::{ParEvents, ParallelOptions};
+pub use self::record::RecordHeader;
+pub use self::recovery::{Anomaly, AnomalyKind};
+pub use self::value::XmlValue;
+pub use self::writer::EvtxWriter;
+
+/// Reads an `.evtx` file from any seekable source
+pub struct EvtxReader<R> {
+    reader: R,
+    header: FileHeader,
+    chunk_count: u16,
+    recovery: Option<Diagnostics>,
+    index: Option<EvtxIndex>,
+    /// Where `events` starts, as a position in record order set by the seek
+    /// methods
+    start_chunk: u16,
+    skip: Skip,
+}
+
+impl EvtxReader<File> {
+    /// Opens and validates the file at `path`. An index sidecar next to it is
+    /// used for seeking if it is up to date.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<EvtxReader<File>, String> {
+        let path = path.as_ref();
+        let file =
+            File::open(path).map_err(|err| format!("Could not open {}: {err}", path.display()))?;
+        let mut reader = EvtxReader::new(file)?;
+        if let Ok(index) = EvtxIndex::load(EvtxIndex::sidecar_path(path)) {
+            let _ = reader.set_index(index);
+        }
+        Ok(reader)
+    }
+
+    /// Opens the file at `path` in recovery mode, see `EvtxReader::lenient`
+    pub fn open_lenient<P: AsRef<Path>>(
+        path: P,
+        diagnostics: Sender<Anomaly>,
+    ) -> Result<EvtxReader<File>, String> {
+        let path = path.as_ref();
+        let file =
+            File::open(path).map_err(|err| format!("Could not open {}: {err}", path.display()))?;
+        EvtxReader::lenient(file, diagnostics)
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl EvtxReader<std::io::Cursor<memmap2::Mmap>> {
+    /// Opens the file at `path` memory-mapped, so chunks are read without system
+    /// calls. The file must not be truncated while it is mapped. An index sidecar
+    /// is used as by `open`.
+    pub fn open_mmap<P: AsRef<Path>>(
+        path: P,
+    ) -> Result<EvtxReader<std::io::Cursor<memmap2::Mmap>>, String> {
+        let path = path.as_ref();
+        let file =
+            File::open(path).map_err(|err| format!("Could not open {}: {err}", path.display()))?;
+        // Safety: the map is only read, and the caller is told not to truncate the file
+        let map = unsafe { memmap2::Mmap::map(&file) }
+            .map_err(|err| format!("Could not map {}: {err}", path.display()))?;
+        let mut reader = EvtxReader::new(std::io::Cursor::new(map))?;
+        if let Ok(index) = EvtxIndex::load(EvtxIndex::sidecar_path(path)) {
+            let _ = reader.set_index(index);
+        }
+        Ok(reader)
+    }
+}
+
+impl<R: Read + Seek> EvtxReader<R> {
+    /// Reads the file header from `reader` and verifies its checksum
+    pub fn new(mut reader: R) -> Result<EvtxReader<R>, String> {
+        let mut data = vec![0u8; FILE_HEADER_SIZE];
+        reader
+            .seek(SeekFrom::Start(0))
+            .and_then(|_| reader.read_exact(&mut data))
+            .map_err(|err| format!("Could not read evtx file header: {err}"))?;
+        let header = FileHeader::parse(&data)?;
+        header.verify()?;
+        Ok(EvtxReader {
+            reader,
+            chunk_count: header.chunk_count,
+            header,
+            recovery: None,
+            index: None,
+            start_chunk: 0,
+            skip: Skip::Nothing,
+        })
+    }
+
+    /// Reads `reader` in recovery mode. Damaged headers, chunks and records are
+    /// skipped or salvaged, and each problem is sent to `diagnostics` as an
+    /// `Anomaly`. Chunks are located by the size of the file, since the header
+    /// of a dirty file may be out of date. Only I/O errors are returned.
+    pub fn lenient(mut reader: R, diagnostics: Sender<Anomaly>) -> Result<EvtxReader<R>, String> {
+        let diagnostics = Diagnostics(Some(diagnostics));
+        let size = reader
+            .seek(SeekFrom::End(0))
+            .map_err(|err| format!("Could not read evtx file size: {err}"))?;
+        let mut data = Vec::with_capacity(FILE_HEADER_SIZE);
+        reader
+            .seek(SeekFrom::Start(0))
+            .and_then(|_| {
+                (&mut reader)
+                    .take(FILE_HEADER_SIZE as u64)
+                    .read_to_end(&mut data)
+            })
+            .map_err(|err| format!("Could not read evtx file header: {err}"))?;
+        data.resize(FILE_HEADER_SIZE, 0);
+
+        let header = match FileHeader::parse(&data) {
+            Ok(header) => {
+                if let Err(err) = header.verify() {
+                    diagnostics.report(0, AnomalyKind::FileHeaderChecksum, None, err);
+                }
+                if header.is_dirty() {
+                    diagnostics.report(
+                        0,
+                        AnomalyKind::DirtyFile,
+                        None,
+                        "File was not closed cleanly".to_owned(),
+                    );
+                }
+                header
+            }
+            Err(err) => {
+                diagnostics.report(0, AnomalyKind::FileHeader, None, err);
+                recovery::blank_file_header()
+            }
+        };
+
+        let chunks = size
+            .saturating_sub(FILE_HEADER_SIZE as u64)
+            .div_ceil(CHUNK_SIZE as u64);
+        let chunk_count = chunks.min(u16::MAX as u64) as u16;
+        if header.chunk_count > chunk_count {
+            diagnostics.report(
+                size,
+                AnomalyKind::TruncatedChunk,
+                None,
+                format!(
+                    "File header lists {} chunks, only {chunk_count} are present",
+                    header.chunk_count
+                ),
+            );
+        }
+        Ok(EvtxReader {
+            reader,
+            header,
+            chunk_count,
+            recovery: Some(diagnostics),
+            index: None,
+            start_chunk: 0,
+            skip: Skip::Nothing,
+        })
+    }
+
+    pub fn header(&self) -> &FileHeader {
+        &self.header
+    }
+
+    /// Reads and verifies the chunk at `index`
+    pub fn chunk(&mut self, index: u16) -> Result<Chunk, String> {
+        let data = self.read_chunk(index)?;
+        parse_chunk(index, data)
+    }
+
+    fn read_chunk(&mut self, index: u16) -> Result<Vec<u8>, String> {
+        let mut data = vec![0u8; CHUNK_SIZE];
+        self.reader
+            .seek(SeekFrom::Start(chunk_offset(index)))
+            .and_then(|_| self.reader.read_exact(&mut data))
+            .map_err(|err| format!("Could not read chunk {index}: {err}"))?;
+        Ok(data)
+    }
+
+    /// Decodes whatever events can be recovered from the chunk at `index`
+    fn recover_chunk(&mut self, index: u16) -> Vec<(RecordHeader, Event)> {
+        match (self.read_damaged_chunk(index), &self.recovery) {
+            (Some(data), Some(diagnostics)) => {
+                recovery::recover_chunk(data, chunk_offset(index), diagnostics)
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Reads as much of the chunk at `index` as is there, padding a truncated
+    /// chunk with zeroes. Chunks that were never written are `None`.
+    fn read_damaged_chunk(&mut self, index: u16) -> Option<Vec<u8>> {
+        let diagnostics = self.recovery.as_ref()?;
+        let offset = chunk_offset(index);
+        let mut data = Vec::with_capacity(CHUNK_SIZE);
+        let reader = &mut self.reader;
+        let read = reader
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| reader.take(CHUNK_SIZE as u64).read_to_end(&mut data));
+        if let Err(err) = read {
+            diagnostics.report(
+                offset,
+                AnomalyKind::UnreadableChunk,
+                None,
+                format!("Could not read chunk {index}: {err}"),
+            );
+            return None;
+        }
+        // space preallocated for chunks that were never written
+        if data.iter().all(|b| *b == 0) {
+            return None;
+        }
+        if data.len() < CHUNK_SIZE {
+            diagnostics.report(
+                offset,
+                AnomalyKind::TruncatedChunk,
+                None,
+                format!("Chunk {index} has {} of {CHUNK_SIZE} bytes", data.len()),
+            );
+            data.resize(CHUNK_SIZE, 0);
+        }
+        Some(data)
+    }
+
+    /// Iterates over every chunk listed in the file header
+    pub fn chunks(&mut self) -> Chunks<'_, R> {
+        Chunks {
+            reader: self,
+            index: 0,
+        }
+    }
+
+    /// Iterates over every event in the file, oldest chunk first, or from the
+    /// position set by `seek_record` or `seek_time`. The first error ends the
+    /// iteration. In recovery mode no errors are returned.
+    pub fn events(self) -> EvtxEvents<R> {
+        EvtxEvents {
+            position: self.start_chunk,
+            skip: self.skip,
+            reader: self,
+            pending: VecDeque::new(),
+            failed: false,
+        }
+    }
+
+    /// Iterates over the events `list` selects, as `events` does
+    pub fn query(self, list: &QueryList) -> QueryFilter<EvtxEvents<R>> {
+        list.filter(self.events())
+    }
+
+    /// The chunk at `position` in record order. Once a log has wrapped around,
+    /// the header's first chunk, the oldest, is not the first in the file.
+    pub(crate) fn chunk_at(&self, position: u16) -> u16 {
+        match self.oldest_chunk() {
+            0 => position,
+            oldest => ((oldest as u32 + position as u32) % self.chunk_count as u32) as u16,
+        }
+    }
+
+    /// The position of chunk `index` in record order, the inverse of `chunk_at`
+    pub(crate) fn position_of(&self, index: u16) -> u16 {
+        match self.oldest_chunk() {
+            0 => index,
+            oldest => {
+                ((index as u32 + self.chunk_count as u32 - oldest as u32) % self.chunk_count as u32)
+                    as u16
+            }
+        }
+    }
+
+    /// The first chunk in record order, if the header gives a valid one
+    fn oldest_chunk(&self) -> u16 {
+        if self.header.first_chunk_number < self.chunk_count as u64 {
+            self.header.first_chunk_number as u16
+        } else {
+            0
+        }
+    }
+}
+
+/// Iterator over the chunks of an `EvtxReader`
+pub struct Chunks<'a, R> {
+    reader: &'a mut EvtxReader<R>,
+    index: u16,
+}
+
+impl<R: Read + Seek> Iterator for Chunks<'_, R> {
+    type Item = Result<Chunk, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.reader.chunk_count {
+            return None;
+        }
+        let chunk = self.reader.chunk(self.index);
+        self.index += 1;
+        Some(chunk)
+    }
+}
+
+/// Iterator over the events of an `EvtxReader`, decoded one chunk at a time
+pub struct EvtxEvents<R> {
+    reader: EvtxReader<R>,
+    /// The next chunk's position in record order
+    position: u16,
+    /// Records still to pass over after a seek
+    skip: Skip,
+    pending: VecDeque<Result<Event, String>>,
+    failed: bool,
+}
+
+impl<R> EvtxEvents<R> {
+    /// Whether `record` comes before the seek position. Once one record is
+    /// returned, none are skipped.
+    fn skipped(&mut self, record: &RecordHeader) -> bool {
+        let skipped = self.skip.skips(record);
+        if !skipped {
+            self.skip = Skip::Nothing;
+        }
+        skipped
+    }
+}
+
+impl<R: Read + Seek> Iterator for EvtxEvents<R> {
+    type Item = Result<Event, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.failed {
+            if let Some(event) = self.pending.pop_front() {
+                self.failed = event.is_err();
+                return Some(event);
+            }
+            if self.position >= self.reader.chunk_count {
+                return None;
+            }
+            let index = self.reader.chunk_at(self.position);
+            self.position += 1;
+            if self.reader.recovery.is_some() {
+                for (record, event) in self.reader.recover_chunk(index) {
+                    if !self.skipped(&record) {
+                        self.pending.push_back(Ok(event));
+                    }
+                }
+                continue;
+            }
+            let chunk = match self.reader.chunk(index) {
+                Ok(chunk) => chunk,
+                Err(err) => {
+                    self.pending.push_back(Err(err));
+                    continue;
+                }
+            };
+            for record in chunk.records() {
+                match record {
+                    Ok(ref record) if self.skipped(record) => {}
+                    Ok(record) => self.pending.push_back(chunk.render(&record).map(Event)),
+                    Err(err) => self.pending.push_back(Err(err)),
+                }
+            }
+        }
+        None
+    }
+}
+
+fn parse_chunk(index: u16, data: Vec<u8>) -> Result<Chunk, String> {
+    let chunk = Chunk::parse(data).map_err(|err| format!("Chunk {index}: {err}"))?;
+    chunk
+        .verify()
+        .map_err(|err| format!("Chunk {index}: {err}"))?;
+    Ok(chunk)
+}
+
+pub(crate) fn chunk_offset(index: u16) -> u64 {
+    FILE_HEADER_SIZE as u64 + index as u64 * CHUNK_SIZE as u64
+}
+
+impl<R: Read + Seek> IntoIterator for EvtxReader<R> {
+    type Item = Event;
+    type IntoIter = EvtxIntoIterator<R>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        EvtxIntoIterator {
+            events: self.events(),
+        }
+    }
+}
+
+/// Yields events like `WinEventsIntoIterator`, stopping at the first event that
+/// cannot be read. Use `EvtxReader::events` to see the error.
+pub struct EvtxIntoIterator<R> {
+    events: EvtxEvents<R>,
+}
+
+impl<R: Read + Seek> Iterator for EvtxIntoIterator<R> {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        self.events.next().and_then(|event| event.ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testdata::{self, SAMPLE_EVENTS};
+    use super::EvtxReader;
+    use std::io::Cursor;
+
+    #[test]
+    fn read_sample_file() {
+        let reader = EvtxReader::new(Cursor::new(testdata::sample_file())).unwrap();
+        assert_eq!(reader.header().chunk_count, 1);
+        assert!(!reader.header().is_dirty());
+        let events = reader
+            .into_iter()
+            .map(|event| event.to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(events, SAMPLE_EVENTS);
+    }
+
+    #[test]
+    fn chunk_tables() {
+        let mut reader = EvtxReader::new(Cursor::new(testdata::sample_file())).unwrap();
+        let chunk = reader.chunk(0).unwrap();
+        assert_eq!(chunk.header.first_record_id, 1);
+        assert_eq!(chunk.header.last_record_id, 3);
+        let ids = chunk
+            .records()
+            .map(|record| record.unwrap().record_id)
+            .collect::<Vec<u64>>();
+        assert_eq!(ids, vec![1, 2, 3]);
+        let templates = chunk.templates().unwrap();
+        assert_eq!(templates.len(), 1);
+        assert_eq!(templates[0].guid, [0xaa; 16]);
+        assert_eq!(
+            chunk.strings().values().collect::<Vec<&String>>(),
+            vec!["Event"]
+        );
+    }
+
+    #[test]
+    fn decode_tree_with_cached_template() {
+        use super::{XmlNode, XmlValue};
+        let mut reader = EvtxReader::new(Cursor::new(testdata::sample_file())).unwrap();
+        let chunk = reader.chunk(0).unwrap();
+        let records = chunk
+            .records()
+            .map(|record| chunk.decode(&record.unwrap()).unwrap())
+            .collect::<Vec<Vec<XmlNode>>>();
+        assert_eq!(chunk.template_cache().len(), 1);
+        match (&records[0][0], &records[1][0]) {
+            (
+                XmlNode::TemplateInstance { template: a, .. },
+                XmlNode::TemplateInstance { template: b, .. },
+            ) => assert!(std::sync::Arc::ptr_eq(a, b)),
+            _ => panic!("expected template instances"),
+        }
+
+        let tree = XmlNode::expand(&records[0]).unwrap();
+        let event = tree[0].as_element().unwrap();
+        assert_eq!(event.name, "Event");
+        assert_eq!(
+            event.child("Provider").unwrap().attribute_value("Name"),
+            Some("Test-Provider".to_owned())
+        );
+        assert_eq!(
+            event.child("EventID").unwrap().children,
+            vec![XmlNode::Value(XmlValue::UInt16(4624))]
+        );
+        assert_eq!(XmlNode::to_xml(&tree).unwrap(), SAMPLE_EVENTS[0]);
+
+        let tree = XmlNode::expand(&records[1]).unwrap();
+        let security = tree[0].as_element().unwrap().child("Security").unwrap();
+        assert!(security.attribute("UserID").is_none());
+        assert_eq!(XmlNode::to_xml(&tree).unwrap(), SAMPLE_EVENTS[1]);
+    }
+
+    #[test]
+    fn bad_checksums() {
+        let mut file = testdata::sample_file();
+        file[50] ^= 0xff;
+        assert!(EvtxReader::new(Cursor::new(file)).is_err());
+
+        let file = testdata::sample_file_with(|chunk| chunk[600] ^= 0xff);
+        let mut events = EvtxReader::new(Cursor::new(file)).unwrap().events();
+        assert!(events.next().unwrap().is_err());
+        assert!(events.next().is_none());
+    }
+
+    #[test]
+    fn truncated_chunk() {
+        use super::chunk::{Chunk, CHUNK_HEADER_SIZE};
+        let chunk = testdata::sample_chunk(1);
+        let free_space = u32::from_le_bytes([chunk[48], chunk[49], chunk[50], chunk[51]]);
+        let short = Chunk::parse(chunk[..free_space as usize].to_vec()).unwrap();
+        assert!(short.verify().is_ok());
+        assert!(Chunk::parse(chunk[..CHUNK_HEADER_SIZE + 8].to_vec()).is_err());
+    }
+
+    #[test]
+    fn query_file() {
+        use crate::prelude::*;
+        let mut writer = super::EvtxWriter::new(Cursor::new(Vec::new())).unwrap();
+        for id in [4624, 4625, 4634] {
+            writer
+                .write_xml(&format!(
+                    "<Event><System><EventID>{id}</EventID><Channel>Security</Channel></System>\
+                     </Event>"
+                ))
+                .unwrap();
+        }
+        let file = writer.finish().unwrap().into_inner();
+        let list = QueryList::new()
+            .with_query(
+                Query::new()
+                    .item(QueryItem::file_selector("Security.evtx").unwrap())
+                    .item(
+                        QueryItem::file_suppressor("Security.evtx")
+                            .unwrap()
+                            .system_conditions(Condition::filter(EventFilter::event(4625)))
+                            .build(),
+                    )
+                    .query(),
+            )
+            .build();
+        let events = EvtxReader::new(Cursor::new(file))
+            .unwrap()
+            .query(&list)
+            .map(|event| event.unwrap().to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(events.len(), 2);
+        assert!(events[0].contains("4624") && events[1].contains("4634"));
+    }
+
+    #[test]
+    fn not_an_evtx_file() {
+        assert!(EvtxReader::new(Cursor::new(vec![0u8; 8192])).is_err());
+        assert!(EvtxReader::new(Cursor::new(b"ElfFile\0".to_vec())).is_err());
+    }
+}
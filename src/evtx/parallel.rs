@@ -0,0 +1,364 @@
+//! Decoding chunks on a pool of worker threads
+//!
+//! Chunks are read in turn by one thread and handed to the workers, which parse,
+//! verify and render them. Only a few chunks per worker are in flight at once, so
+//! memory use does not grow with the size of the file.
+
+use crate::event::Event;
+use crate::evtx::index::Skip;
+use crate::evtx::recovery::{self, Diagnostics};
+use crate::evtx::{chunk_offset, parse_chunk, EvtxReader, RecordHeader};
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{Read, Seek};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Chunks in flight for each worker thread
+const CHUNKS_PER_THREAD: usize = 2;
+
+/// Options for `EvtxReader::par_events`
+#[derive(Clone, Debug)]
+pub struct ParallelOptions {
+    threads: usize,
+    ordered: bool,
+}
+
+impl<'a> ParallelOptions {
+    /// One worker per CPU, with events in the order `events` returns them
+    pub fn new() -> ParallelOptions {
+        ParallelOptions {
+            threads: thread::available_parallelism()
+                .map(|threads| threads.get())
+                .unwrap_or(1),
+            ordered: true,
+        }
+    }
+
+    /// The number of worker threads, at least one
+    pub fn threads(&'a mut self, threads: usize) -> &'a mut Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Whether events come out in the order `events` returns them. Unordered, each chunk's events are
+    /// returned as soon as it is decoded, still in order within the chunk.
+    pub fn ordered(&'a mut self, ordered: bool) -> &'a mut Self {
+        self.ordered = ordered;
+        self
+    }
+
+    pub fn build(&self) -> ParallelOptions {
+        self.clone()
+    }
+}
+
+impl Default for ParallelOptions {
+    fn default() -> ParallelOptions {
+        ParallelOptions::new()
+    }
+}
+
+/// A chunk as read from the file, waiting for a worker
+enum Job {
+    Decode(Vec<u8>),
+    Recover(Vec<u8>, Diagnostics),
+    Failed(String),
+    Empty,
+}
+
+type Decoded = Vec<Result<(RecordHeader, Event), String>>;
+
+impl Job {
+    fn run(self, index: u16) -> Decoded {
+        match self {
+            Job::Decode(data) => match parse_chunk(index, data) {
+                Ok(chunk) => chunk
+                    .records()
+                    .map(|record| {
+                        let record = record?;
+                        let event = chunk.render(&record).map(Event)?;
+                        Ok((record, event))
+                    })
+                    .collect(),
+                Err(err) => vec![Err(err)],
+            },
+            Job::Recover(data, diagnostics) => {
+                recovery::recover_chunk(data, chunk_offset(index), &diagnostics)
+                    .into_iter()
+                    .map(Ok)
+                    .collect()
+            }
+            Job::Failed(err) => vec![Err(err)],
+            Job::Empty => Vec::new(),
+        }
+    }
+}
+
+impl<R: Read + Seek + Send + 'static> EvtxReader<R> {
+    /// Iterates over the events like `events`, decoding chunks on worker threads.
+    /// In recovery mode anomalies are reported from the worker threads, so they
+    /// may arrive out of order.
+    pub fn par_events(mut self, options: &ParallelOptions) -> ParEvents {
+        let window = options.threads * CHUNKS_PER_THREAD;
+        let (credits, credit_receiver) = mpsc::sync_channel::<()>(window);
+        for _ in 0..window {
+            let _ = credits.send(());
+        }
+        let (jobs, job_receiver) = mpsc::sync_channel::<(u16, u16, Job)>(window);
+        let (results, result_receiver) = mpsc::sync_channel(window);
+
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        for _ in 0..options.threads {
+            let jobs = job_receiver.clone();
+            let results = results.clone();
+            thread::spawn(move || work(&jobs, &results));
+        }
+
+        let start = self.start_chunk;
+        let skip = self.skip;
+        thread::spawn(move || {
+            for position in self.start_chunk..self.chunk_count {
+                if credit_receiver.recv().is_err() {
+                    return;
+                }
+                let index = self.chunk_at(position);
+                let job = match self.recovery {
+                    Some(ref diagnostics) => {
+                        let diagnostics = diagnostics.clone();
+                        match self.read_damaged_chunk(index) {
+                            Some(data) => Job::Recover(data, diagnostics),
+                            None => Job::Empty,
+                        }
+                    }
+                    None => match self.read_chunk(index) {
+                        Ok(data) => Job::Decode(data),
+                        Err(err) => Job::Failed(err),
+                    },
+                };
+                if jobs.send((position, index, job)).is_err() {
+                    return;
+                }
+            }
+        });
+
+        ParEvents {
+            results: result_receiver,
+            credits,
+            ordered: options.ordered,
+            next: start,
+            start,
+            finished: BTreeMap::new(),
+            skip,
+            pending: VecDeque::new(),
+            failed: false,
+        }
+    }
+}
+
+/// Runs jobs until the reading thread is done or the events are dropped. Results
+/// are tagged with the chunk's position in record order.
+fn work(jobs: &Mutex<Receiver<(u16, u16, Job)>>, results: &SyncSender<(u16, Decoded)>) {
+    loop {
+        let job = match jobs.lock() {
+            Ok(jobs) => jobs.recv(),
+            Err(_) => return,
+        };
+        let (position, index, job) = match job {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+        // a chunk that trips up the decoder must still take its turn
+        let decoded = panic::catch_unwind(AssertUnwindSafe(|| job.run(index)))
+            .unwrap_or_else(|_| vec![Err(format!("Chunk {index}: decoder panicked"))]);
+        if results.send((position, decoded)).is_err() {
+            return;
+        }
+    }
+}
+
+/// Iterator over the events of an `EvtxReader`, decoded on worker threads
+pub struct ParEvents {
+    results: Receiver<(u16, Decoded)>,
+    /// Lets the reading thread read one more chunk
+    credits: SyncSender<()>,
+    ordered: bool,
+    /// The position of the chunk due next when ordered
+    next: u16,
+    /// The position of the chunk sought to
+    start: u16,
+    /// Chunks decoded ahead of their turn
+    finished: BTreeMap<u16, Decoded>,
+    skip: Skip,
+    pending: VecDeque<Result<Event, String>>,
+    failed: bool,
+}
+
+impl ParEvents {
+    /// The next chunk to return and its position, or `None` once every chunk is
+    /// done
+    fn next_chunk(&mut self) -> Option<(u16, Decoded)> {
+        if !self.ordered {
+            return self.results.recv().ok();
+        }
+        loop {
+            if let Some(decoded) = self.finished.remove(&self.next) {
+                self.next += 1;
+                return Some((self.next - 1, decoded));
+            }
+            let (position, decoded) = self.results.recv().ok()?;
+            self.finished.insert(position, decoded);
+        }
+    }
+
+    /// Whether `record` comes before the seek position. In order, this is as for
+    /// `EvtxEvents`; unordered, only the chunk sought to can hold such records.
+    fn skipped(&mut self, position: u16, record: &RecordHeader) -> bool {
+        if !self.ordered {
+            return position == self.start && self.skip.skips(record);
+        }
+        let skipped = self.skip.skips(record);
+        if !skipped {
+            self.skip = Skip::Nothing;
+        }
+        skipped
+    }
+}
+
+impl Iterator for ParEvents {
+    type Item = Result<Event, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.failed {
+            if let Some(event) = self.pending.pop_front() {
+                self.failed = event.is_err();
+                return Some(event);
+            }
+            let (position, decoded) = self.next_chunk()?;
+            let _ = self.credits.try_send(());
+            for result in decoded {
+                if let Ok((ref record, _)) = result {
+                    if self.skipped(position, record) {
+                        continue;
+                    }
+                }
+                self.pending.push_back(result.map(|(_, event)| event));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ParallelOptions;
+    use crate::event::Event;
+    use crate::evtx::testdata;
+    use crate::evtx::{AnomalyKind, EvtxReader, EvtxWriter};
+    use std::io::Cursor;
+    use std::sync::mpsc::channel;
+
+    fn large_file() -> Vec<u8> {
+        let mut writer = EvtxWriter::new(Cursor::new(Vec::new())).unwrap();
+        for i in 0..2000 {
+            let xml = format!(
+                "<Event xmlns='http://schemas.microsoft.com/win/2004/08/events/event'>\
+                 <System><EventID>{i}</EventID></System>\
+                 <EventData><Data>{}</Data></EventData></Event>",
+                "x".repeat(i % 40 + 1)
+            );
+            writer.write_xml(&xml).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn sequential(file: &[u8]) -> Vec<String> {
+        EvtxReader::new(Cursor::new(file.to_vec()))
+            .unwrap()
+            .into_iter()
+            .map(|event| event.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn same_events_as_sequential() {
+        let file = large_file();
+        let expected = sequential(&file);
+        assert!(
+            EvtxReader::new(Cursor::new(file.clone()))
+                .unwrap()
+                .header()
+                .chunk_count
+                > 3
+        );
+
+        let options = ParallelOptions::new().threads(3).build();
+        let events = EvtxReader::new(Cursor::new(file.clone()))
+            .unwrap()
+            .par_events(&options)
+            .map(|event| event.unwrap().to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(events, expected);
+
+        let options = ParallelOptions::new().threads(4).ordered(false).build();
+        let mut events = EvtxReader::new(Cursor::new(file.clone()))
+            .unwrap()
+            .par_events(&options)
+            .map(|event| event.unwrap().to_string())
+            .collect::<Vec<String>>();
+        let mut sorted = expected.clone();
+        events.sort();
+        sorted.sort();
+        assert_eq!(events, sorted);
+
+        let mut reader = EvtxReader::new(Cursor::new(file.clone())).unwrap();
+        reader.seek_record(1000).unwrap();
+        let mut events = reader
+            .par_events(&options)
+            .map(|event| event.unwrap().to_string())
+            .collect::<Vec<String>>();
+        let mut expected = sequential(&file)[999..].to_vec();
+        events.sort();
+        expected.sort();
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn errors_and_recovery() {
+        let file = testdata::sample_file_with(|chunk| chunk[600] ^= 0xff);
+        let mut events = EvtxReader::new(Cursor::new(file.clone()))
+            .unwrap()
+            .par_events(&ParallelOptions::new());
+        assert!(events.next().unwrap().is_err());
+        assert!(events.next().is_none());
+
+        let (sender, receiver) = channel();
+        let events = EvtxReader::lenient(Cursor::new(file), sender)
+            .unwrap()
+            .par_events(&ParallelOptions::new())
+            .collect::<Result<Vec<Event>, String>>()
+            .unwrap();
+        assert_eq!(events.len(), 3);
+        let kinds = receiver
+            .iter()
+            .map(|anomaly| anomaly.kind)
+            .collect::<Vec<AnomalyKind>>();
+        assert_eq!(kinds, vec![AnomalyKind::ChunkRecordsChecksum]);
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn memory_mapped() {
+        let file = large_file();
+        let path = std::env::temp_dir().join(format!("mmap-{}.evtx", std::process::id()));
+        std::fs::write(&path, &file).unwrap();
+        let events = EvtxReader::open_mmap(&path)
+            .unwrap()
+            .par_events(&ParallelOptions::new())
+            .map(|event| event.unwrap().to_string())
+            .collect::<Vec<String>>();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(events, sequential(&file));
+    }
+}
@@ -0,0 +1,56 @@
+use crate::evtx::cursor::Cursor;
+
+/// Signature at the start of every event record (`**\0\0`)
+pub const RECORD_SIGNATURE: &[u8; 4] = b"\x2a\x2a\x00\x00";
+/// Size of the fixed record header preceding the BinXml data
+pub const RECORD_HEADER_SIZE: usize = 24;
+/// Smallest possible record: header plus the trailing size copy
+pub const MIN_RECORD_SIZE: usize = RECORD_HEADER_SIZE + 4;
+
+/// The fixed header of an event record inside a chunk
+#[derive(Clone, Debug)]
+pub struct RecordHeader {
+    /// Offset of the record from the start of its chunk
+    pub offset: usize,
+    /// Total size of the record, including header and trailing size copy
+    pub size: u32,
+    pub record_id: u64,
+    /// Time the record was written, as a FILETIME
+    pub written: u64,
+}
+
+impl RecordHeader {
+    /// Parses the record at `offset` within `chunk`, which must end no later than
+    /// `limit`. The trailing size copy is checked so a record that overruns into the
+    /// next one is rejected.
+    pub fn parse(chunk: &[u8], offset: usize, limit: usize) -> Result<RecordHeader, String> {
+        let mut cursor = Cursor::new(&chunk[..limit.min(chunk.len())], offset);
+        if cursor.bytes(4)? != RECORD_SIGNATURE {
+            return Err(format!("Missing record signature at offset {offset:#x}"));
+        }
+        let size = cursor.u32()?;
+        if (size as usize) < MIN_RECORD_SIZE || offset + size as usize > limit {
+            return Err(format!("Invalid record size {size} at offset {offset:#x}"));
+        }
+        let record_id = cursor.u64()?;
+        let written = cursor.u64()?;
+        cursor.seek(offset + size as usize - 4);
+        let size_copy = cursor.u32()?;
+        if size_copy != size {
+            return Err(format!(
+                "Record size mismatch at offset {offset:#x}: {size} != {size_copy}"
+            ));
+        }
+        Ok(RecordHeader {
+            offset,
+            size,
+            record_id,
+            written,
+        })
+    }
+
+    /// Range of the BinXml event data within the chunk
+    pub fn data_range(&self) -> std::ops::Range<usize> {
+        self.offset + RECORD_HEADER_SIZE..self.offset + self.size as usize - 4
+    }
+}
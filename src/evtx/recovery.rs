@@ -0,0 +1,372 @@
+//! Lenient reading of damaged `.evtx` files
+//!
+//! Files taken from crashed or tampered hosts often have bad checksums, a dirty
+//! header or a truncated last chunk. In recovery mode every problem is reported as
+//! an `Anomaly` and reading carries on with whatever can still be decoded.
+
+use crate::event::Event;
+use crate::evtx::chunk::{self, Chunk, ChunkHeader, CHUNK_HEADER_SIZE, CHUNK_SIZE};
+use crate::evtx::header::{FileFlags, FileHeader};
+use crate::evtx::record::{RecordHeader, MIN_RECORD_SIZE, RECORD_SIGNATURE};
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::sync::mpsc::Sender;
+
+/// The kind of problem found while recovering a file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnomalyKind {
+    /// The file header could not be parsed; chunks are located by file size instead
+    FileHeader,
+    FileHeaderChecksum,
+    /// The file was not closed cleanly, so the header may be out of date
+    DirtyFile,
+    /// A chunk's header could not be parsed; its records were salvaged by scanning
+    ChunkHeader,
+    ChunkHeaderChecksum,
+    ChunkRecordsChecksum,
+    /// The chunk's string table is damaged; names are read from the records instead
+    StringTable,
+    /// The file ends part way through a chunk
+    TruncatedChunk,
+    /// A chunk could not be read at all
+    UnreadableChunk,
+    /// A record header is damaged; reading resumed at the next valid record
+    CorruptRecord,
+    /// A record's BinXml could not be decoded
+    UndecodableRecord,
+}
+
+/// A problem found in recovery mode
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Anomaly {
+    /// Offset from the start of the file
+    pub offset: u64,
+    pub kind: AnomalyKind,
+    /// Record IDs that could not be recovered because of this problem, if known
+    pub lost_records: Option<RangeInclusive<u64>>,
+    pub message: String,
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:?} at offset {:#x}: {}",
+            self.kind, self.offset, self.message
+        )?;
+        if let Some(ref lost) = self.lost_records {
+            write!(f, " (records {}-{} lost)", lost.start(), lost.end())?;
+        }
+        Ok(())
+    }
+}
+
+/// Where anomalies are sent. A dropped receiver is not an error; the anomalies
+/// are simply discarded.
+#[derive(Clone)]
+pub(crate) struct Diagnostics(pub Option<Sender<Anomaly>>);
+
+impl Diagnostics {
+    /// Discards every anomaly
+    pub fn none() -> Diagnostics {
+        Diagnostics(None)
+    }
+
+    pub fn report(
+        &self,
+        offset: u64,
+        kind: AnomalyKind,
+        lost_records: Option<RangeInclusive<u64>>,
+        message: String,
+    ) {
+        if let Some(ref sender) = self.0 {
+            let _ = sender.send(Anomaly {
+                offset,
+                kind,
+                lost_records,
+                message,
+            });
+        }
+    }
+}
+
+/// Decodes every recoverable event in a chunk. `data` must be `CHUNK_SIZE` bytes
+/// and `offset` is where the chunk starts in the file.
+pub(crate) fn recover_chunk(
+    data: Vec<u8>,
+    offset: u64,
+    diagnostics: &Diagnostics,
+) -> Vec<(RecordHeader, Event)> {
+    let header = match ChunkHeader::parse(&data) {
+        Ok(header) => Some(header),
+        Err(err) => {
+            diagnostics.report(offset, AnomalyKind::ChunkHeader, None, err);
+            None
+        }
+    };
+    let chunk = match header {
+        Some(header) => {
+            let strings = chunk::read_strings(&data, &header).unwrap_or_else(|err| {
+                diagnostics.report(offset, AnomalyKind::StringTable, None, err);
+                HashMap::new()
+            });
+            let chunk = Chunk::from_parts(header, data, strings);
+            if chunk.header_checksum() != chunk.header.header_checksum {
+                diagnostics.report(
+                    offset,
+                    AnomalyKind::ChunkHeaderChecksum,
+                    None,
+                    format!(
+                        "Stored {:#010x}, computed {:#010x}",
+                        chunk.header.header_checksum,
+                        chunk.header_checksum()
+                    ),
+                );
+            }
+            if chunk.records_checksum() != chunk.header.records_checksum {
+                diagnostics.report(
+                    offset,
+                    AnomalyKind::ChunkRecordsChecksum,
+                    None,
+                    format!(
+                        "Stored {:#010x}, computed {:#010x}",
+                        chunk.header.records_checksum,
+                        chunk.records_checksum()
+                    ),
+                );
+            }
+            chunk
+        }
+        None => Chunk::from_parts(headerless(), data, HashMap::new()),
+    };
+    recover_records(&chunk, offset, diagnostics)
+}
+
+/// Header used when the file header is unreadable
+pub(crate) fn blank_file_header() -> FileHeader {
+    FileHeader {
+        first_chunk_number: 0,
+        last_chunk_number: 0,
+        next_record_id: 0,
+        header_size: 0,
+        minor_version: 0,
+        major_version: 0,
+        header_block_size: 0,
+        chunk_count: 0,
+        flags: FileFlags::empty(),
+        checksum: 0,
+        computed_checksum: 0,
+    }
+}
+
+/// Header used to salvage records from a chunk whose real header is unreadable
+fn headerless() -> ChunkHeader {
+    ChunkHeader {
+        first_record_number: 0,
+        last_record_number: 0,
+        first_record_id: 0,
+        last_record_id: 0,
+        header_size: 0,
+        last_record_offset: 0,
+        free_space_offset: CHUNK_SIZE as u32,
+        records_checksum: 0,
+        flags: 0,
+        header_checksum: 0,
+        string_offsets: Vec::new(),
+        template_offsets: Vec::new(),
+    }
+}
+
+/// Walks the records of a chunk, resynchronising on the next valid record
+/// signature whenever a record header is damaged
+pub(crate) fn recover_records(
+    chunk: &Chunk,
+    offset: u64,
+    diagnostics: &Diagnostics,
+) -> Vec<(RecordHeader, Event)> {
+    let data = chunk.data();
+    let limit = chunk.header.free_space_offset as usize;
+    let known_last = match chunk.header.last_record_id {
+        0 => None,
+        id => Some(id),
+    };
+    let mut last_id = match chunk.header.first_record_id {
+        0 => None,
+        id => Some(id - 1),
+    };
+    let mut events = Vec::new();
+    let mut position = CHUNK_HEADER_SIZE;
+
+    while position + MIN_RECORD_SIZE <= limit {
+        let record = match RecordHeader::parse(data, position, limit) {
+            Ok(record) => record,
+            // the unused space after the last record
+            Err(_) if data[position..limit].iter().all(|b| *b == 0) => break,
+            Err(err) => {
+                let next = find_record(data, position + 1, limit);
+                let lost_until = match next {
+                    Some(ref record) => Some(record.record_id.saturating_sub(1)),
+                    None => known_last,
+                };
+                let lost = match (last_id, lost_until) {
+                    (Some(last), Some(until)) if until > last => Some(last + 1..=until),
+                    _ => None,
+                };
+                diagnostics.report(
+                    offset + position as u64,
+                    AnomalyKind::CorruptRecord,
+                    lost,
+                    err,
+                );
+                match next {
+                    Some(record) => record,
+                    None => break,
+                }
+            }
+        };
+        position = record.offset + record.size as usize;
+        last_id = Some(record.record_id);
+        match chunk.render(&record) {
+            Ok(xml) => events.push((record, Event(xml))),
+            Err(err) => diagnostics.report(
+                offset + record.offset as u64,
+                AnomalyKind::UndecodableRecord,
+                Some(record.record_id..=record.record_id),
+                err,
+            ),
+        }
+    }
+    events
+}
+
+/// Finds the next valid record at or after `from`, checking candidates by their
+/// signature, size and trailing size copy
+pub(crate) fn find_record(data: &[u8], from: usize, limit: usize) -> Option<RecordHeader> {
+    let limit = limit.min(data.len());
+    let mut position = from;
+    while position + MIN_RECORD_SIZE <= limit {
+        match data[position..limit]
+            .windows(RECORD_SIGNATURE.len())
+            .position(|window| window == RECORD_SIGNATURE)
+        {
+            Some(found) => {
+                position += found;
+                if let Ok(record) = RecordHeader::parse(data, position, limit) {
+                    return Some(record);
+                }
+                position += 1;
+            }
+            None => return None,
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Anomaly, AnomalyKind};
+    use crate::evtx::chunk::Chunk;
+    use crate::evtx::header::FILE_HEADER_SIZE;
+    use crate::evtx::testdata::{self, SAMPLE_EVENTS};
+    use crate::evtx::EvtxReader;
+    use std::io::Cursor;
+    use std::sync::mpsc;
+
+    fn recover(file: Vec<u8>) -> (Vec<String>, Vec<Anomaly>) {
+        let (sender, receiver) = mpsc::channel();
+        let events = EvtxReader::lenient(Cursor::new(file), sender)
+            .unwrap()
+            .into_iter()
+            .map(|event| event.to_string())
+            .collect();
+        (events, receiver.try_iter().collect())
+    }
+
+    fn kinds(anomalies: &[Anomaly]) -> Vec<AnomalyKind> {
+        anomalies.iter().map(|anomaly| anomaly.kind).collect()
+    }
+
+    fn record_offsets(chunk: &[u8]) -> Vec<usize> {
+        Chunk::parse(chunk.to_vec())
+            .unwrap()
+            .records()
+            .map(|record| record.unwrap().offset)
+            .collect()
+    }
+
+    #[test]
+    fn bad_chunk_checksums() {
+        let file = testdata::sample_file_with(|chunk| chunk[52] ^= 0xff);
+        let (events, anomalies) = recover(file);
+        assert_eq!(events, SAMPLE_EVENTS);
+        assert_eq!(
+            kinds(&anomalies),
+            vec![
+                AnomalyKind::ChunkHeaderChecksum,
+                AnomalyKind::ChunkRecordsChecksum
+            ]
+        );
+        assert_eq!(anomalies[0].offset, FILE_HEADER_SIZE as u64);
+    }
+
+    #[test]
+    fn corrupt_record_header() {
+        let mut chunk = testdata::sample_chunk(1);
+        let offset = record_offsets(&chunk)[1];
+        chunk[offset] = 0;
+        testdata::seal_chunk(&mut chunk);
+        let (events, anomalies) = recover(testdata::file(&[chunk], 4));
+        assert_eq!(events, vec![SAMPLE_EVENTS[0], SAMPLE_EVENTS[2]]);
+        assert_eq!(kinds(&anomalies), vec![AnomalyKind::CorruptRecord]);
+        assert_eq!(anomalies[0].offset, (FILE_HEADER_SIZE + offset) as u64);
+        assert_eq!(anomalies[0].lost_records, Some(2..=2));
+    }
+
+    #[test]
+    fn truncated_file() {
+        let mut file = testdata::sample_file();
+        let last = record_offsets(&file[FILE_HEADER_SIZE..])[2];
+        file.truncate(FILE_HEADER_SIZE + last + 10);
+        let (events, anomalies) = recover(file);
+        assert_eq!(events, SAMPLE_EVENTS[..2]);
+        assert_eq!(
+            kinds(&anomalies),
+            vec![
+                AnomalyKind::TruncatedChunk,
+                AnomalyKind::ChunkRecordsChecksum,
+                AnomalyKind::CorruptRecord
+            ]
+        );
+        assert_eq!(anomalies[2].lost_records, Some(3..=3));
+    }
+
+    #[test]
+    fn dirty_file_with_stale_header() {
+        // the second chunk was written after the header was last updated
+        let mut file = testdata::file(&[testdata::sample_chunk(1)], 4);
+        file.extend(testdata::sample_chunk(4));
+        file[120] |= 0x1;
+        file[24] += 1;
+        let (events, anomalies) = recover(file);
+        assert_eq!(events.len(), 6);
+        assert_eq!(events[3..], SAMPLE_EVENTS);
+        assert_eq!(
+            kinds(&anomalies),
+            vec![AnomalyKind::FileHeaderChecksum, AnomalyKind::DirtyFile]
+        );
+    }
+
+    #[test]
+    fn unreadable_file_header() {
+        let mut file = testdata::sample_file();
+        file[..8].copy_from_slice(&[0; 8]);
+        let (events, anomalies) = recover(file);
+        assert_eq!(events, SAMPLE_EVENTS);
+        assert_eq!(kinds(&anomalies), vec![AnomalyKind::FileHeader]);
+
+        let (events, anomalies) = recover(vec![0xff; 100]);
+        assert!(events.is_empty());
+        assert_eq!(kinds(&anomalies), vec![AnomalyKind::FileHeader]);
+    }
+}
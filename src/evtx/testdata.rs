@@ -0,0 +1,346 @@
+//! Hand-assembled `.evtx` data for the reader tests
+
+use crate::evtx::chunk::{CHUNK_HEADER_SIZE, CHUNK_SIZE};
+use crate::evtx::crc32::{self, Crc32};
+use crate::evtx::header::FILE_HEADER_SIZE;
+
+pub const PROVIDER_GUID: [u8; 16] = [
+    0x25, 0x96, 0x84, 0x54, 0x78, 0x54, 0x94, 0x49, 0xa5, 0xba, 0x3e, 0x3b, 0x03, 0x28, 0xc3, 0x0d,
+];
+pub const WRITTEN: u64 = 132_000_000_000_000_000;
+
+/// Writes BinXml at a known chunk offset, so inline names and templates can point
+/// at themselves the way the real format does
+pub struct Writer {
+    pub buf: Vec<u8>,
+    base: usize,
+}
+
+impl Writer {
+    pub fn new(base: usize) -> Writer {
+        Writer {
+            buf: Vec::new(),
+            base,
+        }
+    }
+
+    pub fn pos(&self) -> usize {
+        self.base + self.buf.len()
+    }
+
+    pub fn u8(&mut self, v: u8) -> &mut Self {
+        self.buf.push(v);
+        self
+    }
+
+    pub fn u16(&mut self, v: u16) -> &mut Self {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    pub fn u32(&mut self, v: u32) -> &mut Self {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    pub fn bytes(&mut self, v: &[u8]) -> &mut Self {
+        self.buf.extend_from_slice(v);
+        self
+    }
+
+    pub fn utf16(&mut self, s: &str) -> &mut Self {
+        for c in s.encode_utf16() {
+            self.u16(c);
+        }
+        self
+    }
+
+    pub fn fragment_header(&mut self) -> &mut Self {
+        self.bytes(&[0x0f, 0x01, 0x01, 0x00])
+    }
+
+    /// Inline name definition, returning its offset
+    pub fn name(&mut self, name: &str) -> u32 {
+        let offset = self.pos() as u32 + 4;
+        self.u32(offset).u32(0).u16(0);
+        self.u16(name.encode_utf16().count() as u16)
+            .utf16(name)
+            .u16(0);
+        offset
+    }
+
+    pub fn open(&mut self, name: &str, attributes: bool) -> u32 {
+        self.u8(if attributes { 0x41 } else { 0x01 })
+            .u16(0xffff)
+            .u32(0);
+        let offset = self.name(name);
+        if attributes {
+            self.u32(0);
+        }
+        offset
+    }
+
+    /// Like `open`, for BinXml embedded in a value, where elements have no
+    /// dependency identifier
+    pub fn open_embedded(&mut self, name: &str, attributes: bool) -> u32 {
+        self.u8(if attributes { 0x41 } else { 0x01 }).u32(0);
+        let offset = self.name(name);
+        if attributes {
+            self.u32(0);
+        }
+        offset
+    }
+
+    pub fn attribute(&mut self, name: &str, more: bool) -> u32 {
+        self.u8(if more { 0x46 } else { 0x06 });
+        self.name(name)
+    }
+
+    pub fn text(&mut self, s: &str) -> &mut Self {
+        self.u8(0x05)
+            .u8(0x01)
+            .u16(s.encode_utf16().count() as u16)
+            .utf16(s)
+    }
+
+    pub fn substitution(&mut self, optional: bool, id: u16, value_type: u8) -> &mut Self {
+        self.u8(if optional { 0x0e } else { 0x0d })
+            .u16(id)
+            .u8(value_type)
+    }
+}
+
+/// Substitution values for `templated_record`
+pub struct Values<'a> {
+    pub provider: &'a str,
+    pub event_id: u16,
+    pub user: Option<&'a [u8]>,
+}
+
+fn push_values(w: &mut Writer, values: &Values) {
+    let provider = values
+        .provider
+        .encode_utf16()
+        .flat_map(|c| c.to_le_bytes().to_vec())
+        .collect::<Vec<u8>>();
+    let user = values.user.unwrap_or(&[]);
+    let subs: Vec<(u8, Vec<u8>)> = vec![
+        (0x01, provider),
+        (0x0f, PROVIDER_GUID.to_vec()),
+        (0x06, values.event_id.to_le_bytes().to_vec()),
+        (0x11, WRITTEN.to_le_bytes().to_vec()),
+        (
+            if values.user.is_some() { 0x13 } else { 0x00 },
+            user.to_vec(),
+        ),
+    ];
+    w.u32(subs.len() as u32);
+    for (value_type, data) in subs.iter() {
+        w.u16(data.len() as u16).u8(*value_type).u8(0);
+    }
+    for (_, data) in subs.iter() {
+        w.bytes(data);
+    }
+}
+
+/// A record using a template. With `template` set to `None` the template is
+/// defined inline and its offset returned; otherwise the existing one is reused.
+pub fn templated_record(base: usize, template: Option<u32>, values: &Values) -> (Vec<u8>, u32) {
+    let mut w = Writer::new(base);
+    w.fragment_header();
+    w.u8(0x0c).u8(0x01).u32(1);
+    let definition = match template {
+        Some(offset) => {
+            w.u32(offset);
+            offset
+        }
+        None => {
+            let offset = w.pos() as u32 + 4;
+            w.u32(offset);
+            w.u32(0).bytes(&[0xaa; 16]);
+            let mut body = Writer::new(w.pos() + 4);
+            body.fragment_header();
+            body.open("Event", false);
+            body.u8(0x02);
+            body.open("Provider", true);
+            body.attribute("Name", true);
+            body.substitution(false, 0, 0x01);
+            body.attribute("Guid", false);
+            body.substitution(true, 1, 0x0f);
+            body.u8(0x03);
+            body.open("EventID", false);
+            body.u8(0x02).substitution(false, 2, 0x06).u8(0x04);
+            body.open("TimeCreated", true);
+            body.attribute("SystemTime", false);
+            body.substitution(false, 3, 0x11).u8(0x03);
+            body.open("Security", true);
+            body.attribute("UserID", false);
+            body.substitution(true, 4, 0x13).u8(0x03);
+            body.u8(0x04).u8(0x00);
+            w.u32(body.buf.len() as u32).bytes(&body.buf);
+            offset
+        }
+    };
+    push_values(&mut w, values);
+    w.u8(0x00);
+    (w.buf, definition)
+}
+
+/// A record without a template: `<Event><Data Name='Foo'>a&lt;b</Data></Event>`
+pub fn plain_record(base: usize) -> Vec<u8> {
+    let mut w = Writer::new(base);
+    w.fragment_header();
+    w.open("Event", false);
+    w.u8(0x02);
+    w.open("Data", true);
+    w.attribute("Name", false);
+    w.text("Foo");
+    w.u8(0x02).text("a<b").u8(0x04);
+    w.u8(0x04).u8(0x00);
+    w.buf
+}
+
+/// Wraps BinXml in a record header
+pub fn record(id: u64, data: &[u8]) -> Vec<u8> {
+    let size = (data.len() + 28) as u32;
+    let mut out = b"\x2a\x2a\x00\x00".to_vec();
+    out.extend_from_slice(&size.to_le_bytes());
+    out.extend_from_slice(&id.to_le_bytes());
+    out.extend_from_slice(&WRITTEN.to_le_bytes());
+    out.extend_from_slice(data);
+    out.extend_from_slice(&size.to_le_bytes());
+    out
+}
+
+/// Assembles a chunk from BinXml builders, each given the offset its record data
+/// starts at and returning that data. Record IDs start at `first_id`.
+pub fn chunk(first_id: u64, records: Vec<Box<dyn Fn(usize) -> Vec<u8> + '_>>) -> Vec<u8> {
+    let mut data = vec![0u8; CHUNK_HEADER_SIZE];
+    let mut last_offset = 0;
+    let mut id = first_id;
+    for build in records.iter() {
+        last_offset = data.len();
+        let body = build(data.len() + 24);
+        data.extend(record(id, &body));
+        id += 1;
+    }
+    let free = data.len();
+    let last_id = id - 1;
+    data.resize(CHUNK_SIZE, 0);
+
+    data[..8].copy_from_slice(b"ElfChnk\0");
+    data[8..16].copy_from_slice(&first_id.to_le_bytes());
+    data[16..24].copy_from_slice(&last_id.to_le_bytes());
+    data[24..32].copy_from_slice(&first_id.to_le_bytes());
+    data[32..40].copy_from_slice(&last_id.to_le_bytes());
+    data[40..44].copy_from_slice(&128u32.to_le_bytes());
+    data[44..48].copy_from_slice(&(last_offset as u32).to_le_bytes());
+    data[48..52].copy_from_slice(&(free as u32).to_le_bytes());
+    seal_chunk(&mut data);
+    data
+}
+
+/// Recomputes both chunk checksums
+pub fn seal_chunk(data: &mut [u8]) {
+    let free = u32::from_le_bytes([data[48], data[49], data[50], data[51]]) as usize;
+    let records = crc32::checksum(&data[CHUNK_HEADER_SIZE..free]);
+    data[52..56].copy_from_slice(&records.to_le_bytes());
+    let header = Crc32::new()
+        .update(&data[..120])
+        .update(&data[128..CHUNK_HEADER_SIZE])
+        .finish();
+    data[124..128].copy_from_slice(&header.to_le_bytes());
+}
+
+/// A complete file holding `chunks`
+pub fn file(chunks: &[Vec<u8>], next_record_id: u64) -> Vec<u8> {
+    let mut data = vec![0u8; FILE_HEADER_SIZE];
+    data[..8].copy_from_slice(b"ElfFile\0");
+    data[16..24].copy_from_slice(&(chunks.len() as u64 - 1).to_le_bytes());
+    data[24..32].copy_from_slice(&next_record_id.to_le_bytes());
+    data[32..36].copy_from_slice(&128u32.to_le_bytes());
+    data[36..38].copy_from_slice(&1u16.to_le_bytes());
+    data[38..40].copy_from_slice(&3u16.to_le_bytes());
+    data[40..42].copy_from_slice(&4096u16.to_le_bytes());
+    data[42..44].copy_from_slice(&(chunks.len() as u16).to_le_bytes());
+    seal_file(&mut data);
+    for chunk in chunks {
+        data.extend_from_slice(chunk);
+    }
+    data
+}
+
+/// Recomputes the file header checksum
+pub fn seal_file(data: &mut [u8]) {
+    let checksum = crc32::checksum(&data[..120]);
+    data[124..128].copy_from_slice(&checksum.to_le_bytes());
+}
+
+/// A file with one chunk of three records: a templated record defining its template
+/// inline, a second one reusing it, and a record without a template
+pub fn sample_file() -> Vec<u8> {
+    sample_file_with(|_| {})
+}
+
+/// Like `sample_file`, letting the caller damage the chunk after its checksums
+/// were computed
+pub fn sample_file_with<F: FnOnce(&mut Vec<u8>)>(damage: F) -> Vec<u8> {
+    let mut chunk = sample_chunk(1);
+    damage(&mut chunk);
+    file(&[chunk], 4)
+}
+
+/// The chunk used by `sample_file`, with record IDs starting at `first_id`
+pub fn sample_chunk(first_id: u64) -> Vec<u8> {
+    let template = std::cell::Cell::new(0u32);
+    let sid = [1u8, 1, 0, 0, 0, 0, 0, 5, 18, 0, 0, 0];
+    let mut data = chunk(
+        first_id,
+        vec![
+            Box::new(|base| {
+                let (data, offset) = templated_record(
+                    base,
+                    None,
+                    &Values {
+                        provider: "Test-Provider",
+                        event_id: 4624,
+                        user: Some(&sid),
+                    },
+                );
+                template.set(offset);
+                data
+            }),
+            Box::new(|base| {
+                templated_record(
+                    base,
+                    Some(template.get()),
+                    &Values {
+                        provider: "Other",
+                        event_id: 1102,
+                        user: None,
+                    },
+                )
+                .0
+            }),
+            Box::new(plain_record),
+        ],
+    );
+    // register the template, and the name of its root element as a common string
+    let definition = template.get() as usize;
+    data[384..388].copy_from_slice(&(definition as u32).to_le_bytes());
+    let name = definition + 24 + 4 + 7;
+    let name = [data[name], data[name + 1], data[name + 2], data[name + 3]];
+    data[128..132].copy_from_slice(&name);
+    seal_chunk(&mut data);
+    data
+}
+
+pub const SAMPLE_EVENTS: [&str; 3] = [
+    "<Event><Provider Name='Test-Provider' Guid='{54849625-5478-4994-A5BA-3E3B0328C30D}'/>\
+     <EventID>4624</EventID><TimeCreated SystemTime='2019-04-17T18:40:00.0000000Z'/>\
+     <Security UserID='S-1-5-18'/></Event>",
+    "<Event><Provider Name='Other' Guid='{54849625-5478-4994-A5BA-3E3B0328C30D}'/>\
+     <EventID>1102</EventID><TimeCreated SystemTime='2019-04-17T18:40:00.0000000Z'/>\
+     <Security/></Event>",
+    "<Event><Data Name='Foo'>a&lt;b</Data></Event>",
+];
@@ -0,0 +1,570 @@
+//! BinXml substitution values, and formatting them the way `EvtRender` prints them
+
+use crate::evtx::binxml::XmlNode;
+use crate::evtx::cursor::{utf16_to_string, Cursor};
+use std::fmt;
+
+pub const NULL_TYPE: u8 = 0x00;
+pub const WSTRING_TYPE: u8 = 0x01;
+pub const STRING_TYPE: u8 = 0x02;
+pub const INT8_TYPE: u8 = 0x03;
+pub const UINT8_TYPE: u8 = 0x04;
+pub const INT16_TYPE: u8 = 0x05;
+pub const UINT16_TYPE: u8 = 0x06;
+pub const INT32_TYPE: u8 = 0x07;
+pub const UINT32_TYPE: u8 = 0x08;
+pub const INT64_TYPE: u8 = 0x09;
+pub const UINT64_TYPE: u8 = 0x0a;
+pub const REAL32_TYPE: u8 = 0x0b;
+pub const REAL64_TYPE: u8 = 0x0c;
+pub const BOOL_TYPE: u8 = 0x0d;
+pub const BINARY_TYPE: u8 = 0x0e;
+pub const GUID_TYPE: u8 = 0x0f;
+pub const SIZE_T_TYPE: u8 = 0x10;
+pub const FILETIME_TYPE: u8 = 0x11;
+pub const SYSTEMTIME_TYPE: u8 = 0x12;
+pub const SID_TYPE: u8 = 0x13;
+pub const HEX_INT32_TYPE: u8 = 0x14;
+pub const HEX_INT64_TYPE: u8 = 0x15;
+pub const BINXML_TYPE: u8 = 0x21;
+/// Set on a value type when the value is an array of that type
+pub const ARRAY_FLAG: u8 = 0x80;
+
+/// A typed value from a BinXml value token or template substitution
+#[derive(Clone, Debug, PartialEq)]
+pub enum XmlValue {
+    Null,
+    String(String),
+    AnsiString(String),
+    Int8(i8),
+    UInt8(u8),
+    Int16(i16),
+    UInt16(u16),
+    Int32(i32),
+    UInt32(u32),
+    Int64(i64),
+    UInt64(u64),
+    Real32(f32),
+    Real64(f64),
+    Bool(bool),
+    Binary(Vec<u8>),
+    Guid([u8; 16]),
+    /// A SIZE_T from a log written by a 32-bit process
+    SizeT32(u32),
+    SizeT64(u64),
+    /// 100ns intervals since 1601-01-01 UTC
+    FileTime(u64),
+    /// year, month, day of week, day, hour, minute, second, milliseconds
+    SystemTime([u16; 8]),
+    /// Binary SID, as stored
+    Sid(Vec<u8>),
+    HexInt32(u32),
+    HexInt64(u64),
+    /// An embedded BinXml fragment
+    BinXml(Vec<XmlNode>),
+    Array(Vec<XmlValue>),
+}
+
+impl XmlValue {
+    /// Decodes a value of `value_type` from `data`. Embedded BinXml needs the
+    /// surrounding chunk and is handled by the decoder instead.
+    pub fn parse(value_type: u8, data: &[u8]) -> Result<XmlValue, String> {
+        if value_type & ARRAY_FLAG != 0 {
+            return XmlValue::parse_array(value_type & !ARRAY_FLAG, data);
+        }
+        let mut cursor = Cursor::new(data, 0);
+        Ok(match value_type {
+            NULL_TYPE => XmlValue::Null,
+            WSTRING_TYPE => XmlValue::String(utf16_to_string(data)),
+            STRING_TYPE => XmlValue::AnsiString(
+                String::from_utf8_lossy(data)
+                    .trim_end_matches('\0')
+                    .to_owned(),
+            ),
+            INT8_TYPE => XmlValue::Int8(cursor.u8()? as i8),
+            UINT8_TYPE => XmlValue::UInt8(cursor.u8()?),
+            INT16_TYPE => XmlValue::Int16(cursor.u16()? as i16),
+            UINT16_TYPE => XmlValue::UInt16(cursor.u16()?),
+            INT32_TYPE => XmlValue::Int32(cursor.u32()? as i32),
+            UINT32_TYPE => XmlValue::UInt32(cursor.u32()?),
+            INT64_TYPE => XmlValue::Int64(cursor.u64()? as i64),
+            UINT64_TYPE => XmlValue::UInt64(cursor.u64()?),
+            REAL32_TYPE => XmlValue::Real32(f32::from_bits(cursor.u32()?)),
+            REAL64_TYPE => XmlValue::Real64(f64::from_bits(cursor.u64()?)),
+            BOOL_TYPE => XmlValue::Bool(cursor.u32()? != 0),
+            BINARY_TYPE => XmlValue::Binary(data.to_vec()),
+            GUID_TYPE => {
+                let mut guid = [0u8; 16];
+                guid.copy_from_slice(cursor.bytes(16)?);
+                XmlValue::Guid(guid)
+            }
+            SIZE_T_TYPE if data.len() == 4 => XmlValue::SizeT32(cursor.u32()?),
+            SIZE_T_TYPE => XmlValue::SizeT64(cursor.u64()?),
+            FILETIME_TYPE => XmlValue::FileTime(cursor.u64()?),
+            SYSTEMTIME_TYPE => {
+                let mut time = [0u16; 8];
+                for part in time.iter_mut() {
+                    *part = cursor.u16()?;
+                }
+                XmlValue::SystemTime(time)
+            }
+            SID_TYPE => {
+                // validate now so formatting cannot fail later
+                format_sid(data)?;
+                XmlValue::Sid(data.to_vec())
+            }
+            HEX_INT32_TYPE => XmlValue::HexInt32(cursor.u32()?),
+            HEX_INT64_TYPE => XmlValue::HexInt64(cursor.u64()?),
+            other => return Err(format!("Unsupported BinXml value type {other:#04x}")),
+        })
+    }
+
+    fn parse_array(value_type: u8, data: &[u8]) -> Result<XmlValue, String> {
+        let items = match value_type {
+            WSTRING_TYPE => {
+                let units = data
+                    .chunks_exact(2)
+                    .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                    .collect::<Vec<u16>>();
+                let mut units = units.as_slice();
+                // a trailing NUL terminates the last string rather than starting one
+                if units.last() == Some(&0) {
+                    units = &units[..units.len() - 1];
+                }
+                if units.is_empty() {
+                    Vec::new()
+                } else {
+                    units
+                        .split(|c| *c == 0)
+                        .map(|s| XmlValue::String(String::from_utf16_lossy(s)))
+                        .collect()
+                }
+            }
+            STRING_TYPE => data
+                .strip_suffix(&[0])
+                .unwrap_or(data)
+                .split(|c| *c == 0)
+                .filter(|_| !data.is_empty())
+                .map(|s| XmlValue::AnsiString(String::from_utf8_lossy(s).into_owned()))
+                .collect(),
+            SID_TYPE => {
+                let mut items = Vec::new();
+                let mut rest = data;
+                while !rest.is_empty() {
+                    let size = 8 + 4 * *rest.get(1).ok_or("Truncated SID array")? as usize;
+                    if size > rest.len() {
+                        return Err("Truncated SID array".to_owned());
+                    }
+                    items.push(XmlValue::parse(SID_TYPE, &rest[..size])?);
+                    rest = &rest[size..];
+                }
+                items
+            }
+            // the width of a SIZE_T is not recorded, so arrays that divide into
+            // 8-byte items are taken to be 64-bit
+            SIZE_T_TYPE => {
+                let size = if data.len().is_multiple_of(8) { 8 } else { 4 };
+                if !data.len().is_multiple_of(size) {
+                    return Err(format!("SIZE_T array has invalid size {}", data.len()));
+                }
+                data.chunks_exact(size)
+                    .map(|item| XmlValue::parse(value_type, item))
+                    .collect::<Result<Vec<XmlValue>, String>>()?
+            }
+            _ => {
+                let size = match fixed_size(value_type) {
+                    Some(size) => size,
+                    None => {
+                        return Err(format!(
+                            "Unsupported BinXml array type {:#04x}",
+                            value_type | ARRAY_FLAG
+                        ))
+                    }
+                };
+                if !data.len().is_multiple_of(size) {
+                    return Err(format!(
+                        "Array of type {value_type:#04x} has invalid size {}",
+                        data.len()
+                    ));
+                }
+                data.chunks_exact(size)
+                    .map(|item| XmlValue::parse(value_type, item))
+                    .collect::<Result<Vec<XmlValue>, String>>()?
+            }
+        };
+        Ok(XmlValue::Array(items))
+    }
+
+    /// Whether the value renders as nothing. Optional substitutions with an
+    /// empty value are left out of the output.
+    pub fn is_empty(&self) -> bool {
+        match self {
+            XmlValue::Null => true,
+            XmlValue::String(s) | XmlValue::AnsiString(s) => s.is_empty(),
+            XmlValue::Binary(b) | XmlValue::Sid(b) => b.is_empty(),
+            XmlValue::BinXml(nodes) => nodes.is_empty(),
+            XmlValue::Array(items) => items.is_empty(),
+            _ => false,
+        }
+    }
+}
+
+/// Size of one element of a fixed-size value type
+fn fixed_size(value_type: u8) -> Option<usize> {
+    match value_type {
+        INT8_TYPE | UINT8_TYPE => Some(1),
+        INT16_TYPE | UINT16_TYPE => Some(2),
+        INT32_TYPE | UINT32_TYPE | REAL32_TYPE | BOOL_TYPE | HEX_INT32_TYPE => Some(4),
+        INT64_TYPE | UINT64_TYPE | REAL64_TYPE | FILETIME_TYPE | HEX_INT64_TYPE => Some(8),
+        GUID_TYPE | SYSTEMTIME_TYPE => Some(16),
+        _ => None,
+    }
+}
+
+/// Values render as `EvtRender` prints them. Arrays are comma separated, and
+/// embedded BinXml renders as its XML text, leaving out anything that cannot be
+/// rendered.
+impl fmt::Display for XmlValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            XmlValue::Null => Ok(()),
+            XmlValue::String(s) | XmlValue::AnsiString(s) => write!(f, "{s}"),
+            XmlValue::Int8(v) => write!(f, "{v}"),
+            XmlValue::UInt8(v) => write!(f, "{v}"),
+            XmlValue::Int16(v) => write!(f, "{v}"),
+            XmlValue::UInt16(v) => write!(f, "{v}"),
+            XmlValue::Int32(v) => write!(f, "{v}"),
+            XmlValue::UInt32(v) => write!(f, "{v}"),
+            XmlValue::Int64(v) => write!(f, "{v}"),
+            XmlValue::UInt64(v) => write!(f, "{v}"),
+            XmlValue::Real32(v) => write!(f, "{v}"),
+            XmlValue::Real64(v) => write!(f, "{v}"),
+            XmlValue::Bool(v) => write!(f, "{v}"),
+            XmlValue::Binary(b) => b.iter().try_for_each(|b| write!(f, "{b:02X}")),
+            XmlValue::Guid(g) => write!(f, "{}", format_guid(g)),
+            XmlValue::SizeT32(v) => write!(f, "{v:#010x}"),
+            XmlValue::SizeT64(v) => write!(f, "{v:#018x}"),
+            XmlValue::FileTime(v) => write!(f, "{}", format_filetime(*v)),
+            XmlValue::SystemTime(t) => write!(f, "{}", format_systemtime(t)),
+            XmlValue::Sid(b) => write!(f, "{}", format_sid(b).unwrap_or_default()),
+            XmlValue::HexInt32(v) => write!(f, "{v:#x}"),
+            XmlValue::HexInt64(v) => write!(f, "{v:#x}"),
+            XmlValue::BinXml(nodes) => {
+                let mut out = String::new();
+                crate::evtx::binxml::render_lossy(nodes, &mut out);
+                write!(f, "{out}")
+            }
+            XmlValue::Array(items) => {
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Formats a GUID as `{XXXXXXXX-XXXX-XXXX-XXXX-XXXXXXXXXXXX}`
+pub fn format_guid(b: &[u8]) -> String {
+    format!(
+        "{{{:08X}-{:04X}-{:04X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}}}",
+        u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        u16::from_le_bytes([b[4], b[5]]),
+        u16::from_le_bytes([b[6], b[7]]),
+        b[8],
+        b[9],
+        b[10],
+        b[11],
+        b[12],
+        b[13],
+        b[14],
+        b[15]
+    )
+}
+
+/// Formats a binary SID as `S-1-5-21-...`
+pub fn format_sid(data: &[u8]) -> Result<String, String> {
+    let mut cursor = Cursor::new(data, 0);
+    let revision = cursor.u8()?;
+    let count = cursor.u8()?;
+    let authority = cursor
+        .bytes(6)?
+        .iter()
+        .fold(0u64, |acc, b| (acc << 8) | *b as u64);
+    let mut sid = format!("S-{revision}-{authority}");
+    for _ in 0..count {
+        sid.push_str(&format!("-{}", cursor.u32()?));
+    }
+    Ok(sid)
+}
+
+/// Formats a FILETIME (100ns intervals since 1601-01-01 UTC) as an ISO 8601 timestamp
+/// with 7 fractional digits, e.g. `2019-03-04T12:30:00.1234567Z`
+pub fn format_filetime(filetime: u64) -> String {
+    let ticks = filetime % 10_000_000;
+    let seconds = filetime / 10_000_000;
+    // days between 1601-01-01 and 1970-01-01
+    let days = (seconds / 86_400) as i64 - 134_774;
+    let (year, month, day) = civil_from_days(days);
+    let rem = seconds % 86_400;
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{ticks:07}Z",
+        rem / 3600,
+        (rem / 60) % 60,
+        rem % 60
+    )
+}
+
+/// Parses an ISO 8601 UTC timestamp like those `format_filetime` produces back into
+/// a FILETIME. Up to 7 fractional digits are kept.
+pub fn parse_filetime(text: &str) -> Option<u64> {
+    let text = text.strip_suffix('Z')?;
+    let (date, time) = text.split_once('T')?;
+    let mut date = date.splitn(3, '-').map(|part| part.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+    let (time, fraction) = match time.split_once('.') {
+        Some((time, fraction)) => (time, fraction),
+        None => (time, ""),
+    };
+    let mut time = time.splitn(3, ':').map(|part| part.parse::<u64>().ok());
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 59
+    {
+        return None;
+    }
+    // the years a SYSTEMTIME can hold, which also keeps `days_from_civil` in range
+    if !(1601..=30_827).contains(&year) {
+        return None;
+    }
+    if !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let ticks = format!("{fraction:0<7}")[..7].parse::<u64>().ok()?;
+    // days between 1601-01-01 and 1970-01-01
+    let days = days_from_civil(year, month as u32, day as u32) + 134_774;
+    if days < 0 {
+        return None;
+    }
+    let seconds = (days as u64)
+        .checked_mul(86_400)?
+        .checked_add(hour * 3600 + minute * 60 + second)?;
+    seconds.checked_mul(10_000_000)?.checked_add(ticks)
+}
+
+/// Formats a SYSTEMTIME as an ISO 8601 timestamp with millisecond precision
+pub fn format_systemtime(t: &[u16; 8]) -> String {
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        t[0], t[1], t[3], t[4], t[5], t[6], t[7]
+    )
+}
+
+/// Converts days since 1970-01-01 into a (year, month, day) civil date
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Converts a civil date into days since 1970-01-01
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Escapes text for use in XML content or a single-quoted attribute
+pub fn escape_xml(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '\'' => out.push_str("&apos;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evtx::binxml::{XmlAttribute, XmlElement};
+
+    fn format_value(value_type: u8, data: &[u8]) -> Result<String, String> {
+        XmlValue::parse(value_type, data).map(|value| value.to_string())
+    }
+
+    #[test]
+    fn scalars() {
+        assert_eq!(
+            format_value(INT16_TYPE, &(-5i16).to_le_bytes()).unwrap(),
+            "-5"
+        );
+        assert_eq!(
+            format_value(UINT32_TYPE, &4624u32.to_le_bytes()).unwrap(),
+            "4624"
+        );
+        assert_eq!(
+            format_value(BOOL_TYPE, &1u32.to_le_bytes()).unwrap(),
+            "true"
+        );
+        assert_eq!(
+            format_value(HEX_INT64_TYPE, &0x8020_0000_0000_0000u64.to_le_bytes()).unwrap(),
+            "0x8020000000000000"
+        );
+        assert_eq!(
+            format_value(HEX_INT32_TYPE, &0x10u32.to_le_bytes()).unwrap(),
+            "0x10"
+        );
+        assert_eq!(format_value(BINARY_TYPE, &[0xde, 0xad]).unwrap(), "DEAD");
+        assert_eq!(
+            format_value(SIZE_T_TYPE, &0x1234u32.to_le_bytes()).unwrap(),
+            "0x00001234"
+        );
+        assert_eq!(
+            format_value(SIZE_T_TYPE, &0x1234u64.to_le_bytes()).unwrap(),
+            "0x0000000000001234"
+        );
+        assert_eq!(
+            format_value(
+                SIZE_T_TYPE | ARRAY_FLAG,
+                &[1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0]
+            )
+            .unwrap(),
+            "0x00000001,0x00000002,0x00000003"
+        );
+        assert!(format_value(SIZE_T_TYPE | ARRAY_FLAG, &[1, 0, 0]).is_err());
+        assert!(format_value(UINT64_TYPE, &[1, 2]).is_err());
+        assert!(format_value(0x30, &[]).is_err());
+    }
+
+    #[test]
+    fn guid_sid_times() {
+        let guid = [
+            0x25, 0x96, 0x84, 0x54, 0x78, 0x54, 0x94, 0x49, 0xa5, 0xba, 0x3e, 0x3b, 0x03, 0x28,
+            0xc3, 0x0d,
+        ];
+        assert_eq!(
+            format_value(GUID_TYPE, &guid).unwrap(),
+            "{54849625-5478-4994-A5BA-3E3B0328C30D}"
+        );
+        let sid = [1, 1, 0, 0, 0, 0, 0, 5, 18, 0, 0, 0];
+        assert_eq!(format_value(SID_TYPE, &sid).unwrap(), "S-1-5-18");
+        assert!(format_value(SID_TYPE, &sid[..10]).is_err());
+        assert_eq!(format_filetime(0), "1601-01-01T00:00:00.0000000Z");
+        assert_eq!(
+            format_filetime(132_000_000_001_234_567),
+            "2019-04-17T18:40:00.1234567Z"
+        );
+        assert_eq!(
+            parse_filetime("2019-04-17T18:40:00.1234567Z"),
+            Some(132_000_000_001_234_567)
+        );
+        assert_eq!(parse_filetime("1601-01-01T00:00:00Z"), Some(0));
+        assert_eq!(
+            parse_filetime("2019-04-17T18:40:00.5Z"),
+            Some(132_000_000_005_000_000)
+        );
+        assert_eq!(parse_filetime("2019-04-17 18:40:00Z"), None);
+        assert_eq!(parse_filetime("2019-04-17T18:40:60Z"), None);
+        assert_eq!(parse_filetime("99999-01-01T00:00:00Z"), None);
+        assert_eq!(parse_filetime("9223372036854775807-01-01T00:00:00Z"), None);
+        assert_eq!(parse_filetime("1600-12-31T23:59:59Z"), None);
+        assert!(parse_filetime("30827-12-31T23:59:59.9999999Z").is_some());
+        let systemtime = [2019u16, 4, 3, 17, 18, 40, 0, 123]
+            .iter()
+            .flat_map(|v| v.to_le_bytes().to_vec())
+            .collect::<Vec<u8>>();
+        assert_eq!(
+            format_value(SYSTEMTIME_TYPE, &systemtime).unwrap(),
+            "2019-04-17T18:40:00.123Z"
+        );
+    }
+
+    #[test]
+    fn arrays() {
+        let strings = "one\0two\0"
+            .encode_utf16()
+            .flat_map(|c| c.to_le_bytes().to_vec())
+            .collect::<Vec<u8>>();
+        assert_eq!(
+            XmlValue::parse(WSTRING_TYPE | ARRAY_FLAG, &strings).unwrap(),
+            XmlValue::Array(vec![
+                XmlValue::String("one".to_owned()),
+                XmlValue::String("two".to_owned())
+            ])
+        );
+        assert_eq!(
+            format_value(HEX_INT32_TYPE | ARRAY_FLAG, &[1, 0, 0, 0, 0xff, 0, 0, 0]).unwrap(),
+            "0x1,0xff"
+        );
+        let sids = [
+            1u8, 1, 0, 0, 0, 0, 0, 5, 18, 0, 0, 0, 1, 2, 0, 0, 0, 0, 0, 5, 32, 0, 0, 0, 0x20, 2, 0,
+            0,
+        ];
+        assert_eq!(
+            format_value(SID_TYPE | ARRAY_FLAG, &sids).unwrap(),
+            "S-1-5-18,S-1-5-32-544"
+        );
+        assert!(format_value(UINT16_TYPE | ARRAY_FLAG, &[1, 2, 3]).is_err());
+        assert!(XmlValue::parse(WSTRING_TYPE | ARRAY_FLAG, &[])
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn embedded_binxml() {
+        let element = |name: &str, attributes, children| {
+            XmlNode::Element(XmlElement {
+                name: name.to_owned(),
+                attributes,
+                children,
+            })
+        };
+        let value = XmlValue::BinXml(vec![element(
+            "Data",
+            vec![XmlAttribute {
+                name: "Name".to_owned(),
+                value: vec![XmlNode::Substitution {
+                    index: 1,
+                    optional: false,
+                }],
+            }],
+            vec![
+                XmlNode::Text("a < b".to_owned()),
+                XmlNode::Substitution {
+                    index: 0,
+                    optional: false,
+                },
+            ],
+        )]);
+        assert_eq!(value.to_string(), "<Data>a &lt; b</Data>");
+        let mut nested = XmlNode::Text("deep".to_owned());
+        for _ in 0..200 {
+            nested = element("E", vec![], vec![nested]);
+        }
+        assert!(XmlValue::BinXml(vec![nested])
+            .to_string()
+            .starts_with("<E>"));
+    }
+}
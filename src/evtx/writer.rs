@@ -0,0 +1,656 @@
+//! Writing `.evtx` files
+//!
+//! Each event is stored the way Windows stores it: a template holding the
+//! event's structure, defined inline the first time it is used in a chunk, and an
+//! instance supplying the text of every attribute and element as values. Names go
+//! into the chunk's common string table and templates into its template table.
+//! Values are written as strings, so events read back render the same XML.
+
+use crate::event::Event;
+use crate::evtx::binxml::{XmlElement, XmlNode};
+use crate::evtx::chunk::{CHUNK_HEADER_SIZE, CHUNK_SIGNATURE, CHUNK_SIZE};
+use crate::evtx::crc32::{self, Crc32};
+use crate::evtx::header::{FileFlags, FILE_HEADER_SIZE, FILE_SIGNATURE};
+use crate::evtx::record::RECORD_SIGNATURE;
+use crate::evtx::value::{parse_filetime, XmlValue, WSTRING_TYPE};
+use crate::evtx::xml::entity;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Seconds between 1601-01-01 and 1970-01-01
+const UNIX_EPOCH_SECONDS: u64 = 11_644_473_600;
+
+/// Writes events to an `.evtx` file
+///
+/// The file header is marked dirty until `finish` is called, like a log Windows
+/// still has open.
+///
+/// ```rust,no_run
+/// use win_event_log::prelude::*;
+///
+/// let mut writer = EvtxWriter::create("subset.evtx").unwrap();
+/// for event in EvtxReader::open("Security.evtx").unwrap() {
+///     if event.to_string().contains("<EventID>4625</EventID>") {
+///         writer.write(&event).unwrap();
+///     }
+/// }
+/// writer.finish().unwrap();
+/// ```
+pub struct EvtxWriter<W> {
+    writer: W,
+    chunk: ChunkWriter,
+    chunk_count: u16,
+    next_record_id: u64,
+}
+
+impl EvtxWriter<File> {
+    /// Creates the file at `path`, replacing any existing file
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<EvtxWriter<File>, String> {
+        let path = path.as_ref();
+        let file = File::create(path)
+            .map_err(|err| format!("Could not create {}: {err}", path.display()))?;
+        EvtxWriter::new(file)
+    }
+}
+
+impl<W: Write + Seek> EvtxWriter<W> {
+    pub fn new(mut writer: W) -> Result<EvtxWriter<W>, String> {
+        writer
+            .seek(SeekFrom::Start(0))
+            .and_then(|_| writer.write_all(&file_header(0, 1, FileFlags::Dirty)))
+            .map_err(|err| format!("Could not write evtx file header: {err}"))?;
+        Ok(EvtxWriter {
+            writer,
+            chunk: ChunkWriter::new(1),
+            chunk_count: 0,
+            next_record_id: 1,
+        })
+    }
+
+    /// Writes an event, returning the record ID it was given
+    pub fn write(&mut self, event: &Event) -> Result<u64, String> {
+        self.write_xml(&event.0)
+    }
+
+    /// Writes an event rendered as XML, returning the record ID it was given
+    pub fn write_xml(&mut self, xml: &str) -> Result<u64, String> {
+        self.write_nodes(&XmlNode::parse(xml)?)
+    }
+
+    /// Writes an event given as a node tree, such as one decoded by `Chunk::decode`,
+    /// returning the record ID it was given
+    pub fn write_nodes(&mut self, nodes: &[XmlNode]) -> Result<u64, String> {
+        let nodes = XmlNode::expand(nodes)?;
+        let element = match nodes.as_slice() {
+            [XmlNode::Element(element)] => element,
+            _ => return Err("An event must consist of a single element".to_owned()),
+        };
+        let written = written_time(element);
+        let id = self.next_record_id;
+        if !self.chunk.record(id, written, element)? {
+            // an event too big for an empty chunk fits nowhere, so there is no
+            // point finishing the chunk, which would leave it empty
+            if self.chunk.record_count() == 0 {
+                return Err(format!("Event {id} does not fit in a chunk"));
+            }
+            self.flush_chunk()?;
+            if !self.chunk.record(id, written, element)? {
+                return Err(format!("Event {id} does not fit in a chunk"));
+            }
+        }
+        self.next_record_id += 1;
+        Ok(id)
+    }
+
+    /// Writes the last chunk and the final file header, returning the writer
+    pub fn finish(mut self) -> Result<W, String> {
+        if self.chunk.record_count() > 0 {
+            self.flush_chunk()?;
+        }
+        let header = file_header(self.chunk_count, self.next_record_id, FileFlags::empty());
+        self.writer
+            .seek(SeekFrom::Start(0))
+            .and_then(|_| self.writer.write_all(&header))
+            .and_then(|_| self.writer.flush())
+            .map_err(|err| format!("Could not write evtx file header: {err}"))?;
+        Ok(self.writer)
+    }
+
+    fn flush_chunk(&mut self) -> Result<(), String> {
+        if self.chunk_count == u16::MAX {
+            return Err("Too many chunks for one evtx file".to_owned());
+        }
+        let chunk = std::mem::replace(&mut self.chunk, ChunkWriter::new(self.next_record_id));
+        let offset = FILE_HEADER_SIZE as u64 + self.chunk_count as u64 * CHUNK_SIZE as u64;
+        self.writer
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| self.writer.write_all(&chunk.finish()))
+            .map_err(|err| format!("Could not write chunk {}: {err}", self.chunk_count))?;
+        self.chunk_count += 1;
+        Ok(())
+    }
+}
+
+/// The time the event was created, falling back to now
+fn written_time(event: &XmlElement) -> u64 {
+    event
+        .child("System")
+        .and_then(|system| system.child("TimeCreated"))
+        .and_then(|time| time.attribute_value("SystemTime"))
+        .and_then(|time| parse_filetime(&time))
+        .unwrap_or_else(|| {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            (now.as_secs() + UNIX_EPOCH_SECONDS) * 10_000_000 + now.subsec_nanos() as u64 / 100
+        })
+}
+
+fn file_header(chunk_count: u16, next_record_id: u64, flags: FileFlags) -> Vec<u8> {
+    let mut data = vec![0u8; FILE_HEADER_SIZE];
+    data[..8].copy_from_slice(FILE_SIGNATURE);
+    data[16..24].copy_from_slice(&(chunk_count.saturating_sub(1) as u64).to_le_bytes());
+    data[24..32].copy_from_slice(&next_record_id.to_le_bytes());
+    data[32..36].copy_from_slice(&128u32.to_le_bytes());
+    data[36..38].copy_from_slice(&1u16.to_le_bytes());
+    data[38..40].copy_from_slice(&3u16.to_le_bytes());
+    data[40..42].copy_from_slice(&(FILE_HEADER_SIZE as u16).to_le_bytes());
+    data[42..44].copy_from_slice(&chunk_count.to_le_bytes());
+    data[120..124].copy_from_slice(&flags.bits().to_le_bytes());
+    let checksum = crc32::checksum(&data[..120]);
+    data[124..128].copy_from_slice(&checksum.to_le_bytes());
+    data
+}
+
+/// The chunk being filled
+struct ChunkWriter {
+    data: Vec<u8>,
+    first_record_id: u64,
+    last_record_id: u64,
+    last_record_offset: usize,
+    /// Offsets of the names already in the chunk
+    names: HashMap<String, u32>,
+    string_table: [u32; 64],
+    /// Offsets of the template definitions already in the chunk, by shape
+    templates: HashMap<String, u32>,
+    template_table: [u32; 32],
+}
+
+impl ChunkWriter {
+    fn new(first_record_id: u64) -> ChunkWriter {
+        ChunkWriter {
+            data: vec![0u8; CHUNK_HEADER_SIZE],
+            first_record_id,
+            last_record_id: first_record_id - 1,
+            last_record_offset: 0,
+            names: HashMap::new(),
+            string_table: [0; 64],
+            templates: HashMap::new(),
+            template_table: [0; 32],
+        }
+    }
+
+    fn record_count(&self) -> u64 {
+        self.last_record_id + 1 - self.first_record_id
+    }
+
+    fn u8(&mut self, v: u8) {
+        self.data.push(v);
+    }
+
+    fn u16(&mut self, v: u16) {
+        self.data.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.data.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn u64(&mut self, v: u64) {
+        self.data.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn patch_u32(&mut self, at: usize, v: u32) {
+        self.data[at..at + 4].copy_from_slice(&v.to_le_bytes());
+    }
+
+    /// Appends a record, returning `false` without changing the chunk if it does
+    /// not fit
+    fn record(&mut self, id: u64, written: u64, event: &XmlElement) -> Result<bool, String> {
+        let mut shape = String::new();
+        let mut values = Vec::new();
+        flatten(event, &mut shape, &mut values)?;
+        let values = values
+            .iter()
+            .map(|value| {
+                let bytes = value
+                    .encode_utf16()
+                    .flat_map(u16::to_le_bytes)
+                    .collect::<Vec<u8>>();
+                if bytes.len() > u16::MAX as usize {
+                    return Err(format!("Value of {} bytes is too long", bytes.len()));
+                }
+                Ok(bytes)
+            })
+            .collect::<Result<Vec<Vec<u8>>, String>>()?;
+
+        let start = self.data.len();
+        let (string_table, template_table) = (self.string_table, self.template_table);
+        self.data.extend_from_slice(RECORD_SIGNATURE);
+        self.u32(0);
+        self.u64(id);
+        self.u64(written);
+        self.data.extend_from_slice(&[0x0f, 0x01, 0x01, 0x00]);
+        self.template_instance(event, shape, &values)?;
+        let size = (self.data.len() + 4 - start) as u32;
+        self.u32(size);
+        self.patch_u32(start + 4, size);
+
+        if self.data.len() > CHUNK_SIZE {
+            // forget the names and templates this record defined, as their
+            // definitions are cut off with it
+            self.data.truncate(start);
+            self.string_table = string_table;
+            self.template_table = template_table;
+            self.names.retain(|_, offset| (*offset as usize) < start);
+            self.templates
+                .retain(|_, offset| (*offset as usize) < start);
+            return Ok(false);
+        }
+        self.last_record_id = id;
+        self.last_record_offset = start;
+        Ok(true)
+    }
+
+    fn template_instance(
+        &mut self,
+        event: &XmlElement,
+        shape: String,
+        values: &[Vec<u8>],
+    ) -> Result<(), String> {
+        self.u8(0x0c);
+        self.u8(0x01);
+        let guid = template_guid(&shape);
+        let id = u32::from_le_bytes([guid[0], guid[1], guid[2], guid[3]]);
+        self.u32(id);
+        match self.templates.get(&shape) {
+            Some(offset) => {
+                let offset = *offset;
+                self.u32(offset);
+            }
+            None => {
+                let offset = self.data.len() as u32 + 4;
+                self.u32(offset);
+                let bucket = id as usize % self.template_table.len();
+                self.u32(self.template_table[bucket]);
+                self.data.extend_from_slice(&guid);
+                let size_at = self.data.len();
+                self.u32(0);
+                self.data.extend_from_slice(&[0x0f, 0x01, 0x01, 0x00]);
+                self.element(event, &mut 0)?;
+                self.u8(0x00);
+                let size = self.data.len() - size_at - 4;
+                self.patch_u32(size_at, size as u32);
+                self.template_table[bucket] = offset;
+                self.templates.insert(shape, offset);
+            }
+        }
+        self.u32(values.len() as u32);
+        for value in values {
+            self.u16(value.len() as u16);
+            self.u8(WSTRING_TYPE);
+            self.u8(0);
+        }
+        for value in values {
+            self.data.extend_from_slice(value);
+        }
+        self.u8(0x00);
+        Ok(())
+    }
+
+    /// Writes an element of a template, numbering its substitutions from `index`
+    /// in the same order as `flatten`
+    fn element(&mut self, element: &XmlElement, index: &mut u16) -> Result<(), String> {
+        let has_attributes = !element.attributes.is_empty();
+        self.u8(if has_attributes { 0x41 } else { 0x01 });
+        self.u16(0xffff);
+        let size_at = self.data.len();
+        self.u32(0);
+        self.name(&element.name);
+        if has_attributes {
+            let list_at = self.data.len();
+            self.u32(0);
+            for (i, attribute) in element.attributes.iter().enumerate() {
+                let more = i + 1 < element.attributes.len();
+                self.u8(if more { 0x46 } else { 0x06 });
+                self.name(&attribute.name);
+                self.substitution(index);
+            }
+            let size = self.data.len() - list_at - 4;
+            self.patch_u32(list_at, size as u32);
+        }
+        let children = content(&element.children)?;
+        if children.is_empty() {
+            self.u8(0x03);
+        } else {
+            self.u8(0x02);
+            for child in children {
+                match child {
+                    Content::Element(element) => self.element(element, index)?,
+                    Content::Text(_) => self.substitution(index),
+                }
+            }
+            self.u8(0x04);
+        }
+        let size = self.data.len() - size_at - 4;
+        self.patch_u32(size_at, size as u32);
+        Ok(())
+    }
+
+    fn substitution(&mut self, index: &mut u16) {
+        self.u8(0x0d);
+        self.u16(*index);
+        self.u8(WSTRING_TYPE);
+        *index += 1;
+    }
+
+    /// Writes a reference to a common string, defining it inline on first use
+    fn name(&mut self, name: &str) {
+        if let Some(offset) = self.names.get(name) {
+            let offset = *offset;
+            self.u32(offset);
+            return;
+        }
+        let offset = self.data.len() as u32 + 4;
+        self.u32(offset);
+        let hash = name_hash(name);
+        let bucket = hash as usize % self.string_table.len();
+        self.u32(self.string_table[bucket]);
+        self.u16(hash);
+        self.u16(name.encode_utf16().count() as u16);
+        for unit in name.encode_utf16() {
+            self.u16(unit);
+        }
+        self.u16(0);
+        self.string_table[bucket] = offset;
+        self.names.insert(name.to_owned(), offset);
+    }
+
+    /// Fills in the header and tables, returning the complete chunk
+    fn finish(mut self) -> Vec<u8> {
+        let free_space = self.data.len();
+        self.data.resize(CHUNK_SIZE, 0);
+        let mut header = Vec::with_capacity(CHUNK_HEADER_SIZE);
+        header.extend_from_slice(CHUNK_SIGNATURE);
+        for v in [
+            self.first_record_id,
+            self.last_record_id,
+            self.first_record_id,
+            self.last_record_id,
+        ] {
+            header.extend_from_slice(&v.to_le_bytes());
+        }
+        for v in [128, self.last_record_offset as u32, free_space as u32] {
+            header.extend_from_slice(&v.to_le_bytes());
+        }
+        header.extend_from_slice(
+            &crc32::checksum(&self.data[CHUNK_HEADER_SIZE..free_space]).to_le_bytes(),
+        );
+        header.resize(128, 0);
+        for offset in self.string_table.iter().chain(self.template_table.iter()) {
+            header.extend_from_slice(&offset.to_le_bytes());
+        }
+        self.data[..CHUNK_HEADER_SIZE].copy_from_slice(&header);
+        let checksum = Crc32::new()
+            .update(&self.data[..120])
+            .update(&self.data[128..CHUNK_HEADER_SIZE])
+            .finish();
+        self.data[124..128].copy_from_slice(&checksum.to_le_bytes());
+        self.data
+    }
+}
+
+/// A child of an element, with adjacent text merged
+enum Content<'a> {
+    Element(&'a XmlElement),
+    Text(String),
+}
+
+fn content(nodes: &[XmlNode]) -> Result<Vec<Content<'_>>, String> {
+    let mut out = Vec::new();
+    let mut text = String::new();
+    for node in nodes {
+        match node {
+            XmlNode::Element(element) => {
+                if !text.is_empty() {
+                    out.push(Content::Text(std::mem::take(&mut text)));
+                }
+                out.push(Content::Element(element));
+            }
+            node => push_text(node, &mut text)?,
+        }
+    }
+    if !text.is_empty() {
+        out.push(Content::Text(text));
+    }
+    Ok(out)
+}
+
+/// The text of an attribute value
+fn attribute_text(nodes: &[XmlNode]) -> Result<String, String> {
+    let mut text = String::new();
+    for node in nodes {
+        push_text(node, &mut text)?;
+    }
+    Ok(text)
+}
+
+/// Appends the text a node other than an element stands for, failing on nodes
+/// that cannot be written as a string value
+fn push_text(node: &XmlNode, text: &mut String) -> Result<(), String> {
+    match node {
+        XmlNode::Text(s) | XmlNode::CData(s) => text.push_str(s),
+        XmlNode::Value(XmlValue::BinXml(nodes)) => text.push_str(&XmlNode::to_xml(nodes)?),
+        XmlNode::Value(value) => text.push_str(&value.to_string()),
+        XmlNode::CharRef(c) => text.extend(char::from_u32(*c as u32)),
+        XmlNode::EntityRef(name) => {
+            text.push(entity(name).ok_or_else(|| format!("Unknown entity `&{name};`"))?)
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Describes the structure of `element` in `shape`, which identifies its template,
+/// and collects the text it holds in `values`
+fn flatten(
+    element: &XmlElement,
+    shape: &mut String,
+    values: &mut Vec<String>,
+) -> Result<(), String> {
+    shape.push('<');
+    shape.push_str(&element.name);
+    for attribute in element.attributes.iter() {
+        shape.push(' ');
+        shape.push_str(&attribute.name);
+        values.push(attribute_text(&attribute.value)?);
+    }
+    shape.push('>');
+    for child in content(&element.children)? {
+        match child {
+            Content::Element(element) => flatten(element, shape, values)?,
+            Content::Text(text) => {
+                shape.push('$');
+                values.push(text);
+            }
+        }
+    }
+    shape.push_str("</>");
+    Ok(())
+}
+
+/// The hash stored with each common string
+fn name_hash(name: &str) -> u16 {
+    name.encode_utf16().fold(0u32, |hash, unit| {
+        hash.wrapping_mul(65599).wrapping_add(unit as u32)
+    }) as u16
+}
+
+/// A GUID derived from the template's shape, so the same structure always gets
+/// the same identifier
+fn template_guid(shape: &str) -> [u8; 16] {
+    let fnv = |seed: u64| {
+        shape.bytes().fold(seed, |hash, b| {
+            (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        })
+    };
+    let mut guid = [0u8; 16];
+    guid[..8].copy_from_slice(&fnv(0xcbf2_9ce4_8422_2325).to_le_bytes());
+    guid[8..].copy_from_slice(&fnv(0x8422_2325_cbf2_9ce4).to_le_bytes());
+    guid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EvtxWriter;
+    use crate::evtx::binxml::{XmlElement, XmlNode};
+    use crate::evtx::testdata::{self, SAMPLE_EVENTS};
+    use crate::evtx::value::XmlValue;
+    use crate::evtx::EvtxReader;
+    use std::io::Cursor;
+
+    fn read_back(file: Vec<u8>) -> Vec<String> {
+        EvtxReader::new(Cursor::new(file))
+            .unwrap()
+            .events()
+            .map(|event| event.unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut writer = EvtxWriter::new(Cursor::new(Vec::new())).unwrap();
+        for (i, event) in SAMPLE_EVENTS.iter().enumerate() {
+            assert_eq!(writer.write_xml(event).unwrap(), i as u64 + 1);
+        }
+        let file = writer.finish().unwrap().into_inner();
+        assert_eq!(read_back(file.clone()), SAMPLE_EVENTS);
+
+        let mut reader = EvtxReader::new(Cursor::new(file)).unwrap();
+        assert!(!reader.header().is_dirty());
+        assert_eq!(reader.header().next_record_id, 4);
+        let chunk = reader.chunk(0).unwrap();
+        assert_eq!(chunk.templates().unwrap().len(), 3);
+        assert!(chunk.strings().values().any(|name| name == "Provider"));
+    }
+
+    #[test]
+    fn decoded_nodes() {
+        let mut reader = EvtxReader::new(Cursor::new(testdata::sample_file())).unwrap();
+        let chunk = reader.chunk(0).unwrap();
+        let mut writer = EvtxWriter::new(Cursor::new(Vec::new())).unwrap();
+        for record in chunk.records() {
+            writer
+                .write_nodes(&chunk.decode(&record.unwrap()).unwrap())
+                .unwrap();
+        }
+        assert_eq!(
+            read_back(writer.finish().unwrap().into_inner()),
+            SAMPLE_EVENTS
+        );
+    }
+
+    #[test]
+    fn many_chunks() {
+        let events = (0..3000)
+            .map(|i| {
+                format!(
+                    "<Event><System><EventID>{i}</EventID>\
+                     <TimeCreated SystemTime='2019-04-17T18:40:00.0000000Z'/></System>\
+                     <EventData><Data Name='Index'>{}</Data></EventData></Event>",
+                    "x".repeat(i % 50 + 1)
+                )
+            })
+            .collect::<Vec<String>>();
+        let mut writer = EvtxWriter::new(Cursor::new(Vec::new())).unwrap();
+        for event in events.iter() {
+            writer.write_xml(event).unwrap();
+        }
+        let file = writer.finish().unwrap().into_inner();
+        let mut reader = EvtxReader::new(Cursor::new(file.clone())).unwrap();
+        assert!(reader.header().chunk_count > 1);
+        for chunk in reader.chunks() {
+            let chunk = chunk.unwrap();
+            assert_eq!(chunk.templates().unwrap().len(), 1);
+            let first = chunk.records().next().unwrap().unwrap();
+            assert_eq!(first.record_id, chunk.header.first_record_id);
+            assert_eq!(first.written, 132_000_000_000_000_000);
+        }
+        assert_eq!(read_back(file), events);
+    }
+
+    #[test]
+    fn rejects_non_events() {
+        let mut writer = EvtxWriter::new(Cursor::new(Vec::new())).unwrap();
+        assert!(writer.write_xml("just text").is_err());
+        assert!(writer.write_xml("<A/><B/>").is_err());
+        assert!(writer.write_xml("<A>").is_err());
+    }
+
+    #[test]
+    fn references_and_values() {
+        let event = |children| {
+            vec![XmlNode::Element(XmlElement {
+                name: "Event".to_owned(),
+                attributes: vec![],
+                children,
+            })]
+        };
+        let mut writer = EvtxWriter::new(Cursor::new(Vec::new())).unwrap();
+        writer
+            .write_nodes(&event(vec![
+                XmlNode::Text("a ".to_owned()),
+                XmlNode::EntityRef("amp".to_owned()),
+                XmlNode::CharRef(0x20),
+                XmlNode::EntityRef("lt".to_owned()),
+            ]))
+            .unwrap();
+        assert!(writer
+            .write_nodes(&event(vec![XmlNode::EntityRef("nbsp".to_owned())]))
+            .unwrap_err()
+            .contains("nbsp"));
+        let embedded = XmlValue::BinXml(vec![XmlNode::Substitution {
+            index: 0,
+            optional: false,
+        }]);
+        assert!(writer
+            .write_nodes(&event(vec![XmlNode::Value(embedded)]))
+            .is_err());
+        assert_eq!(
+            read_back(writer.finish().unwrap().into_inner()),
+            ["<Event>a &amp; &lt;</Event>"]
+        );
+    }
+
+    #[test]
+    fn oversized_event() {
+        // each value fits in a record, but not both in one chunk
+        let big = format!(
+            "<Event><Big>{}</Big><Bigger>{}</Bigger></Event>",
+            "x".repeat(30_000),
+            "y".repeat(30_000)
+        );
+        let mut writer = EvtxWriter::new(Cursor::new(Vec::new())).unwrap();
+        assert!(writer.write_xml(&big).unwrap_err().contains("does not fit"));
+        assert_eq!(writer.write_xml(SAMPLE_EVENTS[0]).unwrap(), 1);
+        assert!(writer.write_xml(&big).is_err());
+        assert_eq!(writer.write_xml(SAMPLE_EVENTS[1]).unwrap(), 2);
+        let file = writer.finish().unwrap().into_inner();
+        let reader = EvtxReader::new(Cursor::new(file.clone())).unwrap();
+        assert_eq!(reader.header().chunk_count, 2);
+        assert_eq!(read_back(file), &SAMPLE_EVENTS[..2]);
+    }
+}
@@ -0,0 +1,267 @@
+//! A small XML parser for rendered events, so they can be turned back into
+//! `XmlNode` trees
+//!
+//! It understands what `EvtRender` and this crate produce: elements, attributes,
+//! text, character and entity references, CDATA sections and processing
+//! instructions. The XML declaration, comments and DTDs are skipped, as is
+//! whitespace between elements.
+
+use crate::evtx::binxml::{XmlAttribute, XmlElement, XmlNode, MAX_DEPTH};
+
+impl XmlNode {
+    /// Parses XML text into a plain tree of elements and text
+    pub fn parse(xml: &str) -> Result<Vec<XmlNode>, String> {
+        let mut parser = Parser { xml, pos: 0 };
+        parser.content(None, 0)
+    }
+}
+
+struct Parser<'a> {
+    xml: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.xml[self.pos..]
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("Invalid XML at offset {}: {message}", self.pos)
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        if !self.rest().starts_with(token) {
+            return Err(self.error(&format!("expected `{token}`")));
+        }
+        self.pos += token.len();
+        Ok(())
+    }
+
+    /// Consumes everything up to and including `end`, returning what came before it
+    fn until(&mut self, end: &str) -> Result<&'a str, String> {
+        let rest = self.rest();
+        match rest.find(end) {
+            Some(found) => {
+                self.pos += found + end.len();
+                Ok(&rest[..found])
+            }
+            None => Err(self.error(&format!("missing `{end}`"))),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| c.is_whitespace() || "/>=".contains(c))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.error("expected a name"));
+        }
+        self.pos += len;
+        Ok(rest[..len].to_owned())
+    }
+
+    /// Parses nodes until the end tag of `parent`, or the end of the input.
+    /// `depth` counts the elements open around them.
+    fn content(&mut self, parent: Option<&str>, depth: usize) -> Result<Vec<XmlNode>, String> {
+        let mut nodes = Vec::new();
+        let mut text = String::new();
+        loop {
+            let rest = self.rest();
+            if rest.is_empty() {
+                if let Some(parent) = parent {
+                    return Err(self.error(&format!("`{parent}` is not closed")));
+                }
+                break;
+            }
+            if rest.starts_with("</") {
+                self.pos += 2;
+                let name = self.name()?;
+                if Some(name.as_str()) != parent {
+                    return Err(self.error(&format!("unexpected end tag `{name}`")));
+                }
+                self.skip_whitespace();
+                self.expect(">")?;
+                break;
+            } else if rest.starts_with("<!--") {
+                self.pos += 4;
+                self.until("-->")?;
+            } else if rest.starts_with("<![CDATA[") {
+                self.pos += 9;
+                flush_text(&mut text, &mut nodes);
+                nodes.push(XmlNode::CData(self.until("]]>")?.to_owned()));
+            } else if rest.starts_with("<?") {
+                self.pos += 2;
+                flush_text(&mut text, &mut nodes);
+                let instruction = self.until("?>")?;
+                let (target, data) = match instruction.split_once(char::is_whitespace) {
+                    Some((target, data)) => (target, data.trim()),
+                    None => (instruction, ""),
+                };
+                if !target.eq_ignore_ascii_case("xml") {
+                    nodes.push(XmlNode::ProcessingInstruction {
+                        target: target.to_owned(),
+                        data: data.to_owned(),
+                    });
+                }
+            } else if rest.starts_with("<!") {
+                self.until(">")?;
+            } else if rest.starts_with('<') {
+                self.pos += 1;
+                flush_text(&mut text, &mut nodes);
+                nodes.push(XmlNode::Element(self.element(depth)?));
+            } else if rest.starts_with('&') {
+                self.reference(&mut text)?;
+            } else {
+                let len = rest.find(['<', '&']).unwrap_or(rest.len());
+                text.push_str(&rest[..len]);
+                self.pos += len;
+            }
+        }
+        flush_text(&mut text, &mut nodes);
+        Ok(nodes)
+    }
+
+    fn element(&mut self, depth: usize) -> Result<XmlElement, String> {
+        if depth >= MAX_DEPTH {
+            return Err(self.error("elements nested too deep"));
+        }
+        let name = self.name()?;
+        let mut attributes = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("/>") {
+                self.pos += 2;
+                return Ok(XmlElement {
+                    name,
+                    attributes,
+                    children: Vec::new(),
+                });
+            }
+            if self.rest().starts_with('>') {
+                self.pos += 1;
+                let children = self.content(Some(&name), depth + 1)?;
+                return Ok(XmlElement {
+                    name,
+                    attributes,
+                    children,
+                });
+            }
+            let attribute = self.name()?;
+            self.skip_whitespace();
+            self.expect("=")?;
+            self.skip_whitespace();
+            let quote = match self.rest().chars().next() {
+                Some(quote) if quote == '\'' || quote == '"' => quote,
+                _ => return Err(self.error("expected a quoted attribute value")),
+            };
+            self.pos += 1;
+            let mut value = String::new();
+            loop {
+                let rest = self.rest();
+                match rest.find([quote, '&', '<']) {
+                    Some(len) if rest[len..].starts_with(quote) => {
+                        value.push_str(&rest[..len]);
+                        self.pos += len + 1;
+                        break;
+                    }
+                    Some(len) if rest[len..].starts_with('&') => {
+                        value.push_str(&rest[..len]);
+                        self.pos += len;
+                        self.reference(&mut value)?;
+                    }
+                    _ => return Err(self.error("unterminated attribute value")),
+                }
+            }
+            attributes.push(XmlAttribute {
+                name: attribute,
+                value: vec![XmlNode::Text(value)],
+            });
+        }
+    }
+
+    /// Decodes a character or entity reference into `out`
+    fn reference(&mut self, out: &mut String) -> Result<(), String> {
+        self.pos += 1;
+        let start = self.pos;
+        let name = self.until(";")?;
+        let c = match name.strip_prefix('#') {
+            Some(hex) if hex.starts_with('x') => u32::from_str_radix(&hex[1..], 16)
+                .ok()
+                .and_then(char::from_u32),
+            Some(decimal) => decimal.parse::<u32>().ok().and_then(char::from_u32),
+            None => entity(name),
+        };
+        match c {
+            Some(c) => {
+                out.push(c);
+                Ok(())
+            }
+            None => {
+                self.pos = start;
+                Err(self.error(&format!("unknown reference `&{name};`")))
+            }
+        }
+    }
+}
+
+/// The character a predefined entity such as `amp` stands for
+pub(crate) fn entity(name: &str) -> Option<char> {
+    match name {
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "amp" => Some('&'),
+        "apos" => Some('\''),
+        "quot" => Some('"'),
+        _ => None,
+    }
+}
+
+/// Ends a run of text. Whitespace on its own only separates markup and is dropped.
+fn flush_text(text: &mut String, nodes: &mut Vec<XmlNode>) {
+    if !text.trim().is_empty() {
+        nodes.push(XmlNode::Text(text.clone()));
+    }
+    text.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evtx::binxml::XmlNode;
+    use crate::evtx::testdata::SAMPLE_EVENTS;
+
+    #[test]
+    fn parse_rendered_events() {
+        for event in SAMPLE_EVENTS.iter() {
+            let nodes = XmlNode::parse(event).unwrap();
+            assert_eq!(XmlNode::to_xml(&nodes).unwrap(), *event);
+        }
+    }
+
+    #[test]
+    fn parse_pretty_printed() {
+        let xml = "<?xml version=\"1.0\"?>\n<!-- exported -->\n<Event a=\"x &amp; y\">\n  \
+                   <Data>&#65;&#x42;</Data>\n  <Empty></Empty>\n</Event>\n";
+        let nodes = XmlNode::parse(xml).unwrap();
+        assert_eq!(
+            XmlNode::to_xml(&nodes).unwrap(),
+            "<Event a='x &amp; y'><Data>AB</Data><Empty/></Event>"
+        );
+        assert!(XmlNode::parse("<Event><Data></Event>").is_err());
+        assert!(XmlNode::parse("<Event>&nbsp;</Event>").is_err());
+    }
+
+    #[test]
+    fn deeply_nested() {
+        let nested = |depth: usize| format!("{}{}", "<a>".repeat(depth), "</a>".repeat(depth));
+        assert!(XmlNode::parse(&nested(64)).is_ok());
+        assert!(XmlNode::parse(&nested(65)).is_err());
+        assert!(XmlNode::parse(&nested(200_000)).is_err());
+    }
+}
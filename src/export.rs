@@ -0,0 +1,388 @@
+//! Exporting and clearing logs
+
+use crate::api::{EvtApi, EvtArchiveExportedLog, EvtClearLog, EvtExportLog};
+use crate::error::Error;
+use crate::query_list::{query_path_kind, validate_file_path, PathKind};
+use crate::session::EvtSession;
+use std::ffi::OsStr;
+use std::os::windows::prelude::*;
+use std::path::{Path, PathBuf};
+use std::ptr::{null, null_mut};
+use winapi::um::winevt::{
+    EvtExportLogChannelPath, EvtExportLogFilePath, EvtExportLogOverwrite,
+    EvtExportLogTolerateQueryErrors,
+};
+
+#[derive(Clone, Debug)]
+enum Source {
+    /// A `QueryList` or structured query XML, naming its own channels or files
+    Query(String),
+    /// A single channel and an XPath filter
+    Channel { path: String, xpath: String },
+    /// A single log file and an XPath filter
+    File { path: PathBuf, xpath: String },
+}
+
+/// Saves the events of a channel or log file to an `.evtx` file
+///
+/// With a session, the target path is on the remote machine.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use win_event_log::prelude::*;
+///
+/// ExportLog::channel("Security", "*[System[(EventID = 4624)]]")
+///     .overwrite(true)
+///     .to(r"C:\cases\logons.evtx")
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct ExportLog {
+    source: Source,
+    overwrite: bool,
+    tolerate_query_errors: bool,
+    archive: Option<u32>,
+    session: Option<EvtSession>,
+}
+
+impl<'a> ExportLog {
+    /// Exports the events a structured query, such as a `QueryList`, selects.
+    /// Its paths must be all channels or all `file://` paths.
+    pub fn query<T: Into<String>>(query: T) -> ExportLog {
+        ExportLog::new(Source::Query(query.into()))
+    }
+
+    /// Exports the events of `channel` selected by the XPath expression
+    /// `xpath`. Use `*` for every event.
+    pub fn channel<C: Into<String>, X: Into<String>>(channel: C, xpath: X) -> ExportLog {
+        ExportLog::new(Source::Channel {
+            path: channel.into(),
+            xpath: xpath.into(),
+        })
+    }
+
+    /// Exports the events of an exported or archived log file selected by the
+    /// XPath expression `xpath`
+    pub fn file<P: AsRef<Path>, X: Into<String>>(path: P, xpath: X) -> ExportLog {
+        ExportLog::new(Source::File {
+            path: path.as_ref().to_path_buf(),
+            xpath: xpath.into(),
+        })
+    }
+
+    fn new(source: Source) -> ExportLog {
+        ExportLog {
+            source,
+            overwrite: false,
+            tolerate_query_errors: false,
+            archive: None,
+            session: None,
+        }
+    }
+
+    /// Replaces the target file if it already exists, instead of failing
+    pub fn overwrite(&'a mut self, overwrite: bool) -> &'a mut Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// Exports what it can when some of a query's paths cannot be read, instead
+    /// of failing
+    pub fn tolerate_query_errors(&'a mut self, tolerate: bool) -> &'a mut Self {
+        self.tolerate_query_errors = tolerate;
+        self
+    }
+
+    /// Adds the events' messages to the exported file, localized for the LCID
+    /// `locale` (such as 1033 for US English), so it can be read on machines
+    /// where the publishers are not installed
+    pub fn archive(&'a mut self, locale: u32) -> &'a mut Self {
+        self.archive = Some(locale);
+        self
+    }
+
+    /// Exports from a remote machine instead of the local one
+    pub fn session(&'a mut self, session: &EvtSession) -> &'a mut Self {
+        self.session = Some(session.clone());
+        self
+    }
+
+    pub fn build(&self) -> ExportLog {
+        self.clone()
+    }
+
+    /// Runs the export, writing the events to `target`
+    pub fn to<P: AsRef<Path>>(&self, target: P) -> Result<(), Error> {
+        let flags = self.flags()?;
+        let target = target_path(self.session.as_ref(), target.as_ref())?;
+        let path = match self.source {
+            Source::Query(_) => None,
+            Source::Channel { ref path, .. } => Some(wide(OsStr::new(path.trim()))),
+            Source::File { ref path, .. } => {
+                validate_file_path(path)?;
+                Some(wide(local_path(path)?.as_os_str()))
+            }
+        };
+        let query = match self.source {
+            Source::Query(ref query) => query,
+            Source::Channel { ref xpath, .. } | Source::File { ref xpath, .. } => xpath,
+        };
+        let export = match *EvtExportLog {
+            Some(EvtApi::ExportLog(ref export)) => export,
+            _ => return Err(Error::Other("EvtExportLog API is not available".to_owned())),
+        };
+        let session_handle = self.session.as_ref().map(EvtSession::lock);
+        let session_handle = session_handle
+            .as_ref()
+            .map_or(null_mut(), |handle| handle.0);
+        if unsafe {
+            export(
+                session_handle,
+                path.as_ref().map_or(null(), |path| path.as_ptr()),
+                wide(OsStr::new(query)).as_ptr(),
+                target.as_ptr(),
+                flags,
+            )
+        } == 0
+        {
+            return Err(Error::last_os("There was an error exporting the log"));
+        }
+        if let Some(locale) = self.archive {
+            let archive = match *EvtArchiveExportedLog {
+                Some(EvtApi::ArchiveExportedLog(ref archive)) => archive,
+                _ => {
+                    return Err(Error::Other(
+                        "EvtArchiveExportedLog API is not available".to_owned(),
+                    ))
+                }
+            };
+            if unsafe { archive(session_handle, target.as_ptr(), locale, 0) } == 0 {
+                return Err(Error::last_os(
+                    "There was an error adding messages to the exported log",
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The `EvtExportLog` flags, after checking the source
+    fn flags(&self) -> Result<u32, Error> {
+        let mut flags = match self.source {
+            Source::Query(ref query) => match query_path_kind(query)? {
+                Some(PathKind::Channel) => EvtExportLogChannelPath,
+                Some(PathKind::File) => EvtExportLogFilePath,
+                None => {
+                    return Err(Error::Other(
+                        "An exported query must name its channels or files".to_owned(),
+                    ))
+                }
+            },
+            Source::Channel { ref path, .. } => {
+                if path.trim().is_empty() {
+                    return Err(Error::Other("Export channel is empty".to_owned()));
+                }
+                if PathKind::of(path.trim()) == PathKind::File {
+                    return Err(Error::Other(
+                        "Log files are exported with ExportLog::file".to_owned(),
+                    ));
+                }
+                EvtExportLogChannelPath
+            }
+            Source::File { .. } => EvtExportLogFilePath,
+        };
+        if self.overwrite {
+            flags |= EvtExportLogOverwrite;
+        }
+        if self.tolerate_query_errors {
+            flags |= EvtExportLogTolerateQueryErrors;
+        }
+        Ok(flags)
+    }
+}
+
+/// Deletes every event in a channel, optionally saving them first
+///
+/// Clearing cannot be undone, so `run` refuses unless `confirm(true)` was
+/// called. It needs the right to clear the channel, which for Security means
+/// running as an administrator.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use win_event_log::prelude::*;
+///
+/// ClearLog::channel("Application")
+///     .backup(r"C:\lab\application-before.evtx")
+///     .confirm(true)
+///     .run()
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct ClearLog {
+    channel: String,
+    backup: Option<PathBuf>,
+    confirmed: bool,
+    session: Option<EvtSession>,
+}
+
+impl<'a> ClearLog {
+    pub fn channel<T: Into<String>>(channel: T) -> ClearLog {
+        ClearLog {
+            channel: channel.into(),
+            backup: None,
+            confirmed: false,
+            session: None,
+        }
+    }
+
+    /// Saves the events to the `.evtx` file `path` before clearing them
+    pub fn backup<P: AsRef<Path>>(&'a mut self, path: P) -> &'a mut Self {
+        self.backup = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Agrees to the events being deleted; without it `run` fails
+    pub fn confirm(&'a mut self, confirmed: bool) -> &'a mut Self {
+        self.confirmed = confirmed;
+        self
+    }
+
+    /// Clears the channel on a remote machine instead of the local one. A
+    /// backup path is then on that machine.
+    pub fn session(&'a mut self, session: &EvtSession) -> &'a mut Self {
+        self.session = Some(session.clone());
+        self
+    }
+
+    pub fn build(&self) -> ClearLog {
+        self.clone()
+    }
+
+    /// Clears the channel
+    pub fn run(&self) -> Result<(), Error> {
+        self.check()?;
+        let backup = match self.backup {
+            Some(ref backup) => Some(target_path(self.session.as_ref(), backup)?),
+            None => None,
+        };
+        let clear = match *EvtClearLog {
+            Some(EvtApi::ClearLog(ref clear)) => clear,
+            _ => return Err(Error::Other("EvtClearLog API is not available".to_owned())),
+        };
+        let session_handle = self.session.as_ref().map(EvtSession::lock);
+        if unsafe {
+            clear(
+                session_handle
+                    .as_ref()
+                    .map_or(null_mut(), |handle| handle.0),
+                wide(OsStr::new(self.channel.trim())).as_ptr(),
+                backup.as_ref().map_or(null(), |backup| backup.as_ptr()),
+                0,
+            )
+        } == 0
+        {
+            return Err(Error::last_os("There was an error clearing the log"));
+        }
+        Ok(())
+    }
+
+    fn check(&self) -> Result<(), Error> {
+        let channel = self.channel.trim();
+        if channel.is_empty() {
+            return Err(Error::Other("Channel to clear is empty".to_owned()));
+        }
+        if PathKind::of(channel) == PathKind::File {
+            return Err(Error::Other("Only channels can be cleared".to_owned()));
+        }
+        if !self.confirmed {
+            return Err(Error::Other(format!(
+                "Clearing {channel} deletes its events; call confirm(true) to go ahead"
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn wide(s: &OsStr) -> Vec<u16> {
+    s.encode_wide().chain(Some(0)).collect()
+}
+
+/// `path` made absolute, since the event log service does not share our
+/// working directory
+fn local_path(path: &Path) -> Result<PathBuf, Error> {
+    std::path::absolute(path)
+        .map_err(|err| Error::Other(format!("Could not resolve {}: {err}", path.display())))
+}
+
+/// An `.evtx` file to write to, on the session's machine or this one
+fn target_path(session: Option<&EvtSession>, path: &Path) -> Result<Vec<u16>, Error> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("evtx") => {}
+        _ => {
+            return Err(Error::Other(format!(
+                "Logs can only be saved to .evtx files: {}",
+                path.display()
+            )))
+        }
+    }
+    match session {
+        Some(_) => Ok(wide(path.as_os_str())),
+        None => Ok(wide(local_path(path)?.as_os_str())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{target_path, ClearLog, ExportLog};
+    use crate::query_list::{Query, QueryItem, QueryList};
+    use std::path::Path;
+    use winapi::um::winevt::{
+        EvtExportLogChannelPath, EvtExportLogFilePath, EvtExportLogOverwrite,
+        EvtExportLogTolerateQueryErrors,
+    };
+
+    #[test]
+    fn export_flags() {
+        let query = QueryList::new()
+            .with_query(
+                Query::new()
+                    .item(QueryItem::selector("Security".to_owned()).build())
+                    .query(),
+            )
+            .build();
+        assert_eq!(
+            ExportLog::query(query).overwrite(true).flags(),
+            Ok(EvtExportLogChannelPath | EvtExportLogOverwrite)
+        );
+        assert_eq!(
+            ExportLog::file(r"C:\logs\Security.evtx", "*")
+                .tolerate_query_errors(true)
+                .flags(),
+            Ok(EvtExportLogFilePath | EvtExportLogTolerateQueryErrors)
+        );
+        assert!(ExportLog::query("*").flags().is_err());
+        assert!(ExportLog::channel(" ", "*").flags().is_err());
+        assert!(ExportLog::channel("file://C:\\a.evtx", "*")
+            .flags()
+            .is_err());
+        assert!(target_path(None, Path::new(r"C:\cases\out.txt")).is_err());
+    }
+
+    #[test]
+    fn clear_needs_confirmation() {
+        assert!(ClearLog::channel("Application").check().is_err());
+        assert!(ClearLog::channel("Application")
+            .confirm(true)
+            .check()
+            .is_ok());
+        assert!(ClearLog::channel("").confirm(true).check().is_err());
+        assert!(ClearLog::channel(r"file://C:\lab\application.evtx")
+            .confirm(true)
+            .check()
+            .is_err());
+        // backups must be .evtx files
+        assert!(target_path(None, Path::new(r"C:\lab\application.log")).is_err());
+        assert!(target_path(None, Path::new(r"C:\lab\application.evtx")).is_ok());
+    }
+}
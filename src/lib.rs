@@ -5,49 +5,90 @@
 //! at runtime. Currently, the API class used to query events is only present on Vista+,
 //! so these functions are loaded dynamically instead of directly linked. This ensures
 //! applications compiled against this crate will continue to work on legacy systems such
-//! as XP or Server 2003. In the future, the legacy APIs will be integrated seamlessly
-//! here to provide a consistent API surface.
+//! as XP or Server 2003.
+//!
+//! Exported `.evtx` files can also be read without the Windows API at all, on any
+//! platform, through `EvtxReader`. Classic `.evt` logs from those legacy systems are
+//! read by `EvtReader`, producing the same `Event` type.
 //!
 //! # Examples
 //!
-//! ```rust
+//! ```rust,no_run
 //! use win_event_log::prelude::*;
 //!
 //! let conditions = vec![ Condition::filter(EventFilter::level(1, Comparison::Equal)) ];
-//! let query =
-//! let query = QueryList.new()
+//! let query = QueryList::new()
 //!     .with_query(
 //!         Query::new().item(
 //!             QueryItem::selector("System")
-//!             .system_conditions(Conditions::or(conditions))
+//!             .system_conditions(Condition::or(conditions))
+//!             .build()
 //!         ).query()
 //!     ).build();
-//! let events = WinEvent5::get(query).unwrap();
+//! # #[cfg(windows)]
+//! let events = WinEvents::get(query).unwrap();
 //! ```
 //!
 
 #[macro_use]
 extern crate bitflags;
+#[cfg(windows)]
 #[macro_use]
 extern crate lazy_static;
 #[cfg(feature = "xml")]
 extern crate quick_xml;
 #[cfg(feature = "xml")]
 extern crate serde;
+#[cfg(windows)]
 extern crate winapi;
 
+#[cfg(windows)]
 mod api;
+mod bookmark;
+mod channel;
+mod checkpoint;
+mod error;
+mod event;
+mod evt;
+mod evtx;
+#[cfg(windows)]
+mod export;
 mod query_list;
-#[cfg(feature = "subscriber")]
+#[cfg(windows)]
+mod render;
+#[cfg(windows)]
+mod session;
+#[cfg(all(windows, feature = "async"))]
+mod stream;
+#[cfg(all(windows, feature = "subscriber"))]
 mod subscriber;
+mod variant;
+#[cfg(windows)]
 #[allow(unused_imports)]
 use api::WinEvents;
 #[allow(unused_imports)]
 use query_list::QueryList;
 
 pub mod prelude {
+    #[cfg(windows)]
     pub use crate::api::*;
+    pub use crate::bookmark::*;
+    pub use crate::channel::*;
+    pub use crate::checkpoint::*;
+    pub use crate::error::*;
+    pub use crate::event::*;
+    pub use crate::evt::*;
+    pub use crate::evtx::*;
+    #[cfg(windows)]
+    pub use crate::export::*;
     pub use crate::query_list::*;
-    #[cfg(feature = "subscriber")]
+    #[cfg(windows)]
+    pub use crate::render::*;
+    #[cfg(windows)]
+    pub use crate::session::*;
+    #[cfg(all(windows, feature = "async"))]
+    pub use crate::stream::QueryStream;
+    #[cfg(all(windows, feature = "subscriber"))]
     pub use crate::subscriber::*;
+    pub use crate::variant::*;
 }
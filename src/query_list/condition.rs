@@ -1,3 +1,4 @@
+use crate::evtx::XmlElement;
 use crate::query_list::event_filter::EventFilter;
 use std::fmt;
 
@@ -20,6 +21,14 @@ impl Condition {
     pub fn and(filters: Vec<Condition>) -> Condition {
         Condition::ConditionAnd(filters)
     }
+
+    pub(crate) fn matches(&self, section: &XmlElement) -> bool {
+        match self {
+            Condition::ConditionItem(item) => item.matches(section),
+            Condition::ConditionOr(items) => items.iter().any(|item| item.matches(section)),
+            Condition::ConditionAnd(items) => items.iter().all(|item| item.matches(section)),
+        }
+    }
 }
 
 impl fmt::Display for Condition {
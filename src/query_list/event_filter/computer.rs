@@ -1,3 +1,4 @@
+use crate::evtx::XmlElement;
 use std::fmt;
 
 #[derive(Clone)]
@@ -9,10 +10,16 @@ impl Computer {
     pub fn new<T: Into<String>>(name: T) -> Computer {
         Computer { name: name.into() }
     }
+
+    pub(crate) fn matches(&self, system: &XmlElement) -> bool {
+        system
+            .elements()
+            .any(|element| element.name == "Computer" && element.text() == self.name)
+    }
 }
 
 impl fmt::Display for Computer {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "System[@Computer = '{}']", self.name)
+        write!(f, "Computer = '{}'", self.name)
     }
 }
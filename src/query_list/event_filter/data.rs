@@ -1,3 +1,4 @@
+use crate::evtx::XmlElement;
 use std::fmt;
 
 #[derive(Clone)]
@@ -9,6 +10,10 @@ impl Name {
     pub fn new<T: Into<String>>(name: T) -> Name {
         Name { name: name.into() }
     }
+
+    pub(crate) fn matches(&self, data: &XmlElement) -> bool {
+        data.attribute_value("Name") == Some(self.name.clone())
+    }
 }
 
 impl fmt::Display for Name {
@@ -28,6 +33,10 @@ impl Value {
             value: value.into(),
         }
     }
+
+    pub(crate) fn matches(&self, data: &XmlElement) -> bool {
+        data.text() == self.value
+    }
 }
 
 impl fmt::Display for Value {
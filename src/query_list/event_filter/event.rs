@@ -1,3 +1,4 @@
+use crate::evtx::XmlElement;
 use std::fmt;
 
 #[derive(Clone)]
@@ -9,6 +10,12 @@ impl Event {
     pub fn new(id: u32) -> Event {
         Event { id }
     }
+
+    pub(crate) fn matches(&self, system: &XmlElement) -> bool {
+        system.elements().any(|element| {
+            element.name == "EventID" && element.text().trim().parse() == Ok(self.id)
+        })
+    }
 }
 
 impl fmt::Display for Event {
@@ -1,3 +1,4 @@
+use crate::evtx::XmlElement;
 use crate::query_list::Comparison;
 use std::fmt;
 
@@ -11,6 +12,16 @@ impl Level {
     pub fn new(level: u32, comparison: Comparison) -> Level {
         Level { level, comparison }
     }
+
+    pub(crate) fn matches(&self, system: &XmlElement) -> bool {
+        system.elements().any(|element| {
+            element.name == "Level"
+                && match element.text().trim().parse() {
+                    Ok(level) => self.comparison.holds(level, self.level),
+                    Err(_) => false,
+                }
+        })
+    }
 }
 
 impl fmt::Display for Level {
@@ -1,3 +1,4 @@
+use crate::evtx::XmlElement;
 use crate::query_list::Comparison;
 use std::fmt;
 
@@ -28,6 +29,17 @@ impl EventDataFilter {
             value: data::Value::new(value),
         }
     }
+
+    /// As in the XPath it renders to, the name and the value may be found on
+    /// different `Data` elements
+    pub(crate) fn matches(&self, event_data: &XmlElement) -> bool {
+        let data = event_data
+            .elements()
+            .filter(|element| element.name == "Data")
+            .collect::<Vec<_>>();
+        data.iter().any(|data| self.name.matches(data))
+            && data.iter().any(|data| self.value.matches(data))
+    }
 }
 
 #[derive(Clone)]
@@ -56,6 +68,17 @@ impl EventFilter {
     pub fn event_data<T: Into<String>>(name: T, value: T) -> EventFilter {
         EventFilter::EventData(EventDataFilter::new(name, value))
     }
+
+    /// Tests the filter against the `System` or `EventData` element it is written for
+    pub(crate) fn matches(&self, section: &XmlElement) -> bool {
+        match self {
+            EventFilter::System(SystemFilter::Computer(item)) => item.matches(section),
+            EventFilter::System(SystemFilter::EventID(item)) => item.matches(section),
+            EventFilter::System(SystemFilter::Level(item)) => item.matches(section),
+            EventFilter::System(SystemFilter::Provider(item)) => item.matches(section),
+            EventFilter::EventData(item) => item.matches(section),
+        }
+    }
 }
 
 impl fmt::Display for EventFilter {
@@ -128,6 +151,35 @@ and
 *[EventData[((Data[@Name = 'TargetUserName'] and Data = 'SYSTEM'))]]
 </Suppress>
 </Query>
+</QueryList>"#
+        );
+    }
+
+    #[test]
+    fn computer_query() {
+        use crate::prelude::*;
+        let ql = QueryList::new()
+            .with_query(
+                Query::new()
+                    .item(
+                        QueryItem::selector("System")
+                            .system_conditions(Condition::filter(EventFilter::computer(
+                                "dc01.example.com",
+                            )))
+                            .build(),
+                    )
+                    .query(),
+            )
+            .build();
+        // `Computer` is an element of `System`, not an attribute
+        assert_eq!(
+            ql.to_string(),
+            r#"<QueryList>
+<Query Id="0">
+<Select Path="System">
+*[System[(Computer = 'dc01.example.com')]]
+</Select>
+</Query>
 </QueryList>"#
         );
     }
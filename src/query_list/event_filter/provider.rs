@@ -1,3 +1,4 @@
+use crate::evtx::XmlElement;
 use std::fmt;
 
 #[derive(Clone)]
@@ -9,6 +10,12 @@ impl Provider {
     pub fn new<T: Into<String>>(name: T) -> Provider {
         Provider { name: name.into() }
     }
+
+    pub(crate) fn matches(&self, system: &XmlElement) -> bool {
+        system.elements().any(|element| {
+            element.name == "Provider" && element.attribute_value("Name") == Some(self.name.clone())
+        })
+    }
 }
 
 impl fmt::Display for Provider {
@@ -1,3 +1,5 @@
+use crate::event::Event;
+use crate::evtx::{XmlElement, XmlNode};
 use std::fmt;
 
 mod condition;
@@ -6,7 +8,7 @@ mod query_item;
 
 pub use self::condition::Condition;
 pub use self::event_filter::EventFilter;
-pub use self::query_item::{QueryItem, QueryItemType};
+pub use self::query_item::{validate_file_path, PathKind, QueryItem, QueryItemType};
 
 #[derive(Clone)]
 /// Comparison conditions supported by the Windows Event Log
@@ -18,6 +20,19 @@ pub enum Comparison {
     LessThanOrEqual,
 }
 
+impl Comparison {
+    /// Whether `left <op> right` holds
+    pub(crate) fn holds<T: PartialOrd>(&self, left: T, right: T) -> bool {
+        match *self {
+            Comparison::Equal => left == right,
+            Comparison::GreaterThan => left > right,
+            Comparison::LessThan => left < right,
+            Comparison::GreaterThanOrEqual => left >= right,
+            Comparison::LessThanOrEqual => left <= right,
+        }
+    }
+}
+
 impl std::fmt::Display for Comparison {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match *self {
@@ -53,6 +68,114 @@ impl<'a> QueryList {
             queries: self.queries.clone(),
         }
     }
+
+    /// Determines whether this list reads from channels or from log files. The event
+    /// log cannot mix the two in one query, so doing so is an error. Returns `None`
+    /// when no item has a path.
+    pub fn path_kind(&self) -> Result<Option<PathKind>, String> {
+        combine_path_kinds(
+            self.queries
+                .iter()
+                .flat_map(|query| query.items.iter())
+                .filter_map(|item| item.path_kind()),
+        )
+    }
+
+    /// Whether the list selects `event`, evaluated here rather than by the event
+    /// log. An event is selected when one `Query` selects it: it matches one of the
+    /// query's `Select` items and none of its `Suppress` items.
+    pub fn matches(&self, event: &Event) -> Result<bool, String> {
+        let nodes = XmlNode::parse(&event.0)?;
+        let root = match nodes.iter().find_map(XmlNode::as_element) {
+            Some(root) => root,
+            None => return Err("Event has no root element".to_owned()),
+        };
+        Ok(self.queries.iter().any(|query| query.selects(root)))
+    }
+
+    /// Applies the list to events from an offline source, such as
+    /// `EvtxReader::events`, returning only those it selects. Events that cannot
+    /// be parsed become errors.
+    pub fn filter<I>(&self, events: I) -> QueryFilter<I::IntoIter>
+    where
+        I: IntoIterator<Item = Result<Event, String>>,
+    {
+        QueryFilter {
+            events: events.into_iter(),
+            list: self.clone(),
+        }
+    }
+}
+
+/// Iterator over the events a `QueryList` selects from an offline source
+pub struct QueryFilter<I> {
+    events: I,
+    list: QueryList,
+}
+
+impl<I: Iterator<Item = Result<Event, String>>> Iterator for QueryFilter<I> {
+    type Item = Result<Event, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let event = match self.events.next()? {
+                Ok(event) => event,
+                Err(err) => return Some(Err(err)),
+            };
+            match self.list.matches(&event) {
+                Ok(true) => return Some(Ok(event)),
+                Ok(false) => {}
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+/// Same as `QueryList::path_kind`, but for a query that has already been rendered
+/// to XML (or was written by hand). Plain XPath queries have no paths and yield `None`.
+#[cfg_attr(not(windows), allow(dead_code))]
+pub(crate) fn query_path_kind(query: &str) -> Result<Option<PathKind>, String> {
+    let mut kinds = Vec::new();
+    let mut rest = query;
+    while let Some(start) = rest.find("Path") {
+        // an attribute name, as in `Path = "…"`, not part of a longer word
+        let attribute = rest[..start].ends_with(char::is_whitespace);
+        rest = rest[start + "Path".len()..].trim_start();
+        if !attribute {
+            continue;
+        }
+        rest = match rest.strip_prefix('=') {
+            Some(value) => value.trim_start(),
+            None => continue,
+        };
+        let quote = match rest.chars().next() {
+            Some(q) if q == '"' || q == '\'' => q,
+            _ => continue,
+        };
+        rest = &rest[1..];
+        match rest.find(quote) {
+            Some(end) => {
+                kinds.push(PathKind::of(rest[..end].trim()));
+                rest = &rest[end + 1..];
+            }
+            None => break,
+        }
+    }
+    combine_path_kinds(kinds.into_iter())
+}
+
+fn combine_path_kinds<I: Iterator<Item = PathKind>>(
+    mut kinds: I,
+) -> Result<Option<PathKind>, String> {
+    let first = match kinds.next() {
+        Some(kind) => kind,
+        None => return Ok(None),
+    };
+    if kinds.any(|kind| kind != first) {
+        Err("A QueryList cannot mix channel paths and file:// paths".to_owned())
+    } else {
+        Ok(Some(first))
+    }
 }
 
 impl fmt::Display for QueryList {
@@ -96,6 +219,13 @@ impl<'a> Query {
             items: self.items.clone(),
         }
     }
+
+    fn selects(&self, event: &XmlElement) -> bool {
+        let (suppressors, selectors): (Vec<&QueryItem>, Vec<&QueryItem>) =
+            self.items.iter().partition(|item| item.is_suppressor());
+        selectors.iter().any(|item| item.matches(event))
+            && !suppressors.iter().any(|item| item.matches(event))
+    }
 }
 
 impl std::fmt::Display for Query {
@@ -206,4 +336,137 @@ and
 </QueryList>"#
         );
     }
+
+    #[test]
+    fn file_and_channel_paths() {
+        use crate::prelude::*;
+        use crate::query_list::query_path_kind;
+        let files = QueryList::new()
+            .with_query(
+                Query::new()
+                    .item(QueryItem::file_selector(r"C:\logs\a.evtx").unwrap())
+                    .item(QueryItem::file_suppressor(r"C:\logs\b.evtx").unwrap())
+                    .query(),
+            )
+            .build();
+        assert_eq!(files.path_kind(), Ok(Some(PathKind::File)));
+        assert_eq!(
+            query_path_kind(&files.to_string()),
+            Ok(Some(PathKind::File))
+        );
+
+        let mixed = QueryList::new()
+            .with_query(
+                Query::new()
+                    .item(QueryItem::selector("Security"))
+                    .item(QueryItem::file_selector(r"C:\logs\a.evtx").unwrap())
+                    .query(),
+            )
+            .build();
+        assert!(mixed.path_kind().is_err());
+        assert!(query_path_kind(&mixed.to_string()).is_err());
+
+        assert_eq!(QueryList::new().path_kind(), Ok(None));
+        assert_eq!(query_path_kind("*[System[(Level = 1)]]"), Ok(None));
+        assert_eq!(
+            query_path_kind("<Select Path='System'>*</Select>"),
+            Ok(Some(PathKind::Channel))
+        );
+        assert_eq!(
+            query_path_kind(r#"<Select Path = "file://C:\logs\a.evtx">*</Select>"#),
+            Ok(Some(PathKind::File))
+        );
+        assert_eq!(
+            query_path_kind(
+                "<Select\n  Path\n=\t'System'>*[EventData[Data[@Name='Path'] = 'x']]</Select>"
+            ),
+            Ok(Some(PathKind::Channel))
+        );
+        assert_eq!(
+            query_path_kind("<Select XPath='file://a.evtx'>*</Select>"),
+            Ok(None)
+        );
+    }
+
+    fn event(channel: &str, id: u32, level: u32, user: &str) -> crate::event::Event {
+        crate::event::Event(format!(
+            "<Event xmlns='http://schemas.microsoft.com/win/2004/08/events/event'><System>\
+             <Provider Name='Microsoft-Windows-Security-Auditing'/><EventID>{id}</EventID>\
+             <Level>{level}</Level><Channel>{channel}</Channel><Computer>dc01</Computer>\
+             </System><EventData><Data Name='TargetUserName'>{user}</Data>\
+             <Data Name='LogonType'>5</Data></EventData></Event>"
+        ))
+    }
+
+    #[test]
+    fn offline_matching() {
+        use crate::prelude::*;
+        let list = QueryList::new()
+            .with_query(
+                Query::new()
+                    .item(
+                        QueryItem::selector("Security")
+                            .system_conditions(Condition::or(vec![
+                                Condition::filter(EventFilter::event(4624)),
+                                Condition::filter(EventFilter::level(
+                                    2,
+                                    Comparison::LessThanOrEqual,
+                                )),
+                            ]))
+                            .build(),
+                    )
+                    .item(
+                        QueryItem::suppressor("Security")
+                            .event_conditions(Condition::filter(EventFilter::event_data(
+                                "TargetUserName",
+                                "SYSTEM",
+                            )))
+                            .build(),
+                    )
+                    .query(),
+            )
+            .with_query(
+                Query::new()
+                    .item(
+                        QueryItem::selector("System")
+                            .system_conditions(Condition::and(vec![
+                                Condition::filter(EventFilter::computer("dc01")),
+                                Condition::filter(EventFilter::provider(
+                                    "Microsoft-Windows-Security-Auditing",
+                                )),
+                            ]))
+                            .build(),
+                    )
+                    .query(),
+            )
+            .build();
+        assert_eq!(list.matches(&event("Security", 4624, 0, "alice")), Ok(true));
+        assert_eq!(list.matches(&event("security", 4625, 2, "alice")), Ok(true));
+        assert_eq!(
+            list.matches(&event("Security", 4625, 4, "alice")),
+            Ok(false)
+        );
+        assert_eq!(
+            list.matches(&event("Security", 4624, 0, "SYSTEM")),
+            Ok(false)
+        );
+        assert_eq!(list.matches(&event("System", 7036, 4, "SYSTEM")), Ok(true));
+        assert_eq!(
+            list.matches(&event("Application", 4624, 0, "alice")),
+            Ok(false)
+        );
+        assert!(list
+            .matches(&crate::event::Event("not xml".to_owned()))
+            .is_err());
+
+        let events = vec![
+            Ok(event("Security", 4624, 0, "alice")),
+            Ok(event("Security", 4625, 4, "alice")),
+            Err("unreadable".to_owned()),
+        ];
+        let filtered = list.filter(events).collect::<Vec<_>>();
+        assert_eq!(filtered.len(), 2);
+        assert!(filtered[0].is_ok());
+        assert!(filtered[1].is_err());
+    }
 }
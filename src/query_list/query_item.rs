@@ -1,7 +1,59 @@
 use std::fmt;
+use std::path::Path;
 
+use crate::evtx::XmlElement;
 use crate::query_list::condition;
 
+/// Prefix the event log uses to mark a `Path` as an exported log file
+/// rather than a channel name
+pub const FILE_PATH_PREFIX: &str = "file://";
+
+/// File extensions the event log can open with `EvtQueryFilePath`
+const FILE_EXTENSIONS: &[&str] = &["evtx", "evt", "etl"];
+
+/// What a query `Path` refers to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PathKind {
+    /// A live channel such as `Security` or `Microsoft-Windows-Sysmon/Operational`
+    Channel,
+    /// An exported or archived log file, written as `file://<path>`
+    File,
+}
+
+impl PathKind {
+    /// Classifies a raw `Path` attribute value
+    pub fn of(path: &str) -> PathKind {
+        if path.len() >= FILE_PATH_PREFIX.len()
+            && path[..FILE_PATH_PREFIX.len()].eq_ignore_ascii_case(FILE_PATH_PREFIX)
+        {
+            PathKind::File
+        } else {
+            PathKind::Channel
+        }
+    }
+}
+
+/// Checks that `path` can be used as a log file in a query. The file itself is not
+/// opened here, so this only rejects paths that can never work.
+pub fn validate_file_path(path: &Path) -> Result<(), String> {
+    let display = path.to_string_lossy();
+    if display.is_empty() {
+        return Err("Log file path is empty".to_owned());
+    }
+    if display.contains(['"', '<', '&']) {
+        return Err(format!(
+            "Log file path contains characters not allowed in a query: {display}"
+        ));
+    }
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if FILE_EXTENSIONS.iter().any(|e| ext.eq_ignore_ascii_case(e)) => Ok(()),
+        _ => Err(format!(
+            "Log file must have one of the extensions {}: {display}",
+            FILE_EXTENSIONS.join(", ")
+        )),
+    }
+}
+
 #[derive(Clone)]
 pub enum QueryItemType {
     Suppressor,
@@ -43,6 +95,30 @@ impl<'a> QueryItem {
         QueryItem::new(QueryItemType::Suppressor, path)
     }
 
+    /// Creates an item reading from an exported `.evtx`/`.evt`/`.etl` file instead
+    /// of a channel. The path is validated and written as `file://<path>`.
+    pub fn file<P: AsRef<Path>>(item_type: QueryItemType, path: P) -> Result<QueryItem, String> {
+        let path = path.as_ref();
+        validate_file_path(path)?;
+        Ok(QueryItem::new(
+            item_type,
+            format!("{FILE_PATH_PREFIX}{}", path.to_string_lossy()),
+        ))
+    }
+
+    pub fn file_selector<P: AsRef<Path>>(path: P) -> Result<QueryItem, String> {
+        QueryItem::file(QueryItemType::Selector, path)
+    }
+
+    pub fn file_suppressor<P: AsRef<Path>>(path: P) -> Result<QueryItem, String> {
+        QueryItem::file(QueryItemType::Suppressor, path)
+    }
+
+    /// Whether this item targets a channel or a log file
+    pub fn path_kind(&self) -> Option<PathKind> {
+        self.path.as_deref().map(PathKind::of)
+    }
+
     pub fn system_conditions(&'a mut self, conditions: condition::Condition) -> &'a mut Self {
         self.system_conditions = Some(conditions);
         self
@@ -56,6 +132,47 @@ impl<'a> QueryItem {
     pub fn build(&self) -> Self {
         self.clone()
     }
+
+    pub(crate) fn is_suppressor(&self) -> bool {
+        matches!(self.query_item_type, QueryItemType::Suppressor)
+    }
+
+    /// Tests the item against a parsed event, as the event log would
+    pub(crate) fn matches(&self, event: &XmlElement) -> bool {
+        let system = event.child("System");
+        if !self.reads(system) {
+            return false;
+        }
+        let system_matches = match self.system_conditions {
+            Some(ref conditions) => system.is_some_and(|system| conditions.matches(system)),
+            None => true,
+        };
+        let data_matches = match self.event_data_conditions {
+            Some(ref conditions) => event
+                .child("EventData")
+                .is_some_and(|data| conditions.matches(data)),
+            None => true,
+        };
+        system_matches && data_matches
+    }
+
+    /// Whether the item's path covers an event with this `System` element. A file
+    /// path covers everything read from an offline source, and a channel covers
+    /// events from that channel, or with no channel recorded at all, as in `.evt`
+    /// files.
+    fn reads(&self, system: Option<&XmlElement>) -> bool {
+        let path = match self.path {
+            Some(ref path) => path.trim(),
+            None => return false,
+        };
+        if PathKind::of(path) == PathKind::File {
+            return true;
+        }
+        match system.and_then(|system| system.child("Channel")) {
+            Some(channel) => channel.text().eq_ignore_ascii_case(path),
+            None => true,
+        }
+    }
 }
 
 impl fmt::Display for QueryItem {
@@ -93,4 +210,29 @@ mod tests {
             &selector.to_string()
         );
     }
+
+    #[test]
+    fn file_selector() {
+        use crate::prelude::*;
+        let selector = QueryItem::file_selector(r"C:\logs\Security.evtx")
+            .unwrap()
+            .system_conditions(Condition::filter(EventFilter::event(4624)))
+            .build();
+        assert_eq!(selector.path_kind(), Some(PathKind::File));
+        assert_eq!(
+            r#"<Select Path="file://C:\logs\Security.evtx">
+*[System[(EventID = 4624)]]
+</Select>"#,
+            &selector.to_string()
+        );
+    }
+
+    #[test]
+    fn invalid_file_paths() {
+        use crate::prelude::*;
+        assert!(QueryItem::file_selector("").is_err());
+        assert!(QueryItem::file_selector(r"C:\logs\Security.txt").is_err());
+        assert!(QueryItem::file_selector("C:\\logs\\Secu\"rity.evtx").is_err());
+        assert!(QueryItem::file_selector(r"C:\logs\legacy.EVT").is_ok());
+    }
 }
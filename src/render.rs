@@ -0,0 +1,160 @@
+//! Rendering selected event properties as values instead of XML
+
+use crate::api::{
+    EvtApi, EvtCreateRenderContext, EvtHandle, EvtHandleWrapper, EvtRender, WinEvents,
+    ERROR_INSUFFICIENT_BUFFER,
+};
+use crate::variant::Variant;
+use std::ffi::OsStr;
+use std::os::windows::prelude::*;
+use std::ptr::{null, null_mut};
+use winapi::shared::minwindef::DWORD;
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::winevt::{
+    EvtRenderContextSystem, EvtRenderContextUser, EvtRenderContextValues, EvtRenderEventValues,
+};
+use winapi::um::winnt::LPCWSTR;
+
+/// Which properties `EvtRender` extracts from each event
+pub struct RenderContext {
+    handle: EvtHandleWrapper,
+}
+
+impl RenderContext {
+    /// Every `System` property, in the order of `SystemProperty`
+    pub fn system() -> Result<RenderContext, String> {
+        RenderContext::create(&[], EvtRenderContextSystem)
+    }
+
+    /// The event's user data, `EventData` or `UserData`, in document order
+    pub fn user() -> Result<RenderContext, String> {
+        RenderContext::create(&[], EvtRenderContextUser)
+    }
+
+    /// The properties at `paths`, such as `Event/System/EventID` or
+    /// `Event/EventData/Data[@Name='TargetUserName']`, in the same order
+    pub fn values<T: AsRef<str>>(paths: &[T]) -> Result<RenderContext, String> {
+        let paths = paths
+            .iter()
+            .map(|path| {
+                OsStr::new(path.as_ref())
+                    .encode_wide()
+                    .chain(Some(0))
+                    .collect::<Vec<u16>>()
+            })
+            .collect::<Vec<Vec<u16>>>();
+        RenderContext::create(&paths, EvtRenderContextValues)
+    }
+
+    fn create(paths: &[Vec<u16>], flags: DWORD) -> Result<RenderContext, String> {
+        let pointers = paths
+            .iter()
+            .map(|path| path.as_ptr())
+            .collect::<Vec<LPCWSTR>>();
+        match *EvtCreateRenderContext {
+            Some(EvtApi::CreateRenderContext(ref create)) => {
+                let handle = unsafe {
+                    create(
+                        pointers.len() as DWORD,
+                        if pointers.is_empty() {
+                            null()
+                        } else {
+                            pointers.as_ptr()
+                        },
+                        flags,
+                    )
+                };
+                if handle.is_null() {
+                    Err(format!(
+                        "There was an error creating the render context: {}",
+                        unsafe { GetLastError() }
+                    ))
+                } else {
+                    Ok(RenderContext {
+                        handle: EvtHandleWrapper(handle),
+                    })
+                }
+            }
+            _ => Err("EvtCreateRenderContext API is not available".to_owned()),
+        }
+    }
+
+    /// Renders the context's properties of `event`
+    pub(crate) fn render(&self, event: EvtHandle) -> Result<Vec<Variant>, String> {
+        let render = match *EvtRender {
+            Some(EvtApi::Render(ref render)) => render,
+            _ => return Err("EvtRender API is not available".to_owned()),
+        };
+        let mut buffer_used: DWORD = 0;
+        let mut property_count: DWORD = 0;
+        let sized = unsafe {
+            render(
+                self.handle.0,
+                event,
+                EvtRenderEventValues,
+                0,
+                null_mut(),
+                &mut buffer_used,
+                &mut property_count,
+            ) != 0
+                || GetLastError() == ERROR_INSUFFICIENT_BUFFER
+        };
+        if !sized {
+            return Err(format!(
+                "There was an error rendering event values: {}",
+                unsafe { GetLastError() }
+            ));
+        }
+        // u64s keep the variants aligned
+        let mut buffer = vec![0u64; (buffer_used as usize).div_ceil(8)];
+        if unsafe {
+            render(
+                self.handle.0,
+                event,
+                EvtRenderEventValues,
+                (buffer.len() * 8) as DWORD,
+                buffer.as_mut_ptr() as _,
+                &mut buffer_used,
+                &mut property_count,
+            )
+        } == 0
+        {
+            return Err(format!(
+                "There was an error rendering event values: {}",
+                unsafe { GetLastError() }
+            ));
+        }
+        let bytes = buffer
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .take(buffer_used as usize)
+            .collect::<Vec<u8>>();
+        Variant::decode_all(&bytes, buffer.as_ptr() as u64, property_count as usize)
+    }
+}
+
+impl WinEvents {
+    /// Iterates over the query results rendered with `context`, without producing
+    /// any XML
+    pub fn values(self, context: RenderContext) -> WinEventValues {
+        WinEventValues {
+            win_events: self,
+            context,
+        }
+    }
+}
+
+/// Iterator over the values of each event in a `WinEvents`
+pub struct WinEventValues {
+    win_events: WinEvents,
+    context: RenderContext,
+}
+
+impl Iterator for WinEventValues {
+    type Item = Result<Vec<Variant>, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let handle = self.win_events.next_evt()?;
+        Some(self.context.render(handle.0))
+    }
+}
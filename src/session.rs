@@ -0,0 +1,229 @@
+//! Remote event log sessions
+
+use crate::api::{EvtApi, EvtHandleWrapper, EvtOpenSession, WinEvents};
+use crate::bookmark::Bookmark;
+use crate::error::Error;
+use std::fmt;
+use std::ptr::{null_mut, write_volatile};
+use std::sync::atomic::{compiler_fence, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use winapi::um::winevt::{
+    EvtRpcLogin, EvtRpcLoginAuthDefault, EvtRpcLoginAuthKerberos, EvtRpcLoginAuthNTLM,
+    EvtRpcLoginAuthNegotiate, EVT_RPC_LOGIN,
+};
+use winapi::um::winnt::PVOID;
+
+/// How a remote session authenticates
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuthMethod {
+    /// Let the system pick, normally Negotiate
+    Default,
+    Negotiate,
+    Kerberos,
+    Ntlm,
+}
+
+impl AuthMethod {
+    fn flags(self) -> u32 {
+        match self {
+            AuthMethod::Default => EvtRpcLoginAuthDefault,
+            AuthMethod::Negotiate => EvtRpcLoginAuthNegotiate,
+            AuthMethod::Kerberos => EvtRpcLoginAuthKerberos,
+            AuthMethod::Ntlm => EvtRpcLoginAuthNTLM,
+        }
+    }
+}
+
+/// A NUL-terminated wide string that is overwritten with zeros when dropped
+#[derive(Clone)]
+struct Secret(Vec<u16>);
+
+impl Secret {
+    fn new(value: &str) -> Secret {
+        // Sized up front so the vector never reallocates, leaving a copy behind
+        let mut wide = Vec::with_capacity(value.len() + 1);
+        wide.extend(value.encode_utf16().chain(Some(0)));
+        Secret(wide)
+    }
+
+    fn as_ptr(&self) -> *mut u16 {
+        self.0.as_ptr() as _
+    }
+
+    fn clear(&mut self) {
+        for unit in self.0.iter_mut() {
+            unsafe { write_volatile(unit, 0) };
+        }
+        compiler_fence(Ordering::SeqCst);
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+/// Builds an `EvtSession` to the event log service on another machine
+///
+/// Without a user the session logs on as the current user. The domain, user
+/// and password are held as wide strings that are zeroed when the builder is
+/// dropped; copies the caller keeps are their own to clear.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use win_event_log::prelude::*;
+///
+/// let session = Session::remote("dc01.example.com")
+///     .domain("EXAMPLE")
+///     .user("auditor")
+///     .password("correct horse battery staple")
+///     .auth(AuthMethod::Kerberos)
+///     .open()
+///     .unwrap();
+/// let events = session.query("*").unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct Session {
+    server: Secret,
+    domain: Option<Secret>,
+    user: Option<Secret>,
+    password: Option<Secret>,
+    auth: AuthMethod,
+}
+
+impl<'a> Session {
+    /// A session to `server`, a host name or IP address
+    pub fn remote<T: AsRef<str>>(server: T) -> Session {
+        Session {
+            server: Secret::new(server.as_ref()),
+            domain: None,
+            user: None,
+            password: None,
+            auth: AuthMethod::Default,
+        }
+    }
+
+    pub fn domain<T: AsRef<str>>(&'a mut self, domain: T) -> &'a mut Self {
+        self.domain = Some(Secret::new(domain.as_ref()));
+        self
+    }
+
+    pub fn user<T: AsRef<str>>(&'a mut self, user: T) -> &'a mut Self {
+        self.user = Some(Secret::new(user.as_ref()));
+        self
+    }
+
+    pub fn password<T: AsRef<str>>(&'a mut self, password: T) -> &'a mut Self {
+        self.password = Some(Secret::new(password.as_ref()));
+        self
+    }
+
+    /// How to authenticate, by default `AuthMethod::Default`
+    pub fn auth(&'a mut self, auth: AuthMethod) -> &'a mut Self {
+        self.auth = auth;
+        self
+    }
+
+    pub fn build(&self) -> Session {
+        self.clone()
+    }
+
+    /// Opens the session
+    pub fn open(&self) -> Result<EvtSession, Error> {
+        if self.server.0.len() <= 1 {
+            return Err(Error::Other("Session server is empty".to_owned()));
+        }
+        let open = match *EvtOpenSession {
+            Some(EvtApi::OpenSession(ref open)) => open,
+            _ => {
+                return Err(Error::Other(
+                    "EvtOpenSession API is not available".to_owned(),
+                ))
+            }
+        };
+        let pointer = |secret: &Option<Secret>| secret.as_ref().map_or(null_mut(), Secret::as_ptr);
+        let mut login = EVT_RPC_LOGIN {
+            Server: self.server.as_ptr(),
+            User: pointer(&self.user),
+            Domain: pointer(&self.domain),
+            Password: pointer(&self.password),
+            Flags: self.auth.flags(),
+        };
+        match unsafe { open(EvtRpcLogin, &mut login as *mut EVT_RPC_LOGIN as PVOID, 0, 0) } {
+            i if i.is_null() => Err(Error::last_os("There was an error opening the session")),
+            i => Ok(EvtSession {
+                handle: Arc::new(Mutex::new(EvtHandleWrapper(i))),
+            }),
+        }
+    }
+}
+
+/// An open session to a remote event log service
+///
+/// Clones share the session, which stays open until the last clone and every
+/// query or subscription opened on it are dropped. Handles are opened on it one
+/// at a time.
+#[derive(Clone)]
+pub struct EvtSession {
+    handle: Arc<Mutex<EvtHandleWrapper>>,
+}
+
+impl fmt::Debug for EvtSession {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EvtSession").finish_non_exhaustive()
+    }
+}
+
+impl EvtSession {
+    /// Queries the remote event log, as `WinEvents::get` does locally
+    pub fn query<T: Into<String> + Clone>(&self, query: T) -> Result<WinEvents, String> {
+        WinEvents::get_in(Some(self), query.into())
+    }
+
+    /// Queries the remote event log after `bookmark`, as `WinEvents::resume` does
+    /// locally
+    pub fn resume<T: Into<String> + Clone>(
+        &self,
+        query: T,
+        bookmark: &Bookmark,
+    ) -> Result<WinEvents, String> {
+        WinEvents::resume_in(Some(self), query.into(), bookmark)
+    }
+
+    /// The session handle, held for as long as it is being passed to the API
+    pub(crate) fn lock(&self) -> MutexGuard<'_, EvtHandleWrapper> {
+        self.handle.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AuthMethod, Secret, Session};
+    use crate::api::tests::assert_send;
+    use winapi::um::winevt::{EvtRpcLoginAuthDefault, EvtRpcLoginAuthNTLM};
+
+    #[test]
+    fn credentials() {
+        let mut secret = Secret::new("hunter2");
+        assert_eq!(secret.0.len(), 8);
+        secret.clear();
+        assert!(secret.0.iter().all(|unit| *unit == 0));
+        let session = Session::remote("host")
+            .user("admin")
+            .password("hunter2")
+            .build();
+        assert!(!format!("{session:?}").contains("hunter2"));
+        assert_eq!(AuthMethod::Default.flags(), EvtRpcLoginAuthDefault);
+        assert_eq!(AuthMethod::Ntlm.flags(), EvtRpcLoginAuthNTLM);
+        assert!(Session::remote("").open().is_err());
+        assert_send::<super::EvtSession>();
+    }
+}
@@ -0,0 +1,228 @@
+//! Async `Stream`s of events, for any executor
+//!
+//! Queries are read on a thread of their own, into a bounded queue, since
+//! `EvtNext` blocks. Subscriptions are streamed from their signal instead; see
+//! `WinEventsSubscriber::into_stream`.
+
+use crate::api::WinEvents;
+use crate::error::Error;
+use crate::event::Event;
+use futures_core::Stream;
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+
+struct State<T> {
+    items: VecDeque<T>,
+    capacity: usize,
+    waker: Option<Waker>,
+    sender_gone: bool,
+    receiver_gone: bool,
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    space: Condvar,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The blocking end of a bounded queue feeding a stream
+pub(crate) struct Sender<T>(Arc<Shared<T>>);
+
+/// The async end of a bounded queue
+pub(crate) struct Receiver<T>(Arc<Shared<T>>);
+
+/// A queue holding up to `capacity` items, at least one
+pub(crate) fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            items: VecDeque::new(),
+            capacity: capacity.max(1),
+            waker: None,
+            sender_gone: false,
+            receiver_gone: false,
+        }),
+        space: Condvar::new(),
+    });
+    (Sender(shared.clone()), Receiver(shared))
+}
+
+impl<T> Sender<T> {
+    /// Waits for room and queues `item`, or gives it back if the receiver is gone
+    pub(crate) fn send(&self, item: T) -> Result<(), T> {
+        let mut state = self.0.lock();
+        while state.items.len() >= state.capacity && !state.receiver_gone {
+            state = self.0.space.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+        if state.receiver_gone {
+            return Err(item);
+        }
+        state.items.push_back(item);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+        Ok(())
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.0.lock();
+        state.sender_gone = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    /// The next item, `None` once the sender is gone and the queue is empty
+    pub(crate) fn poll_recv(&self, cx: &mut Context) -> Poll<Option<T>> {
+        let mut state = self.0.lock();
+        match state.items.pop_front() {
+            Some(item) => {
+                self.0.space.notify_one();
+                Poll::Ready(Some(item))
+            }
+            None if state.sender_gone => Poll::Ready(None),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.0.lock().receiver_gone = true;
+        self.0.space.notify_all();
+    }
+}
+
+/// The events of a query, read on a background thread
+///
+/// At most `capacity` events are read ahead of the consumer. Dropping the stream
+/// stops the thread once its current read returns.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use win_event_log::prelude::*;
+///
+/// let query = QueryList::new()
+///     .with_query(Query::new().item(QueryItem::selector("System").build()).query())
+///     .build();
+/// let events = QueryStream::new(move || WinEvents::get(query), 64).unwrap();
+/// ```
+pub struct QueryStream {
+    receiver: Receiver<Result<Event, Error>>,
+}
+
+impl QueryStream {
+    /// Runs the query `open` makes on a new thread. Its error, if any, is
+    /// returned here.
+    pub fn new<F>(open: F, capacity: usize) -> Result<QueryStream, String>
+    where
+        F: FnOnce() -> Result<WinEvents, String> + Send + 'static,
+    {
+        let (sender, receiver) = channel(capacity);
+        let (opened, open_result) = std::sync::mpsc::sync_channel(1);
+        std::thread::Builder::new()
+            .name("win-event-log query".to_owned())
+            .spawn(move || {
+                let events = match open() {
+                    Ok(events) => {
+                        let _ = opened.send(Ok(()));
+                        events
+                    }
+                    Err(e) => {
+                        let _ = opened.send(Err(e));
+                        return;
+                    }
+                };
+                for event in events {
+                    if sender.send(Ok(event)).is_err() {
+                        break;
+                    }
+                }
+            })
+            .map_err(|e| format!("Could not start the query thread: {e}"))?;
+        match open_result.recv() {
+            Ok(Ok(())) => Ok(QueryStream { receiver }),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err("The query thread stopped before running the query".to_owned()),
+        }
+    }
+}
+
+impl Stream for QueryStream {
+    type Item = Result<Event, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::channel;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn bounded_queue() {
+        let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+        let mut cx = Context::from_waker(&waker);
+        let (sender, receiver) = channel(2);
+        assert_eq!(receiver.poll_recv(&mut cx), Poll::Pending);
+        let producer = thread::spawn(move || {
+            for i in 0..5 {
+                sender.send(i).unwrap();
+            }
+        });
+        // The producer stops at the capacity until items are taken
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while receiver.0.lock().items.len() < 2 {
+            assert!(
+                Instant::now() < deadline,
+                "the producer did not fill the queue"
+            );
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(receiver.0.lock().items.len(), 2);
+        assert!(counter.0.load(Ordering::SeqCst) >= 1);
+        let mut received = Vec::new();
+        loop {
+            match receiver.poll_recv(&mut cx) {
+                Poll::Ready(Some(i)) => received.push(i),
+                Poll::Ready(None) => break,
+                Poll::Pending => thread::sleep(Duration::from_millis(1)),
+            }
+        }
+        producer.join().unwrap();
+        assert_eq!(received, vec![0, 1, 2, 3, 4]);
+
+        let (sender, receiver) = channel(1);
+        drop(receiver);
+        assert_eq!(sender.send(1), Err(1));
+    }
+}
@@ -0,0 +1,423 @@
+use super::multiplexer::{Multiplexer, Tagged};
+use super::{create_event, HandleWrapper, Received, Subscription};
+use crate::error::Error;
+use crate::event::Event;
+use std::collections::VecDeque;
+use std::sync::mpsc::{RecvTimeoutError, TryRecvError};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use winapi::um::synchapi::SetEvent;
+
+/// What the background thread does with an event when the queue is full
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Overflow {
+    /// Wait for the receiver to make room, leaving events in the subscriptions
+    Block,
+    /// Discard the oldest queued event
+    DropOldest,
+    /// Discard the new event
+    DropNewest,
+}
+
+/// Options for `SubscriberThread::spawn`
+#[derive(Clone, Debug)]
+pub struct BackgroundOptions {
+    capacity: usize,
+    overflow: Overflow,
+}
+
+impl<'a> BackgroundOptions {
+    /// Room for 1024 events, blocking when full
+    pub fn new() -> BackgroundOptions {
+        BackgroundOptions {
+            capacity: 1024,
+            overflow: Overflow::Block,
+        }
+    }
+
+    /// How many events can wait for the receiver, at least one
+    pub fn capacity(&'a mut self, capacity: usize) -> &'a mut Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    pub fn overflow(&'a mut self, overflow: Overflow) -> &'a mut Self {
+        self.overflow = overflow;
+        self
+    }
+
+    pub fn build(&self) -> BackgroundOptions {
+        self.clone()
+    }
+}
+
+impl Default for BackgroundOptions {
+    fn default() -> Self {
+        BackgroundOptions::new()
+    }
+}
+
+struct State {
+    events: VecDeque<Event>,
+    /// Events discarded by `Overflow::DropOldest` or `Overflow::DropNewest`
+    dropped: u64,
+    /// Set when the thread is told to stop, or the receiver is gone
+    closed: bool,
+    /// Set when the thread has ended
+    finished: bool,
+}
+
+struct Queue {
+    state: Mutex<State>,
+    capacity: usize,
+    overflow: Overflow,
+    /// Signalled when an event is queued or the thread ends
+    filled: Condvar,
+    /// Signalled when room is made or the queue is closed
+    space: Condvar,
+}
+
+impl Queue {
+    fn new(options: &BackgroundOptions) -> Queue {
+        Queue {
+            state: Mutex::new(State {
+                events: VecDeque::new(),
+                dropped: 0,
+                closed: false,
+                finished: false,
+            }),
+            capacity: options.capacity,
+            overflow: options.overflow,
+            filled: Condvar::new(),
+            space: Condvar::new(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Queues `event` by the overflow policy, returning false once closed
+    fn push(&self, event: Event) -> bool {
+        let mut state = self.lock();
+        if state.events.len() >= self.capacity {
+            match self.overflow {
+                Overflow::Block => {
+                    while state.events.len() >= self.capacity && !state.closed {
+                        state = self.space.wait(state).unwrap_or_else(|e| e.into_inner());
+                    }
+                }
+                Overflow::DropOldest => {
+                    state.events.pop_front();
+                    state.dropped += 1;
+                }
+                Overflow::DropNewest => {
+                    state.dropped += 1;
+                    return !state.closed;
+                }
+            }
+        }
+        if state.closed {
+            return false;
+        }
+        state.events.push_back(event);
+        self.filled.notify_one();
+        true
+    }
+
+    fn close(&self) {
+        self.lock().closed = true;
+        self.space.notify_all();
+    }
+
+    fn finish(&self) {
+        self.lock().finished = true;
+        self.filled.notify_all();
+    }
+
+    fn pop(&self, state: &mut State) -> Option<Event> {
+        let event = state.events.pop_front()?;
+        self.space.notify_one();
+        Some(event)
+    }
+}
+
+/// Ends the receiver when the thread ends, even by panicking
+struct Finish<'a>(&'a Queue);
+
+impl Drop for Finish<'_> {
+    fn drop(&mut self) {
+        self.0.finish();
+    }
+}
+
+/// Guard for a thread reading subscriptions into an `EventReceiver`
+///
+/// Dropping it, or calling `stop`, closes the subscriptions and waits for the
+/// thread to end. Events already queued can still be received afterwards.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use win_event_log::prelude::*;
+///
+/// let subscriptions = vec![
+///     Subscription::channel("Security", "*").start(StartMode::FutureEvents).build(),
+///     Subscription::channel("System", "*").start(StartMode::FutureEvents).build(),
+/// ];
+/// let options = BackgroundOptions::new().overflow(Overflow::DropOldest).build();
+/// let (thread, events) = SubscriberThread::spawn(subscriptions, &options).unwrap();
+/// for event in events.iter().take(10) {
+///     println!("{}", event);
+/// }
+/// thread.stop().unwrap();
+/// ```
+pub struct SubscriberThread {
+    stop: Arc<HandleWrapper>,
+    queue: Arc<Queue>,
+    thread: Option<JoinHandle<Result<(), Error>>>,
+}
+
+impl SubscriberThread {
+    /// Starts `subscriptions` on a new thread. Failing to start any of them is
+    /// reported here.
+    pub fn spawn(
+        subscriptions: Vec<Subscription>,
+        options: &BackgroundOptions,
+    ) -> Result<(SubscriberThread, EventReceiver), Error> {
+        let stop = Arc::new(create_event(false)?);
+        let queue = Arc::new(Queue::new(options));
+        let (started, start_result) = std::sync::mpsc::sync_channel(1);
+        let thread = {
+            let stop = stop.clone();
+            let queue = queue.clone();
+            std::thread::Builder::new()
+                .name("win-event-log subscriber".to_owned())
+                .spawn(move || {
+                    let _finish = Finish(&queue);
+                    let mut multiplexer = Multiplexer::with_cancel(stop);
+                    let subscribed = subscriptions.iter().try_for_each(|subscription| {
+                        multiplexer.add(subscription.subscribe()?).map(|_| ())
+                    });
+                    match subscribed {
+                        Ok(()) => {
+                            let _ = started.send(Ok(()));
+                            run(&mut multiplexer, &queue)
+                        }
+                        Err(e) => {
+                            let _ = started.send(Err(e));
+                            Ok(())
+                        }
+                    }
+                })
+                .map_err(|e| Error::Other(format!("Could not start the subscriber thread: {e}")))?
+        };
+        match start_result.recv() {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => return Err(e),
+            Err(_) => {
+                return Err(Error::Other(
+                    "The subscriber thread stopped before subscribing".to_owned(),
+                ))
+            }
+        }
+        let receiver = EventReceiver {
+            queue: queue.clone(),
+        };
+        let guard = SubscriberThread {
+            stop,
+            queue,
+            thread: Some(thread),
+        };
+        Ok((guard, receiver))
+    }
+
+    /// Stops the thread, returning the error that ended it early, if any, such
+    /// as a subscription failing
+    pub fn stop(mut self) -> Result<(), Error> {
+        self.shut_down()
+    }
+
+    fn shut_down(&mut self) -> Result<(), Error> {
+        unsafe {
+            SetEvent(self.stop.0);
+        }
+        self.queue.close();
+        match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(Error::Other("The subscriber thread panicked".to_owned())),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for SubscriberThread {
+    fn drop(&mut self) {
+        let _ = self.shut_down();
+    }
+}
+
+/// Reads the subscriptions until the thread is stopped or the queue is closed
+fn run(multiplexer: &mut Multiplexer, queue: &Queue) -> Result<(), Error> {
+    loop {
+        match multiplexer.next_timeout(Duration::MAX)? {
+            Received::Event(Tagged {
+                event: Ok(event), ..
+            }) => {
+                if !queue.push(event) {
+                    return Ok(());
+                }
+            }
+            Received::Event(Tagged {
+                id,
+                event: Err(error),
+            }) => {
+                // Skipped or missed events leave the subscription running
+                if multiplexer
+                    .health(id)
+                    .is_some_and(|health| !health.is_live())
+                {
+                    return Err(error);
+                }
+            }
+            Received::TimedOut => {}
+            Received::Cancelled => return Ok(()),
+        }
+    }
+}
+
+/// The receiving end of a `SubscriberThread`
+pub struct EventReceiver {
+    queue: Arc<Queue>,
+}
+
+impl EventReceiver {
+    /// Waits for the next event. `None` means the thread has ended and every
+    /// event has been received.
+    pub fn recv(&self) -> Option<Event> {
+        let mut state = self.queue.lock();
+        loop {
+            if let Some(event) = self.queue.pop(&mut state) {
+                return Some(event);
+            }
+            if state.finished {
+                return None;
+            }
+            state = self
+                .queue
+                .filled
+                .wait(state)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Waits up to `timeout` for the next event
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Event, RecvTimeoutError> {
+        let deadline = Instant::now().checked_add(timeout);
+        let mut state = self.queue.lock();
+        loop {
+            if let Some(event) = self.queue.pop(&mut state) {
+                return Ok(event);
+            }
+            if state.finished {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let remaining = match deadline {
+                Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+                None => timeout,
+            };
+            if remaining == Duration::from_secs(0) {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            state = self
+                .queue
+                .filled
+                .wait_timeout(state, remaining)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+
+    /// The next event if one is queued, without waiting
+    pub fn try_recv(&self) -> Result<Event, TryRecvError> {
+        let mut state = self.queue.lock();
+        match self.queue.pop(&mut state) {
+            Some(event) => Ok(event),
+            None if state.finished => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// How many events the overflow policy has discarded so far
+    pub fn dropped(&self) -> u64 {
+        self.queue.lock().dropped
+    }
+
+    /// Receives events until the thread ends
+    pub fn iter(&self) -> impl Iterator<Item = Event> + '_ {
+        std::iter::from_fn(move || self.recv())
+    }
+}
+
+impl Drop for EventReceiver {
+    fn drop(&mut self) {
+        // Nobody is left to read, so the thread stops at its next event
+        self.queue.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BackgroundOptions, EventReceiver, Overflow, Queue};
+    use crate::event::Event;
+    use std::sync::mpsc::TryRecvError;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn receiver(capacity: usize, overflow: Overflow) -> EventReceiver {
+        let options = BackgroundOptions::new()
+            .capacity(capacity)
+            .overflow(overflow)
+            .build();
+        EventReceiver {
+            queue: Arc::new(Queue::new(&options)),
+        }
+    }
+
+    fn event(i: usize) -> Event {
+        Event(format!("<Event>{i}</Event>"))
+    }
+
+    #[test]
+    fn overflow_policies() {
+        let oldest = receiver(2, Overflow::DropOldest);
+        let newest = receiver(2, Overflow::DropNewest);
+        for i in 0..4 {
+            assert!(oldest.queue.push(event(i)));
+            assert!(newest.queue.push(event(i)));
+        }
+        assert_eq!((oldest.dropped(), newest.dropped()), (2, 2));
+        assert_eq!(oldest.try_recv(), Ok(event(2)));
+        assert_eq!(newest.try_recv(), Ok(event(0)));
+
+        let blocking = receiver(1, Overflow::Block);
+        let queue = blocking.queue.clone();
+        let producer = thread::spawn(move || (0..3).all(|i| queue.push(event(i))));
+        let received = (0..3).map(|_| blocking.recv().unwrap()).collect::<Vec<_>>();
+        assert!(producer.join().unwrap());
+        assert_eq!(received, vec![event(0), event(1), event(2)]);
+        assert_eq!(blocking.dropped(), 0);
+
+        // Closing releases a blocked producer, and finishing ends the receiver
+        assert!(blocking.queue.push(event(3)));
+        let queue = blocking.queue.clone();
+        let producer = thread::spawn(move || queue.push(event(4)));
+        blocking.queue.close();
+        assert!(!producer.join().unwrap());
+        blocking.queue.finish();
+        assert_eq!(blocking.recv(), Some(event(3)));
+        assert_eq!(blocking.try_recv(), Err(TryRecvError::Disconnected));
+        assert_eq!(blocking.recv(), None);
+    }
+}
@@ -0,0 +1,123 @@
+use crate::api::{render_event, EvtHandle, EvtHandleWrapper};
+use crate::error::Error;
+use crate::event::Event;
+use crate::session::EvtSession;
+use crate::subscriber::Health;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Mutex, MutexGuard};
+use winapi::shared::minwindef::DWORD;
+use winapi::um::winevt::{EvtSubscribeActionDeliver, EVT_SUBSCRIBE_NOTIFY_ACTION};
+use winapi::um::winnt::PVOID;
+
+type BoxedCallback = Box<dyn FnMut(Result<Event, Error>) + Send>;
+
+/// The context passed to `EvtSubscribe`
+pub(crate) struct Callback {
+    callback: Mutex<BoxedCallback>,
+    health: Mutex<Health>,
+}
+
+/// A push-mode subscription, calling a closure on a system thread for each event
+///
+/// Errors the subscription reports, such as missed events, are passed to the
+/// closure too, and recorded in `health`. Dropping the subscriber closes the subscription, waiting for a
+/// call in progress to return, and then drops the closure.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use win_event_log::prelude::*;
+///
+/// let subscriber = Subscription::channel("System", "*")
+///     .start(StartMode::FutureEvents)
+///     .subscribe_with(|event| match event {
+///         Ok(event) => println!("{}", event),
+///         Err(e) if e.is_stale() => println!("Events were missed"),
+///         Err(e) => println!("Error: {}", e),
+///     })
+///     .unwrap();
+/// ```
+pub struct CallbackSubscriber {
+    handle: Option<EvtHandleWrapper>,
+    callback: *mut Callback,
+    /// Kept open for as long as the subscription
+    _bookmark: Option<EvtHandleWrapper>,
+    _session: Option<EvtSession>,
+}
+
+// The context is only shared with the event log's own threads, and guards what
+// it holds with mutexes; the subscription handle is only used to close it
+unsafe impl Send for CallbackSubscriber {}
+unsafe impl Sync for CallbackSubscriber {}
+
+impl CallbackSubscriber {
+    /// Takes ownership of `callback` once `subscribe` has returned the
+    /// subscription handle it was registered with
+    pub(crate) fn new(
+        subscribe: impl FnOnce(PVOID, Option<&EvtHandleWrapper>) -> Result<EvtHandle, Error>,
+        callback: BoxedCallback,
+        bookmark: Option<EvtHandleWrapper>,
+        session: Option<EvtSession>,
+    ) -> Result<CallbackSubscriber, Error> {
+        let callback: *mut Callback = Box::into_raw(Box::new(Callback {
+            callback: Mutex::new(callback),
+            health: Mutex::new(Health::default()),
+        }));
+        match subscribe(callback as PVOID, bookmark.as_ref()) {
+            Ok(handle) => Ok(CallbackSubscriber {
+                handle: Some(EvtHandleWrapper(handle)),
+                callback,
+                _bookmark: bookmark,
+                _session: session,
+            }),
+            Err(e) => {
+                drop(unsafe { Box::from_raw(callback) });
+                Err(e)
+            }
+        }
+    }
+
+    /// Whether the subscription still works, and the last error it reported
+    pub fn health(&self) -> Health {
+        let callback = unsafe { &*self.callback };
+        lock(&callback.health).clone()
+    }
+}
+
+impl Drop for CallbackSubscriber {
+    fn drop(&mut self) {
+        // Closing the subscription stops the calls, so the closure can go after it
+        drop(self.handle.take());
+        drop(unsafe { Box::from_raw(self.callback) });
+    }
+}
+
+/// The `EVT_SUBSCRIBE_CALLBACK` passed to `EvtSubscribe`, with a `Callback` as
+/// the context
+pub(crate) unsafe extern "system" fn deliver(
+    action: EVT_SUBSCRIBE_NOTIFY_ACTION,
+    context: PVOID,
+    event: EvtHandle,
+) -> DWORD {
+    let context = &*(context as *const Callback);
+    // Unwinding into the event log service is undefined, so panics end here
+    let _ = catch_unwind(AssertUnwindSafe(|| {
+        let item = if action == EvtSubscribeActionDeliver {
+            render_event(event).inspect_err(|error| lock(&context.health).skipped(error))
+        } else {
+            // For errors the handle is the error code
+            let error = Error::Windows {
+                code: event as usize as u32,
+                context: "The subscription reported an error".to_owned(),
+            };
+            lock(&context.health).read_failed(&error);
+            Err(error)
+        };
+        (*lock(&context.callback))(item)
+    }));
+    0
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
@@ -0,0 +1,55 @@
+use crate::error::Error;
+
+/// What a subscriber has run into so far
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Health {
+    last_error: Option<Error>,
+    failed: bool,
+}
+
+impl Health {
+    /// False once reading the subscription has failed with anything other than
+    /// missed events, after which it may deliver nothing more
+    pub fn is_live(&self) -> bool {
+        !self.failed
+    }
+
+    /// The most recent error, including events that were skipped or missed
+    pub fn last_error(&self) -> Option<&Error> {
+        self.last_error.as_ref()
+    }
+
+    /// Records a failure reading the subscription
+    pub(crate) fn read_failed(&mut self, error: &Error) {
+        self.failed |= !error.is_stale();
+        self.last_error = Some(error.clone());
+    }
+
+    /// Records an event that could not be rendered, which leaves the
+    /// subscription itself working
+    pub(crate) fn skipped(&mut self, error: &Error) {
+        self.last_error = Some(error.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Health;
+    use crate::error::{Error, ERROR_EVT_QUERY_RESULT_STALE};
+
+    #[test]
+    fn failures() {
+        let error = |code| Error::Windows {
+            code,
+            context: "There was an error reading events".to_owned(),
+        };
+        let mut health = Health::default();
+        assert!(health.is_live() && health.last_error().is_none());
+        health.read_failed(&error(ERROR_EVT_QUERY_RESULT_STALE));
+        health.skipped(&error(13));
+        assert!(health.is_live());
+        assert_eq!(health.last_error(), Some(&error(13)));
+        health.read_failed(&error(1722));
+        assert!(!health.is_live());
+    }
+}
@@ -1,12 +1,40 @@
-use crate::api::{Event, EvtApi, EvtSubscribe, WinEvents, WinEventsIntoIterator};
+use crate::api::{
+    render_event, EvtApi, EvtHandle, EvtHandleWrapper, EvtSubscribe, WinEvents,
+    WinEventsIntoIterator,
+};
+use crate::bookmark::Bookmark;
+use crate::error::Error;
+use crate::event::Event;
+use crate::session::EvtSession;
 use std::ffi::OsString;
 use std::os::windows::ffi::OsStrExt;
 use std::ptr::null_mut;
+use std::sync::Arc;
+use std::time::Duration;
 use winapi::um::handleapi::CloseHandle;
-use winapi::um::synchapi::{CreateEventA, WaitForSingleObject};
-use winapi::um::winbase::{INFINITE, WAIT_OBJECT_0};
-use winapi::um::winevt::EvtSubscribeStartAtOldestRecord;
-use winapi::um::winnt::HANDLE;
+use winapi::um::synchapi::CreateEventA;
+use winapi::um::winevt::EVT_SUBSCRIBE_CALLBACK;
+use winapi::um::winnt::{HANDLE, PVOID};
+
+mod background;
+mod callback;
+mod health;
+mod multiplexer;
+#[cfg(feature = "async")]
+mod stream;
+mod subscription;
+mod wait;
+
+pub use self::background::{BackgroundOptions, EventReceiver, Overflow, SubscriberThread};
+use self::callback::deliver;
+pub use self::callback::CallbackSubscriber;
+pub use self::health::Health;
+pub use self::multiplexer::{Multiplexer, Tagged};
+#[cfg(feature = "async")]
+pub use self::stream::SubscriptionStream;
+pub use self::subscription::{StartMode, Subscription};
+use self::wait::{next_event, WinSignal};
+pub use self::wait::{CancelHandle, Received};
 
 pub struct HandleWrapper(HANDLE);
 
@@ -20,69 +48,208 @@ impl Drop for HandleWrapper {
     }
 }
 
+// Event objects can be set, reset and waited on from any thread
+unsafe impl Send for HandleWrapper {}
+unsafe impl Sync for HandleWrapper {}
+
+/// A pull-mode subscription
+///
+/// `Send`, so it can be moved to a reader thread, but not `Sync`. A
+/// `CancelHandle` can be shared with other threads to wake the reader.
 pub struct WinEventsSubscriber {
-    signal: Option<HandleWrapper>,
-    events: Option<WinEventsIntoIterator>,
-    has_events: bool,
+    signal: HandleWrapper,
+    cancel: Arc<HandleWrapper>,
+    events: WinEventsIntoIterator,
+    health: Health,
+    /// Kept open for as long as the subscription
+    _bookmark: Option<EvtHandleWrapper>,
 }
 
 impl WinEventsSubscriber {
-    pub fn get<T: Into<String> + Clone>(query: T) -> Result<WinEventsSubscriber, String> {
-        let ffi_query = {
-            let mut tmp = OsString::from(query.into())
-                .encode_wide()
-                .collect::<Vec<u16>>();
-            tmp.append(&mut OsString::from("\0").encode_wide().collect::<Vec<u16>>());
-            tmp
+    /// Subscribes with a structured query, starting at the oldest record. See
+    /// `Subscription` for other starting points.
+    pub fn get<T: Into<String> + Clone>(query: T) -> Result<WinEventsSubscriber, Error> {
+        Subscription::query(query).subscribe()
+    }
+
+    /// Subscribes like `get`, starting after the events `bookmark` has already
+    /// passed instead of at the oldest record. An empty bookmark starts at the
+    /// oldest record.
+    pub fn resume<T: Into<String> + Clone>(
+        query: T,
+        bookmark: &Bookmark,
+    ) -> Result<WinEventsSubscriber, Error> {
+        Subscription::query(query)
+            .start(StartMode::AfterBookmark(bookmark.clone()))
+            .subscribe()
+    }
+
+    pub(crate) fn subscribe(
+        session: Option<EvtSession>,
+        channel: Option<&str>,
+        query: &str,
+        bookmark: Option<EvtHandleWrapper>,
+        flags: u32,
+    ) -> Result<WinEventsSubscriber, Error> {
+        let signal = create_event(true)?;
+        let cancel = create_event(false)?;
+        let subscription = evt_subscribe(
+            session.as_ref(),
+            Delivery::Signal(signal.0),
+            channel,
+            query,
+            bookmark.as_ref(),
+            flags,
+        )?;
+        Ok(WinEventsSubscriber {
+            signal,
+            cancel: Arc::new(cancel),
+            events: WinEvents::with_session(subscription, session).into_iter(),
+            health: Health::default(),
+            _bookmark: bookmark,
+        })
+    }
+
+    /// The next event if one has already arrived, without waiting. Errors are
+    /// recorded in `health`.
+    pub fn try_next(&mut self) -> Option<Event> {
+        self.fetch().ok().flatten()
+    }
+
+    /// The next event if one has already arrived, recording any error
+    pub(crate) fn fetch(&mut self) -> Result<Option<Event>, Error> {
+        let handle = match self.events.win_events.try_next_evt() {
+            Ok(handle) => handle,
+            Err(error) => {
+                self.health.read_failed(&error);
+                return Err(error);
+            }
+        };
+        handle
+            .map(|handle| render_event(handle.0))
+            .transpose()
+            .inspect_err(|error| self.health.skipped(error))
+    }
+
+    /// Whether the subscription still works, and the last error it ran into
+    pub fn health(&self) -> &Health {
+        &self.health
+    }
+
+    /// The next event, waiting up to `timeout` for one to arrive
+    pub fn next_timeout(&mut self, timeout: Duration) -> Result<Received, Error> {
+        let signal = WinSignal {
+            cancel: self.cancel.0,
+            signals: vec![self.signal.0],
+        };
+        next_event(&signal, timeout, || self.fetch())
+    }
+
+    /// A handle that wakes `next_timeout`, for shutting down a reader thread
+    pub fn cancel_handle(&self) -> CancelHandle {
+        CancelHandle(self.cancel.clone())
+    }
+}
+
+/// A manual-reset event object
+fn create_event(set: bool) -> Result<HandleWrapper, Error> {
+    match unsafe { CreateEventA(null_mut(), 1, set as _, null_mut()) } {
+        i if i.is_null() => Err(Error::last_os("Could not create the signal event")),
+        i => Ok(HandleWrapper(i)),
+    }
+}
+
+/// How `EvtSubscribe` hands over events
+pub(crate) enum Delivery {
+    /// Sets the event object when events arrive, to be read with `EvtNext`
+    Signal(HANDLE),
+    /// Calls `deliver` with a `Callback` as the context
+    Callback(PVOID),
+}
+
+/// Calls `EvtSubscribe` on `session`, or on the local machine
+pub(crate) fn evt_subscribe(
+    session: Option<&EvtSession>,
+    delivery: Delivery,
+    channel: Option<&str>,
+    query: &str,
+    bookmark: Option<&EvtHandleWrapper>,
+    flags: u32,
+) -> Result<EvtHandle, Error> {
+    let (signal, context, callback): (HANDLE, PVOID, EVT_SUBSCRIBE_CALLBACK) = match delivery {
+        Delivery::Signal(signal) => (signal, null_mut(), None),
+        Delivery::Callback(context) => (null_mut(), context, Some(deliver)),
+    };
+    let wide = |s: &str| {
+        OsString::from(s)
+            .encode_wide()
+            .chain(Some(0))
+            .collect::<Vec<u16>>()
+    };
+    let ffi_query = wide(query);
+    let ffi_channel = channel.map(wide);
+    if let Some(EvtApi::Subscribe(ref evt_subscribe)) = *EvtSubscribe {
+        let session_handle = session.map(EvtSession::lock);
+        let subscription = unsafe {
+            evt_subscribe(
+                session_handle
+                    .as_ref()
+                    .map_or(null_mut(), |handle| handle.0),
+                signal,
+                ffi_channel
+                    .as_ref()
+                    .map_or(null_mut(), |channel| channel.as_ptr() as _),
+                ffi_query.as_ptr(),
+                bookmark.map_or(null_mut(), |bookmark| bookmark.0),
+                context,
+                callback,
+                flags,
+            )
         };
-        let signal = unsafe { CreateEventA(null_mut(), 1, 1, null_mut()) };
-        if let Some(EvtApi::Subscribe(ref evt_subscribe)) = *EvtSubscribe {
-            let subscription = unsafe {
-                evt_subscribe(
-                    null_mut(),
-                    signal,
-                    null_mut(),
-                    ffi_query.as_ptr(),
-                    null_mut(),
-                    null_mut(),
-                    None,
-                    EvtSubscribeStartAtOldestRecord,
-                )
-            };
-            Ok(WinEventsSubscriber {
-                signal: Some(HandleWrapper(signal)),
-                events: Some(WinEvents::new(subscription).into_iter()),
-                has_events: false,
-            })
-        } else {
-            Err("There is an error calling the EvtSubscribe() API".to_owned())
+        if subscription.is_null() {
+            return Err(Error::last_os("There was an error subscribing"));
         }
+        Ok(subscription)
+    } else {
+        Err(Error::Other(
+            "There is an error calling the EvtSubscribe() API".to_owned(),
+        ))
     }
+}
 
-    /// Gets the next item from the event log. If there are no more evens `None` is returned.
-    pub fn next(&mut self) -> Option<Event> {
-        match self.signal {
-            Some(ref mut signal) => {
-                if !self.has_events {
-                    let x = unsafe { WaitForSingleObject(signal.0 as _, INFINITE) };
-                    self.has_events = x == WAIT_OBJECT_0;
-                }
-                match self.has_events {
-                    true => match self.events {
-                        Some(ref mut events) => {
-                            let event = events.next();
-                            self.has_events = event.is_some();
-                            event
-                        }
-                        None => {
-                            self.has_events = false;
-                            None
-                        }
-                    },
-                    false => None,
-                }
-            }
-            None => None,
+/// Reads the events that have arrived. `None` means no event is available right
+/// now, or reading failed as recorded in `health`; later calls pick up new ones,
+/// so the subscription can be polled. Use `next_timeout` to wait instead.
+impl Iterator for WinEventsSubscriber {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        self.try_next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::tests::{assert_send, NotSync};
+
+    #[test]
+    fn thread_safety() {
+        use super::{
+            CallbackSubscriber, CancelHandle, EventReceiver, Multiplexer, SubscriberThread,
+            WinEventsSubscriber,
+        };
+        assert_send::<WinEventsSubscriber>();
+        assert_send::<Multiplexer>();
+        assert_send::<CallbackSubscriber>();
+        assert_send::<SubscriberThread>();
+        assert_send::<EventReceiver>();
+        assert_send::<CancelHandle>();
+        <WinEventsSubscriber as NotSync<_>>::check();
+        <Multiplexer as NotSync<_>>::check();
+        #[cfg(feature = "async")]
+        {
+            assert_send::<super::SubscriptionStream>();
+            assert_send::<crate::stream::QueryStream>();
         }
     }
 }
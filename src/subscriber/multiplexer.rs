@@ -0,0 +1,183 @@
+use super::wait::{next_event, WinSignal};
+use super::{create_event, CancelHandle, HandleWrapper, Health, Received, WinEventsSubscriber};
+use crate::error::Error;
+use crate::event::Event;
+use std::sync::Arc;
+use std::time::Duration;
+use winapi::um::winnt::MAXIMUM_WAIT_OBJECTS;
+
+/// An event, or an error, from one of a `Multiplexer`'s subscriptions
+#[derive(Debug, PartialEq)]
+pub struct Tagged {
+    /// The index `Multiplexer::add` returned for the subscription
+    pub id: usize,
+    pub event: Result<Event, Error>,
+}
+
+/// Reads several subscriptions at once, waiting on all of their signals together
+///
+/// Subscriptions take turns: each read starts with the subscription after the
+/// one that produced the previous event, so a busy channel cannot hold up the
+/// others. Subscriptions that fail stop being read, and their `health` says why.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use win_event_log::prelude::*;
+///
+/// let mut multiplexer = Multiplexer::new().unwrap();
+/// for channel in &["Security", "System", "Microsoft-Windows-Sysmon/Operational"] {
+///     let subscriber = Subscription::channel(*channel, "*")
+///         .start(StartMode::FutureEvents)
+///         .subscribe()
+///         .unwrap();
+///     multiplexer.add(subscriber).unwrap();
+/// }
+/// while let Ok(Received::Event(tagged)) = multiplexer.next_timeout(Duration::from_secs(60)) {
+///     if let Ok(event) = tagged.event {
+///         println!("{}: {}", tagged.id, event);
+///     }
+/// }
+/// ```
+pub struct Multiplexer {
+    subscribers: Vec<WinEventsSubscriber>,
+    cancel: Arc<HandleWrapper>,
+    /// The subscription read first on the next turn
+    next: usize,
+}
+
+impl Multiplexer {
+    pub fn new() -> Result<Multiplexer, Error> {
+        Ok(Multiplexer::with_cancel(Arc::new(create_event(false)?)))
+    }
+
+    pub(crate) fn with_cancel(cancel: Arc<HandleWrapper>) -> Multiplexer {
+        Multiplexer {
+            subscribers: Vec::new(),
+            cancel,
+            next: 0,
+        }
+    }
+
+    /// Adds a subscription, returning the id its events are tagged with
+    pub fn add(&mut self, subscriber: WinEventsSubscriber) -> Result<usize, Error> {
+        // One wait slot is taken by the cancel event
+        if self.subscribers.len() + 1 >= MAXIMUM_WAIT_OBJECTS as usize {
+            return Err(Error::Other(format!(
+                "At most {} subscriptions can be multiplexed",
+                MAXIMUM_WAIT_OBJECTS - 1
+            )));
+        }
+        self.subscribers.push(subscriber);
+        Ok(self.subscribers.len() - 1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.subscribers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subscribers.is_empty()
+    }
+
+    /// The health of the subscription `id`
+    pub fn health(&self, id: usize) -> Option<&Health> {
+        self.subscribers.get(id).map(WinEventsSubscriber::health)
+    }
+
+    /// The next event if one has already arrived, without waiting
+    pub fn try_next(&mut self) -> Option<Tagged> {
+        take_turn(&mut self.subscribers, &mut self.next, fetch_live)
+    }
+
+    /// The next event from any subscription, waiting up to `timeout` for one to
+    /// arrive
+    pub fn next_timeout(&mut self, timeout: Duration) -> Result<Received<Tagged>, Error> {
+        let signal = WinSignal {
+            cancel: self.cancel.0,
+            // A failed subscription's signal could stay set and never be drained
+            signals: self
+                .subscribers
+                .iter()
+                .filter(|subscriber| subscriber.health().is_live())
+                .map(|subscriber| subscriber.signal.0)
+                .collect(),
+        };
+        let (subscribers, next) = (&mut self.subscribers, &mut self.next);
+        next_event(&signal, timeout, || {
+            Ok(take_turn(subscribers, next, fetch_live))
+        })
+    }
+
+    /// A handle that wakes `next_timeout`, for shutting down a reader thread
+    pub fn cancel_handle(&self) -> CancelHandle {
+        CancelHandle(self.cancel.clone())
+    }
+}
+
+/// Reads one event, or error, from the first subscription that has one,
+/// starting at `next`. `fetch` gives `None` for a subscription with nothing to
+/// read.
+fn take_turn<S>(
+    subscribers: &mut [S],
+    next: &mut usize,
+    mut fetch: impl FnMut(&mut S) -> Option<Result<Event, Error>>,
+) -> Option<Tagged> {
+    let count = subscribers.len();
+    for offset in 0..count {
+        let id = (*next + offset) % count;
+        if let Some(event) = fetch(&mut subscribers[id]) {
+            *next = (id + 1) % count;
+            return Some(Tagged { id, event });
+        }
+    }
+    None
+}
+
+/// Failed subscriptions are not read again
+fn fetch_live(subscriber: &mut WinEventsSubscriber) -> Option<Result<Event, Error>> {
+    if subscriber.health().is_live() {
+        subscriber.fetch().transpose()
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{take_turn, Tagged};
+    use crate::event::Event;
+    use std::collections::VecDeque;
+
+    #[test]
+    fn subscriptions_take_turns() {
+        let event = |name: &str| Event(format!("<Event>{name}</Event>"));
+        let mut channels = vec![
+            (0..4).map(|i| event(&format!("busy {i}"))).collect(),
+            VecDeque::from(vec![event("quiet")]),
+            VecDeque::new(),
+            VecDeque::from(vec![event("other")]),
+        ];
+        let mut next = 0;
+        let mut turns = Vec::new();
+        while let Some(Tagged { id, event }) = take_turn(&mut channels, &mut next, |events| {
+            events.pop_front().map(Ok)
+        }) {
+            turns.push((id, event.unwrap()));
+        }
+        assert_eq!(
+            turns,
+            vec![
+                (0, event("busy 0")),
+                (1, event("quiet")),
+                (3, event("other")),
+                (0, event("busy 1")),
+                (0, event("busy 2")),
+                (0, event("busy 3")),
+            ]
+        );
+        let mut none: Vec<VecDeque<Event>> = Vec::new();
+        assert!(take_turn(&mut none, &mut next, |events| events.pop_front().map(Ok)).is_none());
+    }
+}
@@ -0,0 +1,189 @@
+use super::{Health, WinEventsSubscriber};
+use crate::error::Error;
+use crate::event::Event;
+use futures_core::Stream;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::pin::Pin;
+use std::ptr::null_mut;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use winapi::shared::ntdef::BOOLEAN;
+use winapi::um::handleapi::INVALID_HANDLE_VALUE;
+use winapi::um::synchapi::{ResetEvent, WaitForSingleObject};
+use winapi::um::threadpoollegacyapiset::UnregisterWaitEx;
+use winapi::um::winbase::{RegisterWaitForSingleObject, INFINITE, WAIT_OBJECT_0};
+use winapi::um::winnt::{HANDLE, PVOID, WT_EXECUTEONLYONCE};
+
+/// A subscription as a `Stream`, woken by its signal or by cancelling
+///
+/// Events are only read from the subscription as the stream is polled, so a slow
+/// consumer leaves them queued in the event log service rather than in memory
+/// here. The stream ends once the subscriber's `CancelHandle` is used or the
+/// subscription fails, after yielding the error, and dropping it closes the
+/// subscription.
+pub struct SubscriptionStream {
+    subscriber: WinEventsSubscriber,
+    waker: Arc<Mutex<Option<Waker>>>,
+    /// The one-shot thread pool waits on the signal and the cancel event, while
+    /// they are registered
+    waits: Vec<HANDLE>,
+}
+
+// A registered wait can be removed from any thread
+unsafe impl Send for SubscriptionStream {}
+
+impl WinEventsSubscriber {
+    /// Turns the subscriber into a `Stream` of its events
+    pub fn into_stream(self) -> SubscriptionStream {
+        SubscriptionStream {
+            subscriber: self,
+            waker: Arc::new(Mutex::new(None)),
+            waits: Vec::new(),
+        }
+    }
+}
+
+impl SubscriptionStream {
+    /// Removes the registered waits, waiting for their callbacks if those are
+    /// running, so the waker outlives every call
+    fn unregister(&mut self) {
+        for wait in self.waits.drain(..) {
+            unsafe {
+                UnregisterWaitEx(wait, INVALID_HANDLE_VALUE);
+            }
+        }
+    }
+
+    fn cancelled(&self) -> bool {
+        unsafe { WaitForSingleObject(self.subscriber.cancel.0, 0) == WAIT_OBJECT_0 }
+    }
+}
+
+impl SubscriptionStream {
+    /// Whether the subscription still works, and the last error it ran into
+    pub fn health(&self) -> &Health {
+        self.subscriber.health()
+    }
+}
+
+impl Stream for SubscriptionStream {
+    type Item = Result<Event, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if !this.subscriber.health().is_live() {
+            return Poll::Ready(None);
+        }
+        if let Some(item) = this.subscriber.fetch().transpose() {
+            return Poll::Ready(Some(item));
+        }
+        if this.cancelled() {
+            return Poll::Ready(None);
+        }
+        this.unregister();
+        // As in `next_timeout`, an event arriving just before the reset is still
+        // picked up
+        unsafe {
+            ResetEvent(this.subscriber.signal.0);
+        }
+        if let Some(item) = this.subscriber.fetch().transpose() {
+            return Poll::Ready(Some(item));
+        }
+        *this.waker.lock().unwrap_or_else(|e| e.into_inner()) = Some(cx.waker().clone());
+        // Cancelling wakes the stream too, so it can end
+        for handle in [this.subscriber.signal.0, this.subscriber.cancel.0] {
+            let mut wait: HANDLE = null_mut();
+            if unsafe {
+                RegisterWaitForSingleObject(
+                    &mut wait,
+                    handle,
+                    Some(wake),
+                    Arc::as_ptr(&this.waker) as PVOID,
+                    INFINITE,
+                    WT_EXECUTEONLYONCE,
+                )
+            } == 0
+            {
+                let error = Error::last_os("There was an error waiting for events");
+                this.unregister();
+                return Poll::Ready(Some(Err(error)));
+            }
+            this.waits.push(wait);
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for SubscriptionStream {
+    fn drop(&mut self) {
+        self.unregister();
+    }
+}
+
+/// The thread pool callback for the signal and the cancel event, with the stream's waker as context
+unsafe extern "system" fn wake(context: PVOID, _timed_out: BOOLEAN) {
+    let waker = &*(context as *const Mutex<Option<Waker>>);
+    // A panicking waker must not unwind into the thread pool
+    let _ = catch_unwind(AssertUnwindSafe(|| {
+        let waker = waker.lock().unwrap_or_else(|e| e.into_inner()).take();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{StartMode, Subscription};
+    use futures_core::Stream;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn cancel_wakes_pending_stream() {
+        // a filter no event matches, so the stream stays pending
+        let subscriber =
+            match Subscription::channel("Application", "*[System[EventID = 1 and EventID = 2]]")
+                .start(StartMode::FutureEvents)
+                .subscribe()
+            {
+                Ok(subscriber) => subscriber,
+                Err(err) => {
+                    println!("cancel_wakes_pending_stream(): {err}");
+                    return;
+                }
+            };
+        let cancel = subscriber.cancel_handle();
+        let mut stream = subscriber.into_stream();
+        let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+        let mut cx = Context::from_waker(&waker);
+        assert!(Pin::new(&mut stream).poll_next(&mut cx).is_pending());
+
+        cancel.cancel();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while counter.0.load(Ordering::SeqCst) == 0 {
+            assert!(
+                Instant::now() < deadline,
+                "cancelling did not wake the stream"
+            );
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(matches!(
+            Pin::new(&mut stream).poll_next(&mut cx),
+            Poll::Ready(None)
+        ));
+    }
+}
@@ -0,0 +1,245 @@
+use super::callback::CallbackSubscriber;
+use super::{evt_subscribe, Delivery, WinEventsSubscriber};
+use crate::api::{bookmark_handle, EvtHandleWrapper};
+use crate::bookmark::Bookmark;
+use crate::error::Error;
+use crate::event::Event;
+use crate::query_list::{query_path_kind, PathKind};
+use crate::session::EvtSession;
+use winapi::um::winevt::{
+    EvtSubscribeStartAfterBookmark, EvtSubscribeStartAtOldestRecord, EvtSubscribeStrict,
+    EvtSubscribeToFutureEvents,
+};
+
+/// Where a subscription starts reading
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StartMode {
+    /// Only events raised after subscribing
+    FutureEvents,
+    /// Every event still in the log, then new ones as they arrive
+    OldestRecord,
+    /// The events after the bookmark's position. An empty bookmark starts at the
+    /// oldest record.
+    AfterBookmark(Bookmark),
+}
+
+#[derive(Clone, Debug)]
+enum Source {
+    /// A `QueryList` or structured query XML, naming its own channels
+    Query(String),
+    /// A single channel and an XPath filter
+    Channel { path: String, xpath: String },
+}
+
+/// Builds a `WinEventsSubscriber`
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use win_event_log::prelude::*;
+///
+/// let events = Subscription::channel("Security", "*[System[(EventID = 4624)]]")
+///     .start(StartMode::FutureEvents)
+///     .subscribe()
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct Subscription {
+    source: Source,
+    start: StartMode,
+    strict: bool,
+    session: Option<EvtSession>,
+}
+
+impl<'a> Subscription {
+    /// Subscribes with a structured query, such as a `QueryList`. Its channels
+    /// come from the `Path` of each item.
+    pub fn query<T: Into<String>>(query: T) -> Subscription {
+        Subscription::new(Source::Query(query.into()))
+    }
+
+    /// Subscribes to `channel`, with events filtered by the XPath expression
+    /// `xpath`. Use `*` for every event.
+    pub fn channel<C: Into<String>, X: Into<String>>(channel: C, xpath: X) -> Subscription {
+        Subscription::new(Source::Channel {
+            path: channel.into(),
+            xpath: xpath.into(),
+        })
+    }
+
+    fn new(source: Source) -> Subscription {
+        Subscription {
+            source,
+            start: StartMode::OldestRecord,
+            strict: false,
+            session: None,
+        }
+    }
+
+    /// Where to start, by default at the oldest record
+    pub fn start(&'a mut self, start: StartMode) -> &'a mut Self {
+        self.start = start;
+        self
+    }
+
+    /// With `StartMode::AfterBookmark`, fails to subscribe if the bookmarked
+    /// event is no longer in the log instead of silently starting at the oldest
+    /// record, so missed events are noticed
+    pub fn strict(&'a mut self, strict: bool) -> &'a mut Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Subscribes on a remote machine instead of the local one
+    pub fn session(&'a mut self, session: &EvtSession) -> &'a mut Self {
+        self.session = Some(session.clone());
+        self
+    }
+
+    pub fn build(&self) -> Subscription {
+        self.clone()
+    }
+
+    /// Starts the subscription
+    pub fn subscribe(&self) -> Result<WinEventsSubscriber, Error> {
+        let flags = self.flags()?;
+        let (channel, query) = self.target();
+        WinEventsSubscriber::subscribe(
+            self.session.clone(),
+            channel,
+            query,
+            self.bookmark()?,
+            flags,
+        )
+    }
+
+    /// Starts the subscription in push mode, calling `callback` with each event
+    /// as it arrives instead of waiting to be asked
+    pub fn subscribe_with<F>(&self, callback: F) -> Result<CallbackSubscriber, Error>
+    where
+        F: FnMut(Result<Event, Error>) + Send + 'static,
+    {
+        let flags = self.flags()?;
+        let (channel, query) = self.target();
+        let subscribe = |context, bookmark: Option<&EvtHandleWrapper>| {
+            evt_subscribe(
+                self.session.as_ref(),
+                Delivery::Callback(context),
+                channel,
+                query,
+                bookmark,
+                flags,
+            )
+        };
+        CallbackSubscriber::new(
+            subscribe,
+            Box::new(callback),
+            self.bookmark()?,
+            self.session.clone(),
+        )
+    }
+
+    /// The channel, if any, and the query or XPath filter
+    fn target(&self) -> (Option<&str>, &str) {
+        match self.source {
+            Source::Query(ref query) => (None, query),
+            Source::Channel {
+                ref path,
+                ref xpath,
+            } => (Some(path), xpath),
+        }
+    }
+
+    fn bookmark(&self) -> Result<Option<EvtHandleWrapper>, Error> {
+        match self.start {
+            StartMode::AfterBookmark(ref bookmark) if !bookmark.is_empty() => {
+                Ok(Some(bookmark_handle(bookmark)?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// The `EvtSubscribe` flags, after checking the settings fit together
+    fn flags(&self) -> Result<u32, Error> {
+        match self.source {
+            Source::Query(ref query) => {
+                if let Some(PathKind::File) = query_path_kind(query)? {
+                    return Err(Error::Other("Log files cannot be subscribed to".to_owned()));
+                }
+            }
+            Source::Channel { ref path, .. } => {
+                if path.trim().is_empty() {
+                    return Err(Error::Other("Subscription channel is empty".to_owned()));
+                }
+                if PathKind::of(path.trim()) == PathKind::File {
+                    return Err(Error::Other("Log files cannot be subscribed to".to_owned()));
+                }
+            }
+        }
+        let flags = match self.start {
+            StartMode::FutureEvents => EvtSubscribeToFutureEvents,
+            StartMode::OldestRecord => EvtSubscribeStartAtOldestRecord,
+            StartMode::AfterBookmark(ref bookmark) if bookmark.is_empty() => {
+                if self.strict {
+                    return Err(Error::Other(
+                        "A strict subscription needs a bookmark with a position".to_owned(),
+                    ));
+                }
+                EvtSubscribeStartAtOldestRecord
+            }
+            StartMode::AfterBookmark(_) => EvtSubscribeStartAfterBookmark,
+        };
+        match (self.strict, &self.start) {
+            (true, StartMode::AfterBookmark(_)) => Ok(flags | EvtSubscribeStrict),
+            (true, _) => Err(Error::Other(
+                "Only subscriptions after a bookmark can be strict".to_owned(),
+            )),
+            (false, _) => Ok(flags),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StartMode, Subscription};
+    use crate::bookmark::Bookmark;
+    use winapi::um::winevt::{
+        EvtSubscribeStartAfterBookmark, EvtSubscribeStartAtOldestRecord, EvtSubscribeStrict,
+        EvtSubscribeToFutureEvents,
+    };
+
+    #[test]
+    fn subscription_flags() {
+        let mut bookmark = Bookmark::new();
+        bookmark.set("Security", 42);
+        assert_eq!(
+            Subscription::query("*").flags(),
+            Ok(EvtSubscribeStartAtOldestRecord)
+        );
+        assert_eq!(
+            Subscription::channel("Security", "*")
+                .start(StartMode::FutureEvents)
+                .flags(),
+            Ok(EvtSubscribeToFutureEvents)
+        );
+        assert_eq!(
+            Subscription::channel("Security", "*")
+                .start(StartMode::AfterBookmark(bookmark))
+                .strict(true)
+                .flags(),
+            Ok(EvtSubscribeStartAfterBookmark | EvtSubscribeStrict)
+        );
+        assert!(Subscription::channel("Security", "*")
+            .start(StartMode::AfterBookmark(Bookmark::new()))
+            .strict(true)
+            .flags()
+            .is_err());
+        assert!(Subscription::channel("Security", "*")
+            .strict(true)
+            .flags()
+            .is_err());
+        assert!(Subscription::channel("file://C:\\a.evtx", "*")
+            .flags()
+            .is_err());
+    }
+}
@@ -0,0 +1,215 @@
+//! Waiting for a subscription's signal, separated from the Windows calls so the
+//! retry logic can be tested
+
+use super::HandleWrapper;
+use crate::error::Error;
+use crate::event::Event;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use winapi::shared::minwindef::DWORD;
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::synchapi::{ResetEvent, SetEvent, WaitForMultipleObjects};
+use winapi::um::winbase::{INFINITE, WAIT_OBJECT_0};
+use winapi::um::winnt::HANDLE;
+
+const WAIT_TIMEOUT: DWORD = 258;
+
+/// What a timed read produced
+#[derive(Debug, PartialEq)]
+pub enum Received<T = Event> {
+    Event(T),
+    /// No event arrived in time
+    TimedOut,
+    /// The subscriber's `CancelHandle` was used
+    Cancelled,
+}
+
+/// Wakes a reader blocked in `WinEventsSubscriber::next_timeout`, from any thread
+///
+/// Cancelling is permanent: every later `next_timeout` on the subscriber returns
+/// `Received::Cancelled` straight away, although `try_next` still drains events
+/// that have already arrived.
+#[derive(Clone)]
+pub struct CancelHandle(pub(crate) Arc<HandleWrapper>);
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        unsafe {
+            SetEvent((self.0).0);
+        }
+    }
+}
+
+/// How a wait on the signal ended
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Wait {
+    Signalled,
+    TimedOut,
+    Cancelled,
+    Failed(u32),
+}
+
+/// The events a subscription sets when it has new events, or is cancelled
+pub(crate) trait Signal {
+    /// Clears the new events signal
+    fn reset(&self);
+    fn wait(&self, timeout: Duration) -> Wait;
+}
+
+/// The cancel event and one or more signals, borrowed from subscribers for the
+/// wait
+pub(crate) struct WinSignal {
+    pub(crate) cancel: HANDLE,
+    pub(crate) signals: Vec<HANDLE>,
+}
+
+impl Signal for WinSignal {
+    fn reset(&self) {
+        for signal in &self.signals {
+            unsafe {
+                ResetEvent(*signal);
+            }
+        }
+    }
+
+    fn wait(&self, timeout: Duration) -> Wait {
+        // Rounded up, so short timeouts still wait, and kept below INFINITE
+        let millis = timeout
+            .as_nanos()
+            .div_ceil(1_000_000)
+            .min(u128::from(INFINITE - 1)) as u32;
+        // The cancel event comes first, so it wins when others are set too
+        let handles = Some(self.cancel)
+            .into_iter()
+            .chain(self.signals.iter().cloned())
+            .collect::<Vec<HANDLE>>();
+        match unsafe { WaitForMultipleObjects(handles.len() as DWORD, handles.as_ptr(), 0, millis) }
+        {
+            WAIT_OBJECT_0 => Wait::Cancelled,
+            x if x < WAIT_OBJECT_0 + handles.len() as DWORD => Wait::Signalled,
+            WAIT_TIMEOUT => Wait::TimedOut,
+            _ => Wait::Failed(unsafe { GetLastError() }),
+        }
+    }
+}
+
+/// Reads the next event with `fetch`, waiting on `signal` for up to `timeout`
+/// whenever there are none. Errors from `fetch` are returned straight away.
+///
+/// The signal stays set until it is reset, so it is only reset once `fetch` has
+/// run dry, and `fetch` is tried once more afterwards in case an event arrived in
+/// between.
+pub(crate) fn next_event<S: Signal, T>(
+    signal: &S,
+    timeout: Duration,
+    mut fetch: impl FnMut() -> Result<Option<T>, Error>,
+) -> Result<Received<T>, Error> {
+    let deadline = Instant::now().checked_add(timeout);
+    loop {
+        if let Some(event) = fetch()? {
+            return Ok(Received::Event(event));
+        }
+        signal.reset();
+        if let Some(event) = fetch()? {
+            return Ok(Received::Event(event));
+        }
+        let remaining = match deadline {
+            Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+            None => timeout,
+        };
+        match signal.wait(remaining) {
+            Wait::Signalled => continue,
+            Wait::TimedOut => return Ok(Received::TimedOut),
+            Wait::Cancelled => return Ok(Received::Cancelled),
+            Wait::Failed(code) => {
+                return Err(Error::Windows {
+                    code,
+                    context: "There was an error waiting for events".to_owned(),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{next_event, Received, Signal, Wait};
+    use crate::event::Event;
+    use std::cell::{Cell, RefCell};
+    use std::collections::VecDeque;
+    use std::time::Duration;
+
+    /// Plays back a script of wait results, and of events for `fetch`
+    struct MockSignal {
+        waits: RefCell<VecDeque<Wait>>,
+        resets: Cell<usize>,
+    }
+
+    impl Signal for MockSignal {
+        fn reset(&self) {
+            self.resets.set(self.resets.get() + 1);
+        }
+
+        fn wait(&self, _: Duration) -> Wait {
+            self.waits
+                .borrow_mut()
+                .pop_front()
+                .unwrap_or(Wait::TimedOut)
+        }
+    }
+
+    fn read(waits: Vec<Wait>, fetches: Vec<Option<&str>>) -> (Received, usize, usize) {
+        let signal = MockSignal {
+            waits: RefCell::new(waits.into()),
+            resets: Cell::new(0),
+        };
+        let mut fetches: VecDeque<_> = fetches.into();
+        let received = next_event(&signal, Duration::from_secs(1), || {
+            Ok(fetches
+                .pop_front()
+                .flatten()
+                .map(|xml| Event(xml.to_owned())))
+        })
+        .unwrap();
+        let waits_left = signal.waits.borrow().len();
+        (received, signal.resets.get(), waits_left)
+    }
+
+    #[test]
+    fn wait_and_retry() {
+        let event = Received::Event(Event("<Event/>".to_owned()));
+        // Ready straight away, without touching the signal
+        assert_eq!(read(vec![], vec![Some("<Event/>")]), (event, 0, 0));
+        // Arrives between running dry and the reset
+        let event = Received::Event(Event("<Event/>".to_owned()));
+        assert_eq!(read(vec![], vec![None, Some("<Event/>")]), (event, 1, 0));
+        // Arrives after a wait
+        let event = Received::Event(Event("<Event/>".to_owned()));
+        assert_eq!(
+            read(
+                vec![Wait::Signalled, Wait::Cancelled],
+                vec![None, None, Some("<Event/>")]
+            ),
+            (event, 1, 1)
+        );
+        // A signal with nothing behind it waits again
+        assert_eq!(
+            read(vec![Wait::Signalled, Wait::TimedOut], vec![]),
+            (Received::TimedOut, 2, 0)
+        );
+        assert_eq!(
+            read(vec![Wait::Cancelled], vec![]),
+            (Received::Cancelled, 1, 0)
+        );
+        let signal = MockSignal {
+            waits: RefCell::new(vec![Wait::Failed(6)].into()),
+            resets: Cell::new(0),
+        };
+        assert_eq!(
+            next_event(&signal, Duration::from_secs(1), || Ok(None::<Event>))
+                .unwrap_err()
+                .code(),
+            Some(6)
+        );
+    }
+}
@@ -0,0 +1,527 @@
+//! `EVT_VARIANT` values, as returned by `EvtRender` with a render context and by
+//! the channel and log property APIs
+//!
+//! Decoding works on a plain byte buffer and the address it was filled in at, so
+//! it does not depend on the Windows API and can be tested anywhere.
+
+use crate::evtx::cursor::Cursor;
+use crate::evtx::value::{
+    format_filetime, format_guid, format_sid, format_systemtime, ARRAY_FLAG, BINARY_TYPE,
+    BOOL_TYPE, FILETIME_TYPE, GUID_TYPE, HEX_INT32_TYPE, HEX_INT64_TYPE, INT16_TYPE, INT32_TYPE,
+    INT64_TYPE, INT8_TYPE, NULL_TYPE, REAL32_TYPE, REAL64_TYPE, SID_TYPE, SIZE_T_TYPE, STRING_TYPE,
+    SYSTEMTIME_TYPE, UINT16_TYPE, UINT32_TYPE, UINT64_TYPE, UINT8_TYPE, WSTRING_TYPE,
+};
+use std::fmt;
+use std::mem::size_of;
+
+/// Size of an `EVT_VARIANT`: an 8 byte union, then `Count` and `Type`
+pub const EVT_VARIANT_SIZE: usize = 16;
+const EVT_HANDLE_TYPE: u8 = 0x20;
+const EVT_XML_TYPE: u8 = 0x23;
+const POINTER_SIZE: usize = size_of::<usize>();
+
+/// A decoded `EVT_VARIANT`
+#[derive(Clone, Debug, PartialEq)]
+pub enum Variant {
+    Null,
+    String(String),
+    AnsiString(String),
+    SByte(i8),
+    Byte(u8),
+    Int16(i16),
+    UInt16(u16),
+    Int32(i32),
+    UInt32(u32),
+    Int64(i64),
+    UInt64(u64),
+    Single(f32),
+    Double(f64),
+    Boolean(bool),
+    Binary(Vec<u8>),
+    Guid([u8; 16]),
+    SizeT(u64),
+    /// 100ns intervals since 1601-01-01 UTC
+    FileTime(u64),
+    /// year, month, day of week, day, hour, minute, second, milliseconds
+    SysTime([u16; 8]),
+    /// Binary SID
+    Sid(Vec<u8>),
+    HexInt32(u32),
+    HexInt64(u64),
+    /// An `EVT_HANDLE` owned by the structure the variant came from
+    EvtHandle(u64),
+    /// Rendered XML text
+    EvtXml(String),
+    Array(Vec<Variant>),
+}
+
+/// Indexes of the values rendered with `EvtRenderContextSystem`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SystemProperty {
+    ProviderName,
+    ProviderGuid,
+    EventID,
+    Qualifiers,
+    Level,
+    Task,
+    Opcode,
+    Keywords,
+    TimeCreated,
+    EventRecordId,
+    ActivityID,
+    RelatedActivityID,
+    ProcessID,
+    ThreadID,
+    Channel,
+    Computer,
+    UserID,
+    Version,
+}
+
+impl SystemProperty {
+    /// Position of the property in the rendered values
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl Variant {
+    /// Decodes `count` consecutive `EVT_VARIANT`s at the start of `buffer`.
+    /// `address` is where the buffer was when the API filled it in, which is what
+    /// its pointers are relative to. Pointers outside of the buffer are an error.
+    pub fn decode_all(buffer: &[u8], address: u64, count: usize) -> Result<Vec<Variant>, String> {
+        let buffer = Buffer {
+            data: buffer,
+            address,
+        };
+        (0..count)
+            .map(|index| buffer.variant(index * EVT_VARIANT_SIZE))
+            .collect()
+    }
+
+    /// Decodes the `EVT_VARIANT` at the start of `buffer`, as `decode_all` does
+    pub fn decode(buffer: &[u8], address: u64) -> Result<Variant, String> {
+        Buffer {
+            data: buffer,
+            address,
+        }
+        .variant(0)
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == Variant::Null
+    }
+
+    /// The value as a string, for the string types
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Variant::String(s) | Variant::AnsiString(s) | Variant::EvtXml(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The value as an unsigned integer, for the integer types that fit
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Variant::Byte(v) => Some(v as u64),
+            Variant::UInt16(v) => Some(v as u64),
+            Variant::UInt32(v) | Variant::HexInt32(v) => Some(v as u64),
+            Variant::UInt64(v) | Variant::HexInt64(v) | Variant::SizeT(v) => Some(v),
+            Variant::FileTime(v) => Some(v),
+            Variant::SByte(v) if v >= 0 => Some(v as u64),
+            Variant::Int16(v) if v >= 0 => Some(v as u64),
+            Variant::Int32(v) if v >= 0 => Some(v as u64),
+            Variant::Int64(v) if v >= 0 => Some(v as u64),
+            _ => None,
+        }
+    }
+}
+
+/// A buffer of variants, and the address it was filled in at
+struct Buffer<'a> {
+    data: &'a [u8],
+    address: u64,
+}
+
+impl<'a> Buffer<'a> {
+    fn variant(&self, offset: usize) -> Result<Variant, String> {
+        let mut cursor = Cursor::new(self.data, offset + 8);
+        let count = cursor.u32()? as usize;
+        let variant_type = cursor.u32()?;
+        let value_type = (variant_type & 0x7f) as u8;
+        if variant_type & ARRAY_FLAG as u32 != 0 {
+            return self.array(offset, value_type, count);
+        }
+        let mut value = Cursor::new(self.data, offset);
+        Ok(match value_type {
+            NULL_TYPE => Variant::Null,
+            INT8_TYPE => Variant::SByte(value.u8()? as i8),
+            UINT8_TYPE => Variant::Byte(value.u8()?),
+            INT16_TYPE => Variant::Int16(value.u16()? as i16),
+            UINT16_TYPE => Variant::UInt16(value.u16()?),
+            INT32_TYPE => Variant::Int32(value.u32()? as i32),
+            UINT32_TYPE => Variant::UInt32(value.u32()?),
+            INT64_TYPE => Variant::Int64(value.u64()? as i64),
+            UINT64_TYPE => Variant::UInt64(value.u64()?),
+            REAL32_TYPE => Variant::Single(f32::from_bits(value.u32()?)),
+            REAL64_TYPE => Variant::Double(f64::from_bits(value.u64()?)),
+            BOOL_TYPE => Variant::Boolean(value.u32()? != 0),
+            SIZE_T_TYPE => Variant::SizeT(self.pointer_value(offset)?),
+            FILETIME_TYPE => Variant::FileTime(value.u64()?),
+            HEX_INT32_TYPE => Variant::HexInt32(value.u32()?),
+            HEX_INT64_TYPE => Variant::HexInt64(value.u64()?),
+            EVT_HANDLE_TYPE => Variant::EvtHandle(self.pointer_value(offset)?),
+            WSTRING_TYPE | EVT_XML_TYPE | STRING_TYPE | BINARY_TYPE | GUID_TYPE
+            | SYSTEMTIME_TYPE | SID_TYPE => match self.pointer(offset)? {
+                None => Variant::Null,
+                Some(target) => self.pointed(value_type, target, count)?,
+            },
+            other => return Err(format!("Unknown EVT_VARIANT type {other:#x}")),
+        })
+    }
+
+    /// A value stored behind the variant's pointer
+    fn pointed(&self, value_type: u8, target: usize, count: usize) -> Result<Variant, String> {
+        let mut cursor = Cursor::new(self.data, target);
+        Ok(match value_type {
+            WSTRING_TYPE => Variant::String(cursor.utf16z()?),
+            EVT_XML_TYPE => Variant::EvtXml(cursor.utf16z()?),
+            STRING_TYPE => Variant::AnsiString(self.ansi(target)?),
+            BINARY_TYPE => Variant::Binary(cursor.bytes(count)?.to_vec()),
+            GUID_TYPE => Variant::Guid(guid(cursor.bytes(16)?)),
+            SYSTEMTIME_TYPE => Variant::SysTime(systemtime(&mut cursor)?),
+            SID_TYPE => Variant::Sid(self.sid(target)?),
+            other => return Err(format!("EVT_VARIANT type {other:#x} is not a pointer")),
+        })
+    }
+
+    /// An array variant. Strings and SIDs are arrays of pointers; everything else
+    /// is stored inline, one element after another.
+    fn array(&self, offset: usize, value_type: u8, count: usize) -> Result<Variant, String> {
+        let target = match self.pointer(offset)? {
+            Some(target) => target,
+            None => return Ok(Variant::Array(Vec::new())),
+        };
+        let size = match value_type {
+            INT8_TYPE | UINT8_TYPE => 1,
+            INT16_TYPE | UINT16_TYPE => 2,
+            INT32_TYPE | UINT32_TYPE | REAL32_TYPE | BOOL_TYPE | HEX_INT32_TYPE => 4,
+            INT64_TYPE | UINT64_TYPE | REAL64_TYPE | FILETIME_TYPE | HEX_INT64_TYPE => 8,
+            GUID_TYPE | SYSTEMTIME_TYPE => 16,
+            WSTRING_TYPE | STRING_TYPE | SID_TYPE | SIZE_T_TYPE | EVT_HANDLE_TYPE => POINTER_SIZE,
+            other => return Err(format!("EVT_VARIANT type {other:#x} cannot be an array")),
+        };
+        let end = count
+            .checked_mul(size)
+            .and_then(|len| len.checked_add(target))
+            .filter(|end| *end <= self.data.len());
+        if end.is_none() {
+            return Err(format!(
+                "EVT_VARIANT array of {count} items overruns the buffer"
+            ));
+        }
+        let items = (0..count)
+            .map(|index| {
+                let item = target + index * size;
+                let mut value = Cursor::new(self.data, item);
+                Ok(match value_type {
+                    INT8_TYPE => Variant::SByte(value.u8()? as i8),
+                    UINT8_TYPE => Variant::Byte(value.u8()?),
+                    INT16_TYPE => Variant::Int16(value.u16()? as i16),
+                    UINT16_TYPE => Variant::UInt16(value.u16()?),
+                    INT32_TYPE => Variant::Int32(value.u32()? as i32),
+                    UINT32_TYPE => Variant::UInt32(value.u32()?),
+                    INT64_TYPE => Variant::Int64(value.u64()? as i64),
+                    UINT64_TYPE => Variant::UInt64(value.u64()?),
+                    REAL32_TYPE => Variant::Single(f32::from_bits(value.u32()?)),
+                    REAL64_TYPE => Variant::Double(f64::from_bits(value.u64()?)),
+                    BOOL_TYPE => Variant::Boolean(value.u32()? != 0),
+                    FILETIME_TYPE => Variant::FileTime(value.u64()?),
+                    HEX_INT32_TYPE => Variant::HexInt32(value.u32()?),
+                    HEX_INT64_TYPE => Variant::HexInt64(value.u64()?),
+                    GUID_TYPE => Variant::Guid(guid(value.bytes(16)?)),
+                    SYSTEMTIME_TYPE => Variant::SysTime(systemtime(&mut value)?),
+                    SIZE_T_TYPE => Variant::SizeT(self.pointer_value(item)?),
+                    EVT_HANDLE_TYPE => Variant::EvtHandle(self.pointer_value(item)?),
+                    _ => match self.pointer(item)? {
+                        Some(target) => self.pointed(value_type, target, 0)?,
+                        None => Variant::Null,
+                    },
+                })
+            })
+            .collect::<Result<Vec<Variant>, String>>()?;
+        Ok(Variant::Array(items))
+    }
+
+    /// A pointer-sized integer at `offset`
+    fn pointer_value(&self, offset: usize) -> Result<u64, String> {
+        let mut cursor = Cursor::new(self.data, offset);
+        match POINTER_SIZE {
+            4 => cursor.u32().map(|value| value as u64),
+            _ => cursor.u64(),
+        }
+    }
+
+    /// The offset in the buffer of the pointer at `offset`, or `None` if it is null
+    fn pointer(&self, offset: usize) -> Result<Option<usize>, String> {
+        let pointer = self.pointer_value(offset)?;
+        if pointer == 0 {
+            return Ok(None);
+        }
+        match pointer.checked_sub(self.address) {
+            Some(target) if target < self.data.len() as u64 => Ok(Some(target as usize)),
+            _ => Err(format!(
+                "EVT_VARIANT pointer {pointer:#x} is outside of the buffer"
+            )),
+        }
+    }
+
+    /// A NUL-terminated string in the current code page, decoded as UTF-8
+    fn ansi(&self, target: usize) -> Result<String, String> {
+        let rest = &self.data[target..];
+        match rest.iter().position(|b| *b == 0) {
+            Some(len) => Ok(String::from_utf8_lossy(&rest[..len]).into_owned()),
+            None => Err("Unterminated string in EVT_VARIANT".to_owned()),
+        }
+    }
+
+    /// A binary SID, whose length is given by its sub-authority count
+    fn sid(&self, target: usize) -> Result<Vec<u8>, String> {
+        let mut cursor = Cursor::new(self.data, target + 1);
+        let count = cursor.u8()? as usize;
+        Ok(Cursor::new(self.data, target)
+            .bytes(8 + count * 4)?
+            .to_vec())
+    }
+}
+
+fn guid(bytes: &[u8]) -> [u8; 16] {
+    let mut guid = [0u8; 16];
+    guid.copy_from_slice(bytes);
+    guid
+}
+
+fn systemtime(cursor: &mut Cursor) -> Result<[u16; 8], String> {
+    let mut time = [0u16; 8];
+    for part in time.iter_mut() {
+        *part = cursor.u16()?;
+    }
+    Ok(time)
+}
+
+/// Values print as `EvtRender` prints them in XML, with arrays comma separated
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Variant::Null => Ok(()),
+            Variant::String(s) | Variant::AnsiString(s) | Variant::EvtXml(s) => write!(f, "{s}"),
+            Variant::SByte(v) => write!(f, "{v}"),
+            Variant::Byte(v) => write!(f, "{v}"),
+            Variant::Int16(v) => write!(f, "{v}"),
+            Variant::UInt16(v) => write!(f, "{v}"),
+            Variant::Int32(v) => write!(f, "{v}"),
+            Variant::UInt32(v) => write!(f, "{v}"),
+            Variant::Int64(v) => write!(f, "{v}"),
+            Variant::UInt64(v) => write!(f, "{v}"),
+            Variant::Single(v) => write!(f, "{v}"),
+            Variant::Double(v) => write!(f, "{v}"),
+            Variant::Boolean(v) => write!(f, "{v}"),
+            Variant::Binary(b) => b.iter().try_for_each(|b| write!(f, "{b:02X}")),
+            Variant::Guid(g) => write!(f, "{}", format_guid(g)),
+            Variant::SizeT(v) | Variant::EvtHandle(v) => write!(f, "{v:#018x}"),
+            Variant::FileTime(v) => write!(f, "{}", format_filetime(*v)),
+            Variant::SysTime(t) => write!(f, "{}", format_systemtime(t)),
+            Variant::Sid(b) => write!(f, "{}", format_sid(b).unwrap_or_default()),
+            Variant::HexInt32(v) => write!(f, "{v:#x}"),
+            Variant::HexInt64(v) => write!(f, "{v:#x}"),
+            Variant::Array(items) => {
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SystemProperty, Variant, EVT_VARIANT_SIZE};
+
+    const ADDRESS: u64 = 0x7ff0_1234_0000;
+
+    /// Lays out variants followed by the data they point to, as `EvtRender` does
+    struct Builder {
+        variants: Vec<u8>,
+        data: Vec<u8>,
+        count: usize,
+    }
+
+    impl Builder {
+        fn new(count: usize) -> Builder {
+            Builder {
+                variants: Vec::new(),
+                data: Vec::new(),
+                count,
+            }
+        }
+
+        /// The address `bytes` will have once appended to the data
+        fn add_data(&mut self, bytes: &[u8]) -> u64 {
+            let address = ADDRESS + (self.count * EVT_VARIANT_SIZE + self.data.len()) as u64;
+            self.data.extend_from_slice(bytes);
+            address
+        }
+
+        fn variant(&mut self, value: u64, count: u32, variant_type: u32) -> &mut Self {
+            self.variants.extend_from_slice(&value.to_le_bytes());
+            self.variants.extend_from_slice(&count.to_le_bytes());
+            self.variants.extend_from_slice(&variant_type.to_le_bytes());
+            self
+        }
+
+        fn build(&self) -> Vec<u8> {
+            assert_eq!(self.variants.len(), self.count * EVT_VARIANT_SIZE);
+            [&self.variants[..], &self.data[..]].concat()
+        }
+    }
+
+    fn utf16z(s: &str) -> Vec<u8> {
+        s.encode_utf16()
+            .chain(Some(0))
+            .flat_map(|unit| unit.to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn scalars() {
+        let mut builder = Builder::new(9);
+        builder
+            .variant(0, 0, 0)
+            .variant(0xff, 0, 3)
+            .variant(0xfffe, 0, 5)
+            .variant(4624, 0, 6)
+            .variant(u64::MAX, 0, 10)
+            .variant(1.5f64.to_bits(), 0, 12)
+            .variant(1, 0, 13)
+            .variant(0x01d4_f550_f2e4_1000, 0, 17)
+            .variant(0x8020_0000_0000_0000, 0, 21);
+        let values = Variant::decode_all(&builder.build(), ADDRESS, 9).unwrap();
+        assert_eq!(
+            values,
+            vec![
+                Variant::Null,
+                Variant::SByte(-1),
+                Variant::Int16(-2),
+                Variant::UInt16(4624),
+                Variant::UInt64(u64::MAX),
+                Variant::Double(1.5),
+                Variant::Boolean(true),
+                Variant::FileTime(0x01d4_f550_f2e4_1000),
+                Variant::HexInt64(0x8020_0000_0000_0000),
+            ]
+        );
+        assert_eq!(values[3].as_u64(), Some(4624));
+        assert_eq!(values[1].as_u64(), None);
+        assert_eq!(values[8].to_string(), "0x8020000000000000");
+    }
+
+    #[test]
+    fn pointers() {
+        let mut builder = Builder::new(7);
+        let name = builder.add_data(&utf16z("Microsoft-Windows-Security-Auditing"));
+        let guid = builder.add_data(&[
+            0x25, 0x96, 0x84, 0x54, 0x78, 0x54, 0x94, 0x49, 0xa5, 0xba, 0x3e, 0x3b, 0x03, 0x28,
+            0xc3, 0x0d,
+        ]);
+        let sid = builder.add_data(&[1, 1, 0, 0, 0, 0, 0, 5, 18, 0, 0, 0]);
+        let binary = builder.add_data(&[0xde, 0xad]);
+        let ansi = builder.add_data(b"legacy\0");
+        let time = builder.add_data(
+            &[2019u16, 4, 3, 17, 18, 40, 0, 5]
+                .iter()
+                .flat_map(|part| part.to_le_bytes())
+                .collect::<Vec<u8>>(),
+        );
+        builder
+            .variant(name, 0, 1)
+            .variant(guid, 0, 15)
+            .variant(sid, 0, 19)
+            .variant(binary, 2, 14)
+            .variant(ansi, 0, 2)
+            .variant(time, 0, 18)
+            .variant(0, 0, 1);
+        let values = Variant::decode_all(&builder.build(), ADDRESS, 7).unwrap();
+        let text = values
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(
+            text,
+            vec![
+                "Microsoft-Windows-Security-Auditing",
+                "{54849625-5478-4994-A5BA-3E3B0328C30D}",
+                "S-1-5-18",
+                "DEAD",
+                "legacy",
+                "2019-04-17T18:40:00.005Z",
+                "",
+            ]
+        );
+        assert_eq!(
+            values[0].as_str(),
+            Some("Microsoft-Windows-Security-Auditing")
+        );
+        assert!(values[6].is_null());
+    }
+
+    #[test]
+    fn arrays() {
+        let mut builder = Builder::new(3);
+        let first = builder.add_data(&utf16z("a"));
+        let second = builder.add_data(&utf16z("bc"));
+        let strings = builder.add_data(&[first.to_le_bytes(), second.to_le_bytes()].concat());
+        let numbers = builder.add_data(
+            &[1u32, 2, 3]
+                .iter()
+                .flat_map(|n| n.to_le_bytes())
+                .collect::<Vec<u8>>(),
+        );
+        builder
+            .variant(strings, 2, 0x81)
+            .variant(numbers, 3, 0x88)
+            .variant(0, 0, 0x88);
+        let values = Variant::decode_all(&builder.build(), ADDRESS, 3).unwrap();
+        assert_eq!(values[0].to_string(), "a,bc");
+        assert_eq!(
+            values[1],
+            Variant::Array(vec![
+                Variant::UInt32(1),
+                Variant::UInt32(2),
+                Variant::UInt32(3)
+            ])
+        );
+        assert_eq!(values[2], Variant::Array(Vec::new()));
+    }
+
+    #[test]
+    fn malformed() {
+        let mut builder = Builder::new(1);
+        builder.variant(ADDRESS + 0x1000, 0, 1);
+        assert!(Variant::decode(&builder.build(), ADDRESS).is_err());
+
+        let mut builder = Builder::new(1);
+        let numbers = builder.add_data(&[0u8; 8]);
+        builder.variant(numbers, 3, 0x88);
+        assert!(Variant::decode(&builder.build(), ADDRESS).is_err());
+
+        let mut builder = Builder::new(1);
+        builder.variant(0, 0, 0x1f);
+        assert!(Variant::decode(&builder.build(), ADDRESS).is_err());
+        assert!(Variant::decode_all(&[0u8; 8], ADDRESS, 1).is_err());
+        assert_eq!(SystemProperty::Computer.index(), 15);
+    }
+}